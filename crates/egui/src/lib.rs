@@ -25,13 +25,15 @@ use rhyolite::future::{gpu_future, GPUFutureBlock};
 use rhyolite::ImageWithView;
 use rhyolite::{
     ash::vk,
-    buffer::{BufferLike, BufferVec, StagingBelt},
+    buffer::{BufferLike, BufferVec, DeviceVec, StagingBelt},
     commands::record_commands,
     ecs::IntoRenderSystem,
     future::{GPUBorrowedResource, GPUOwnedResource},
     pipeline::{
-        CachedPipeline, DescriptorSetLayout, GraphicsPipeline, GraphicsPipelineBuildInfo,
-        PipelineCache, PipelineLayout,
+        BlendState, CachedPipeline, ColorBlendAttachment, ColorBlendState, DescriptorAllocator,
+        DescriptorSetWriter, GraphicsPipeline, GraphicsPipelineBuildInfo, GraphicsPipelineState,
+        LayoutCache, PipelineCache, PipelineLayout, RenderingFormats, VertexAttribute,
+        VertexBinding, VertexInputState,
     },
     shader::ShaderModule,
     shader::SpecializedShader,
@@ -91,7 +93,8 @@ impl<Filter: QueryFilter + Send + Sync + 'static> Plugin for EguiPlugin<Filter>
             &mut x.dynamic_rendering
         })
         .unwrap();
-        app.add_device_extension::<push_descriptor::Meta>().unwrap();
+        // Without push descriptors, descriptor sets are allocated from pools.
+        app.add_device_extension::<push_descriptor::Meta>().ok();
     }
     fn finish(&self, app: &mut App) {
         app.init_resource::<EguiDeviceBuffer<Filter>>();
@@ -124,31 +127,38 @@ impl<Filter: QueryFilter + Send + Sync + 'static> Plugin for EguiPlugin<Filter>
 pub struct EguiPipelines {
     pipeline: CachedPipeline<GraphicsPipeline>,
     layout: Arc<PipelineLayout>,
+    use_push_descriptors: bool,
+    descriptor_allocator: DescriptorAllocator,
 }
 fn initialize_pipelines(
     mut commands: Commands,
     device: Res<Device>,
     pipeline_cache: Res<PipelineCache>,
+    layout_cache: Res<LayoutCache>,
     assets: Res<AssetServer>,
 ) {
-    let desc0 = DescriptorSetLayout::new(
-        device.clone(),
-        &playout_macro::layout!("../assets/draw.playout", 0),
-        vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR,
-    )
-    .unwrap();
-    let layout = PipelineLayout::new(
-        device.clone(),
-        vec![Arc::new(desc0)],
-        &[vk::PushConstantRange {
-            offset: 0,
-            size: std::mem::size_of::<[f32; 2]>() as u32,
-            stage_flags: vk::ShaderStageFlags::VERTEX,
-        }], // Ideally this can be specified automatically
-        vk::PipelineLayoutCreateFlags::empty(),
-    )
-    .unwrap();
-    let layout = Arc::new(layout);
+    let use_push_descriptors = device.get_extension::<push_descriptor::Meta>().is_ok();
+    let desc0 = layout_cache
+        .descriptor_set_layout(
+            &playout_macro::layout!("../assets/draw.playout", 0),
+            if use_push_descriptors {
+                vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR
+            } else {
+                vk::DescriptorSetLayoutCreateFlags::empty()
+            },
+        )
+        .unwrap();
+    let layout = layout_cache
+        .pipeline_layout(
+            vec![desc0],
+            &[vk::PushConstantRange {
+                offset: 0,
+                size: std::mem::size_of::<[f32; 2]>() as u32,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+            }], // Ideally this can be specified automatically
+            vk::PipelineLayoutCreateFlags::empty(),
+        )
+        .unwrap();
     let pipeline_create_info = GraphicsPipelineBuildInfo {
         device: device.clone(),
         layout: layout.clone(),
@@ -166,104 +176,55 @@ fn initialize_pipelines(
                 ..Default::default()
             },
         ],
-        builder: move |builder: rhyolite::pipeline::Builder| {
-            builder.build(
-                vk::GraphicsPipelineCreateInfo::default()
-                    .input_assembly_state(&vk::PipelineInputAssemblyStateCreateInfo {
-                        topology: vk::PrimitiveTopology::TRIANGLE_LIST,
-                        primitive_restart_enable: vk::FALSE,
-                        ..Default::default()
-                    })
-                    .vertex_input_state(
-                        &vk::PipelineVertexInputStateCreateInfo::default()
-                            .vertex_binding_descriptions(&[vk::VertexInputBindingDescription {
-                                binding: 0,
-                                stride: std::mem::size_of::<egui::epaint::Vertex>() as u32,
-                                input_rate: vk::VertexInputRate::VERTEX,
-                            }])
-                            .vertex_attribute_descriptions(&[
-                                vk::VertexInputAttributeDescription {
-                                    binding: 0,
-                                    location: 0,
-                                    format: vk::Format::R32G32_SFLOAT,
-                                    offset: 0,
-                                }, // pos
-                                vk::VertexInputAttributeDescription {
-                                    binding: 0,
-                                    location: 1,
-                                    format: vk::Format::R32G32_SFLOAT,
-                                    offset: std::mem::size_of::<[f32; 2]>() as u32,
-                                }, // uv
-                                vk::VertexInputAttributeDescription {
-                                    binding: 0,
-                                    location: 2,
-                                    format: vk::Format::R8G8B8A8_UNORM,
-                                    offset: std::mem::size_of::<[f32; 4]>() as u32,
-                                }, // color
-                            ]),
-                    )
-                    .viewport_state(
-                        &vk::PipelineViewportStateCreateInfo::default()
-                            .scissors(&[vk::Rect2D {
-                                offset: vk::Offset2D { x: 0, y: 0 },
-                                extent: vk::Extent2D {
-                                    width: 2560,
-                                    height: 1440,
-                                },
-                            }])
-                            .viewports(&[vk::Viewport {
-                                x: 0.0,
-                                y: 0.0,
-                                width: 2560.0,
-                                height: 1440.0,
-                                min_depth: 0.0,
-                                max_depth: 1.0,
-                            }]),
-                    )
-                    .rasterization_state(&vk::PipelineRasterizationStateCreateInfo {
-                        polygon_mode: vk::PolygonMode::FILL,
-                        cull_mode: vk::CullModeFlags::NONE,
-                        line_width: 1.0,
-                        ..Default::default()
-                    })
-                    .multisample_state(&vk::PipelineMultisampleStateCreateInfo {
-                        rasterization_samples: vk::SampleCountFlags::TYPE_1,
-                        sample_shading_enable: vk::FALSE,
-                        ..Default::default()
-                    })
-                    .color_blend_state(
-                        &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
-                            vk::PipelineColorBlendAttachmentState {
-                                color_write_mask: vk::ColorComponentFlags::R
-                                    | vk::ColorComponentFlags::G
-                                    | vk::ColorComponentFlags::B
-                                    | vk::ColorComponentFlags::A,
-                                src_color_blend_factor: vk::BlendFactor::ONE,
-                                dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
-                                color_blend_op: vk::BlendOp::ADD,
-                                src_alpha_blend_factor: vk::BlendFactor::ONE,
-                                dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
-                                alpha_blend_op: vk::BlendOp::ADD,
-                                blend_enable: vk::TRUE,
-                                ..Default::default()
-                            },
-                        ]),
-                    )
-                    .dynamic_state(
-                        &vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&[
-                            vk::DynamicState::VIEWPORT,
-                            vk::DynamicState::SCISSOR,
-                        ]),
-                    )
-                    .push_next(
-                        &mut vk::PipelineRenderingCreateInfo::default()
-                            .color_attachment_formats(&[vk::Format::B8G8R8A8_SRGB]),
-                    ),
-            )
+        state: GraphicsPipelineState {
+            vertex_input: VertexInputState {
+                bindings: vec![VertexBinding {
+                    binding: 0,
+                    stride: std::mem::size_of::<egui::epaint::Vertex>() as u32,
+                    input_rate: vk::VertexInputRate::VERTEX,
+                }],
+                attributes: vec![
+                    VertexAttribute {
+                        binding: 0,
+                        location: 0,
+                        format: vk::Format::R32G32_SFLOAT,
+                        offset: 0,
+                    }, // pos
+                    VertexAttribute {
+                        binding: 0,
+                        location: 1,
+                        format: vk::Format::R32G32_SFLOAT,
+                        offset: std::mem::size_of::<[f32; 2]>() as u32,
+                    }, // uv
+                    VertexAttribute {
+                        binding: 0,
+                        location: 2,
+                        format: vk::Format::R8G8B8A8_UNORM,
+                        offset: std::mem::size_of::<[f32; 4]>() as u32,
+                    }, // color
+                ],
+            },
+            color_blend: ColorBlendState {
+                attachments: vec![ColorBlendAttachment {
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            rendering: RenderingFormats {
+                color_formats: vec![vk::Format::B8G8R8A8_SRGB],
+                ..Default::default()
+            },
+            ..Default::default()
         },
     };
     let pipeline = pipeline_cache.create_graphics(pipeline_create_info);
-    commands.insert_resource(EguiPipelines { pipeline, layout });
+    commands.insert_resource(EguiPipelines {
+        pipeline,
+        layout,
+        use_push_descriptors,
+        descriptor_allocator: DescriptorAllocator::new(device.clone()),
+    });
 }
 
 #[derive(Resource)]
@@ -315,8 +276,8 @@ impl<Filter: QueryFilter + Send + Sync + 'static> FromWorld for EguiHostBuffer<F
 pub struct EguiDeviceBuffer<Filter: QueryFilter> {
     total_indices_count: usize,
     total_vertices_count: usize,
-    index_buffer: GPUBorrowedResource<BufferVec<MaybeUninit<u32>>>,
-    vertex_buffer: GPUBorrowedResource<BufferVec<MaybeUninit<egui::epaint::Vertex>>>,
+    index_buffer: GPUBorrowedResource<DeviceVec<MaybeUninit<u32>>>,
+    vertex_buffer: GPUBorrowedResource<DeviceVec<MaybeUninit<egui::epaint::Vertex>>>,
     textures: BTreeMap<
         u64,
         (
@@ -330,12 +291,12 @@ pub struct EguiDeviceBuffer<Filter: QueryFilter> {
 impl<Filter: QueryFilter + Send + Sync + 'static> EguiDeviceBuffer<Filter> {
     fn new(allocator: &Allocator) -> Self {
         Self {
-            index_buffer: GPUBorrowedResource::new(BufferVec::new_resource(
+            index_buffer: GPUBorrowedResource::new(DeviceVec::new(
                 allocator.clone(),
                 4,
                 vk::BufferUsageFlags::INDEX_BUFFER,
             )),
-            vertex_buffer: GPUBorrowedResource::new(BufferVec::new_resource(
+            vertex_buffer: GPUBorrowedResource::new(DeviceVec::new(
                 allocator.clone(),
                 4,
                 vk::BufferUsageFlags::VERTEX_BUFFER,
@@ -618,27 +579,27 @@ fn resize_device_buffers<Filter: QueryFilter + Send + Sync + 'static>(
 ) {
     let device_buffers: &mut EguiDeviceBuffer<Filter> = &mut *device_buffers;
     if device_buffers.vertex_buffer.len() < device_buffers.total_vertices_count {
-        device_buffers.vertex_buffer = {
-            let mut buf = BufferVec::new_resource(
+        device_buffers.vertex_buffer = GPUBorrowedResource::new(
+            DeviceVec::with_len(
                 allocator.clone(),
                 4,
-                vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-            );
-            buf.resize(device_buffers.total_vertices_count, MaybeUninit::uninit());
-            GPUBorrowedResource::new(buf)
-        };
+                vk::BufferUsageFlags::VERTEX_BUFFER,
+                device_buffers.total_vertices_count,
+            )
+            .unwrap(),
+        );
     }
 
     if device_buffers.index_buffer.len() < device_buffers.total_indices_count {
-        device_buffers.index_buffer = {
-            let mut buf = BufferVec::new_resource(
+        device_buffers.index_buffer = GPUBorrowedResource::new(
+            DeviceVec::with_len(
                 allocator.clone(),
                 4,
-                vk::BufferUsageFlags::INDEX_BUFFER | vk::BufferUsageFlags::TRANSFER_DST,
-            );
-            buf.resize(device_buffers.total_indices_count, MaybeUninit::uninit());
-            GPUBorrowedResource::new(buf)
-        };
+                vk::BufferUsageFlags::INDEX_BUFFER,
+                device_buffers.total_indices_count,
+            )
+            .unwrap(),
+        );
     }
 }
 
@@ -700,6 +661,7 @@ pub fn draw<'w, 's, Filter: QueryFilter + Send + Sync + 'static>(
     allocator: Res<'w, Allocator>,
 ) -> impl GPUFutureBlock<Returned = EguiHostBuffer<Filter>> + use<'w, 's, Filter> {
     let egui_pipeline = egui_pipeline.into_inner();
+    egui_pipeline.descriptor_allocator.next_frame().unwrap();
     let reused_host_buffers = input
         .take()
         .unwrap_or_else(|| EguiHostBuffer::new(allocator.clone()));
@@ -782,6 +744,7 @@ pub fn draw<'w, 's, Filter: QueryFilter + Send + Sync + 'static>(
                     &bytemuck::cast_slice(&[viewport_logical_size.x, viewport_logical_size.y]),
                 );
 
+                let mut descriptor_writer = DescriptorSetWriter::new();
                 let mut current_vertex = 0;
                 let mut current_indice = 0;
                 for egui::epaint::ClippedPrimitive {
@@ -816,21 +779,33 @@ pub fn draw<'w, 's, Filter: QueryFilter + Send + Sync + 'static>(
                     let (texture, options) = device_buffer.textures.get(&texture_id).unwrap();
                     let sampler = device_buffer.samplers.get(options).unwrap();
 
-                    pass.push_descriptor_set(
-                        egui_pipeline.layout.as_ref().raw(),
+                    descriptor_writer.clear();
+                    descriptor_writer.combined_image_sampler(
                         0,
-                        &[vk::WriteDescriptorSet {
-                            dst_binding: 0,
-                            descriptor_count: 1,
-                            descriptor_type: vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
-                            p_image_info: &vk::DescriptorImageInfo {
-                                image_view: texture.raw_image_view(),
-                                image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
-                                sampler: sampler.raw(),
-                            },
-                            ..Default::default()
-                        }],
+                        &**texture,
+                        vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        sampler,
                     );
+                    if egui_pipeline.use_push_descriptors {
+                        pass.push_descriptor_set(
+                            egui_pipeline.layout.raw(),
+                            0,
+                            &descriptor_writer.writes(vk::DescriptorSet::null()),
+                        );
+                    } else {
+                        let descriptor_set = egui_pipeline
+                            .descriptor_allocator
+                            .allocate(&egui_pipeline.layout.desc_sets()[0])
+                            .unwrap();
+                        descriptor_writer
+                            .write(egui_pipeline.descriptor_allocator.device(), descriptor_set);
+                        pass.bind_descriptor_sets(
+                            egui_pipeline.layout.raw(),
+                            0,
+                            &[descriptor_set],
+                            &[],
+                        );
+                    }
 
                     pass.draw_indexed(
                         mesh.indices.len() as u32,
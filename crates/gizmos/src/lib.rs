@@ -16,8 +16,10 @@ use rhyolite::dispose::RenderObject;
 use rhyolite::ecs::{Barriers, IntoRenderSystemConfigs, RenderCommands};
 use rhyolite::immediate_buffer_transfer::{ImmediateBufferTransferSet, ImmediateBuffers};
 use rhyolite::pipeline::{
-    CachedPipeline, DescriptorSetLayout, GraphicsPipeline, GraphicsPipelineBuildInfo,
-    PipelineCache, PipelineLayout,
+    BlendState, CachedPipeline, ColorBlendAttachment, ColorBlendState, GraphicsPipeline,
+    GraphicsPipelineBuildInfo, GraphicsPipelineState, InputAssemblyState, LayoutCache,
+    PipelineCache, PipelineLayout, RenderingFormats, VertexAttribute, VertexBinding,
+    VertexInputState,
 };
 use rhyolite::shader::{ShaderModule, SpecializedShader};
 use rhyolite::staging::UniformBelt;
@@ -134,33 +136,32 @@ fn initialize_pipelines(
     mut commands: Commands,
     device: Res<Device>,
     pipeline_cache: Res<PipelineCache>,
+    layout_cache: Res<LayoutCache>,
     assets: Res<AssetServer>,
 ) {
-    let layout = PipelineLayout::new(
-        device.clone(),
-        vec![Arc::new(
-            DescriptorSetLayout::new(
-                device.clone(),
-                &[vk::DescriptorSetLayoutBinding {
-                    binding: 0,
-                    descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
-                    descriptor_count: 1,
-                    stage_flags: vk::ShaderStageFlags::VERTEX,
-                    ..Default::default()
-                }],
-                vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR,
-            )
-            .unwrap(),
-        )],
-        &[vk::PushConstantRange {
-            offset: 0,
-            size: std::mem::size_of::<[f32; 2]>() as u32,
-            stage_flags: vk::ShaderStageFlags::VERTEX,
-        }], // Ideally this can be specified automatically
-        vk::PipelineLayoutCreateFlags::empty(),
-    )
-    .unwrap();
-    let layout = Arc::new(layout);
+    let desc0 = layout_cache
+        .descriptor_set_layout(
+            &[vk::DescriptorSetLayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::UNIFORM_BUFFER,
+                descriptor_count: 1,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+                ..Default::default()
+            }],
+            vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR,
+        )
+        .unwrap();
+    let layout = layout_cache
+        .pipeline_layout(
+            vec![desc0],
+            &[vk::PushConstantRange {
+                offset: 0,
+                size: std::mem::size_of::<[f32; 2]>() as u32,
+                stage_flags: vk::ShaderStageFlags::VERTEX,
+            }], // Ideally this can be specified automatically
+            vk::PipelineLayoutCreateFlags::empty(),
+        )
+        .unwrap();
     let pipeline_create_info = GraphicsPipelineBuildInfo {
         layout: layout.clone(),
         device: device.clone(),
@@ -178,96 +179,48 @@ fn initialize_pipelines(
                 ..Default::default()
             },
         ],
-        builder: move |builder: rhyolite::pipeline::Builder| {
-            builder.build(
-                vk::GraphicsPipelineCreateInfo::default()
-                    .vertex_input_state(
-                        &vk::PipelineVertexInputStateCreateInfo::default()
-                            .vertex_attribute_descriptions(&[
-                                vk::VertexInputAttributeDescription {
-                                    binding: 0,
-                                    location: 0,
-                                    format: vk::Format::R32G32B32_SFLOAT,
-                                    offset: offset_of!(GizmoBufferItem, position) as u32,
-                                }, // pos
-                                vk::VertexInputAttributeDescription {
-                                    binding: 0,
-                                    location: 1,
-                                    format: vk::Format::R8G8B8A8_UNORM,
-                                    offset: offset_of!(GizmoBufferItem, color) as u32,
-                                }, // color
-                            ])
-                            .vertex_binding_descriptions(&[vk::VertexInputBindingDescription {
-                                binding: 0,
-                                stride: std::mem::size_of::<GizmoBufferItem>() as u32,
-                                input_rate: vk::VertexInputRate::VERTEX,
-                            }]),
-                    )
-                    .input_assembly_state(&vk::PipelineInputAssemblyStateCreateInfo {
-                        topology: vk::PrimitiveTopology::LINE_LIST,
-                        primitive_restart_enable: vk::FALSE,
-                        ..Default::default()
-                    })
-                    .viewport_state(
-                        &vk::PipelineViewportStateCreateInfo::default()
-                            .viewports(&[vk::Viewport {
-                                x: 0.0,
-                                y: 0.0,
-                                width: 2560.0,
-                                height: 1440.0,
-                                min_depth: 0.0,
-                                max_depth: 1.0,
-                            }])
-                            .scissors(&[vk::Rect2D {
-                                offset: vk::Offset2D { x: 0, y: 0 },
-                                extent: vk::Extent2D {
-                                    width: 2560,
-                                    height: 1440,
-                                },
-                            }]),
-                    )
-                    .rasterization_state(&vk::PipelineRasterizationStateCreateInfo {
-                        polygon_mode: vk::PolygonMode::FILL,
-                        cull_mode: vk::CullModeFlags::NONE,
-                        line_width: 1.0,
-                        ..Default::default()
-                    })
-                    .multisample_state(&vk::PipelineMultisampleStateCreateInfo {
-                        rasterization_samples: vk::SampleCountFlags::TYPE_1,
-                        sample_shading_enable: vk::FALSE,
-                        ..Default::default()
-                    })
-                    .color_blend_state(
-                        &vk::PipelineColorBlendStateCreateInfo::default().attachments(&[
-                            vk::PipelineColorBlendAttachmentState {
-                                color_write_mask: vk::ColorComponentFlags::R
-                                    | vk::ColorComponentFlags::G
-                                    | vk::ColorComponentFlags::B
-                                    | vk::ColorComponentFlags::A,
-                                src_color_blend_factor: vk::BlendFactor::ONE,
-                                dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
-                                color_blend_op: vk::BlendOp::ADD,
-                                src_alpha_blend_factor: vk::BlendFactor::ONE,
-                                dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
-                                alpha_blend_op: vk::BlendOp::ADD,
-                                blend_enable: vk::TRUE,
-                                ..Default::default()
-                            },
-                        ]),
-                    )
-                    .dynamic_state(
-                        &vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&[
-                            vk::DynamicState::PRIMITIVE_TOPOLOGY,
-                            vk::DynamicState::LINE_WIDTH,
-                            vk::DynamicState::VIEWPORT,
-                            vk::DynamicState::SCISSOR,
-                        ]),
-                    )
-                    .push_next(
-                        &mut vk::PipelineRenderingCreateInfo::default()
-                            .color_attachment_formats(&[vk::Format::B8G8R8A8_UNORM]),
-                    ),
-            )
+        state: GraphicsPipelineState {
+            vertex_input: VertexInputState {
+                bindings: vec![VertexBinding {
+                    binding: 0,
+                    stride: std::mem::size_of::<GizmoBufferItem>() as u32,
+                    input_rate: vk::VertexInputRate::VERTEX,
+                }],
+                attributes: vec![
+                    VertexAttribute {
+                        binding: 0,
+                        location: 0,
+                        format: vk::Format::R32G32B32_SFLOAT,
+                        offset: offset_of!(GizmoBufferItem, position) as u32,
+                    }, // pos
+                    VertexAttribute {
+                        binding: 0,
+                        location: 1,
+                        format: vk::Format::R8G8B8A8_UNORM,
+                        offset: offset_of!(GizmoBufferItem, color) as u32,
+                    }, // color
+                ],
+            },
+            input_assembly: InputAssemblyState {
+                topology: vk::PrimitiveTopology::LINE_LIST,
+                primitive_restart_enable: false,
+            },
+            color_blend: ColorBlendState {
+                attachments: vec![ColorBlendAttachment {
+                    blend: Some(BlendState::PREMULTIPLIED_ALPHA),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            },
+            dynamic_states: vec![
+                vk::DynamicState::PRIMITIVE_TOPOLOGY,
+                vk::DynamicState::LINE_WIDTH,
+            ],
+            rendering: RenderingFormats {
+                color_formats: vec![vk::Format::B8G8R8A8_UNORM],
+                ..Default::default()
+            },
+            ..Default::default()
         },
     };
     let pipeline = pipeline_cache.create_graphics(pipeline_create_info);
@@ -1,21 +1,22 @@
 #![feature(let_chains)]
-use bevy::asset::{AssetServer, Assets};
+use bevy::asset::{AssetServer, Assets, Handle};
 use bevy::diagnostic::FrameCount;
 use bevy::ecs::schedule::IntoSystemConfigs;
 use bevy::prelude::{Mut, Query};
 use rhyolite::ash::vk;
 use rhyolite::swapchain::SwapchainImage;
-use rhyolite::{ImageExt, ImageViewLike, ImageWithView};
+use rhyolite::{ImageExt, ImageWithView};
 use std::ops::Deref;
 
-use bevy::app::{PluginGroup, PostUpdate, Startup};
+use bevy::app::{PluginGroup, PostUpdate, Startup, Update};
 use bevy::ecs::system::{Commands, Local, Res, ResMut, Resource};
 use bevy::ecs::{entity::Entity, query::With};
 use bevy::window::PrimaryWindow;
 use rhyolite::debug::DebugUtilsPlugin;
 use rhyolite::pipeline::{
-    CachedPipeline, ComputePipeline, ComputePipelineCreateInfo, DescriptorSetLayout, PipelineCache,
-    PipelineLayout,
+    CachedPipeline, ComputePipeline, ComputePipelineCreateInfo, DescriptorAllocator,
+    DescriptorBuffer, DescriptorBufferPlugin, DescriptorSetWriter, LayoutCache, PipelineCache,
+    PipelineLayout, PipelineLayoutError,
 };
 use rhyolite::shader::{ShaderModule, SpecializedShader};
 use rhyolite::{
@@ -44,8 +45,11 @@ fn main() {
     .add_plugins(RhyolitePlugin::default())
     .add_plugins(SwapchainPlugin::default());
 
+    // Descriptors are written into a descriptor buffer if supported. Otherwise they are pushed,
+    // and without push descriptors, the descriptor set is allocated from a pool.
+    app.add_plugins(DescriptorBufferPlugin);
     app.add_device_extension::<ash::khr::push_descriptor::Meta>()
-        .unwrap();
+        .ok();
 
     let primary_window = app
         .world_mut()
@@ -64,7 +68,8 @@ fn main() {
             ..Default::default()
         });
 
-    app.add_systems(Startup, initialize_pipeline);
+    app.add_systems(Startup, initialize_game);
+    app.add_systems(Update, initialize_pipeline);
     app.add_systems(
         PostUpdate,
         run_compute_shader
@@ -77,59 +82,29 @@ fn main() {
 
 #[derive(Resource)]
 struct GameOfLifePipeline {
+    run_shader: Handle<ShaderModule>,
+    init_shader: Handle<ShaderModule>,
+    /// Created once the shaders are loaded, because the pipeline layout is reflected from them.
+    pipelines: Option<GameOfLifePipelines>,
+    game: GPUBorrowedResource<ImageWithView<Image>>,
+}
+
+struct GameOfLifePipelines {
     run_pipeline: CachedPipeline<ComputePipeline>,
     init_pipeline: CachedPipeline<ComputePipeline>,
-    game: GPUBorrowedResource<ImageWithView<Image>>,
     layout: Arc<PipelineLayout>,
+    descriptors: GameOfLifeDescriptors,
 }
-fn initialize_pipeline(
-    mut commands: Commands,
-    device: Res<Device>,
-    pipeline_cache: Res<PipelineCache>,
-    assets: Res<AssetServer>,
-    allocator: Res<Allocator>,
-) {
-    let desc0 = DescriptorSetLayout::new(
-        device.clone(),
-        &playout_macro::layout!("../assets/game_of_life/game_of_life.playout", 0),
-        vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR,
-    )
-    .unwrap();
-
-    let layout = PipelineLayout::new(
-        device.clone(),
-        vec![Arc::new(desc0)],
-        &[vk::PushConstantRange {
-            offset: 0,
-            size: std::mem::size_of::<[f32; 2]>() as u32,
-            stage_flags: vk::ShaderStageFlags::VERTEX,
-        }], // Ideally this can be specified automatically
-        vk::PipelineLayoutCreateFlags::empty(),
-    )
-    .unwrap();
-    let layout = Arc::new(layout);
-    let run_pipeline = pipeline_cache.create_compute(ComputePipelineCreateInfo {
-        device: device.clone(),
-        shader: SpecializedShader {
-            stage: vk::ShaderStageFlags::COMPUTE,
-            shader: assets.load("game_of_life/game_of_life.comp"),
-            ..Default::default()
-        },
-        layout: layout.clone(),
-        flags: vk::PipelineCreateFlags::empty(),
-    });
 
-    let init_pipeline = pipeline_cache.create_compute(ComputePipelineCreateInfo {
-        device: device.clone(),
-        shader: SpecializedShader {
-            stage: vk::ShaderStageFlags::COMPUTE,
-            shader: assets.load("game_of_life/game_of_life_init.comp"),
-            ..Default::default()
-        },
-        layout: layout.clone(),
-        flags: vk::PipelineCreateFlags::empty(),
-    });
+enum GameOfLifeDescriptors {
+    /// The set is written into a descriptor buffer at the given offset.
+    Buffer(DescriptorBuffer, vk::DeviceSize),
+    Push,
+    /// The set is allocated from a pool. The allocator keeps the set alive.
+    Set(vk::DescriptorSet, DescriptorAllocator),
+}
 
+fn initialize_game(mut commands: Commands, assets: Res<AssetServer>, allocator: Res<Allocator>) {
     let game_img_create_info = vk::ImageCreateInfo {
         image_type: vk::ImageType::TYPE_2D,
         format: vk::Format::R8G8B8A8_UNORM,
@@ -146,16 +121,106 @@ fn initialize_pipeline(
         initial_layout: vk::ImageLayout::UNDEFINED,
         ..Default::default()
     };
+    let game = Image::new_device_image(allocator.clone(), &game_img_create_info)
+        .unwrap()
+        .with_view()
+        .unwrap();
     commands.insert_resource(GameOfLifePipeline {
-        init_pipeline,
+        run_shader: assets.load("game_of_life/game_of_life.comp"),
+        init_shader: assets.load("game_of_life/game_of_life_init.comp"),
+        pipelines: None,
+        game: GPUBorrowedResource::new(game),
+    });
+}
+
+fn initialize_pipeline(
+    mut game_of_life_pipeline: ResMut<GameOfLifePipeline>,
+    device: Res<Device>,
+    pipeline_cache: Res<PipelineCache>,
+    layout_cache: Res<LayoutCache>,
+    shaders: Res<Assets<ShaderModule>>,
+    allocator: Res<Allocator>,
+) {
+    if game_of_life_pipeline.pipelines.is_some() {
+        return;
+    }
+    let use_descriptor_buffer = DescriptorBuffer::is_supported(&device);
+    let use_push_descriptors = device
+        .get_extension::<ash::khr::push_descriptor::Meta>()
+        .is_ok();
+    let run_shader = SpecializedShader {
+        stage: vk::ShaderStageFlags::COMPUTE,
+        shader: game_of_life_pipeline.run_shader.clone(),
+        ..Default::default()
+    };
+    let init_shader = SpecializedShader {
+        stage: vk::ShaderStageFlags::COMPUTE,
+        shader: game_of_life_pipeline.init_shader.clone(),
+        ..Default::default()
+    };
+
+    // The descriptor set layout and push constant ranges are reflected from the shaders.
+    let layout = match layout_cache.from_shaders(
+        &[run_shader.clone(), init_shader.clone()],
+        &shaders,
+        &[],
+        if use_descriptor_buffer {
+            vk::DescriptorSetLayoutCreateFlags::DESCRIPTOR_BUFFER_EXT
+        } else if use_push_descriptors {
+            vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR
+        } else {
+            vk::DescriptorSetLayoutCreateFlags::empty()
+        },
+    ) {
+        Ok(layout) => layout,
+        Err(PipelineLayoutError::ShaderNotLoaded(_)) => return,
+        Err(err) => panic!("{err}"),
+    };
+    let pipeline_flags = if use_descriptor_buffer {
+        vk::PipelineCreateFlags::DESCRIPTOR_BUFFER_EXT
+    } else {
+        vk::PipelineCreateFlags::empty()
+    };
+    let run_pipeline = pipeline_cache.create_compute(ComputePipelineCreateInfo {
+        device: device.clone(),
+        shader: run_shader,
+        layout: layout.clone(),
+        flags: pipeline_flags,
+    });
+    let init_pipeline = pipeline_cache.create_compute(ComputePipelineCreateInfo {
+        device: device.clone(),
+        shader: init_shader,
+        layout: layout.clone(),
+        flags: pipeline_flags,
+    });
+
+    // The image never changes, so the descriptor set only needs to be written once.
+    let set_layout = &layout.desc_sets()[0];
+    let mut writer = DescriptorSetWriter::new();
+    writer.storage_image(0, &*game_of_life_pipeline.game);
+    let descriptors = if use_descriptor_buffer {
+        let mut descriptor_buffer = DescriptorBuffer::new(
+            allocator.clone(),
+            set_layout.descriptor_buffer_size().unwrap(),
+            vk::BufferUsageFlags::RESOURCE_DESCRIPTOR_BUFFER_EXT,
+        )
+        .unwrap();
+        let offset = descriptor_buffer.allocate(set_layout).unwrap();
+        descriptor_buffer.write(set_layout, offset, &writer);
+        GameOfLifeDescriptors::Buffer(descriptor_buffer, offset)
+    } else if use_push_descriptors {
+        GameOfLifeDescriptors::Push
+    } else {
+        let mut descriptor_allocator = DescriptorAllocator::new(device.clone());
+        let set = descriptor_allocator.allocate(set_layout).unwrap();
+        writer.write(&device, set);
+        GameOfLifeDescriptors::Set(set, descriptor_allocator)
+    };
+    game_of_life_pipeline.pipelines = Some(GameOfLifePipelines {
         run_pipeline,
-        game: GPUBorrowedResource::new(
-            Image::new_device_image(allocator.clone(), &game_img_create_info)
-                .unwrap()
-                .with_view()
-                .unwrap(),
-        ),
+        init_pipeline,
         layout,
+        descriptors,
     });
 }
 
@@ -170,40 +235,52 @@ fn run_compute_shader<'w, 's>(
 ) -> impl GPUFutureBlock + use<'w, 's> {
     let game_of_life_pipeline = game_of_life_pipeline.into_inner();
     gpu_future! { move
-        let pipeline = pipeline_cache.retrieve(
-            if *initialized {
-                &mut game_of_life_pipeline.run_pipeline
-            } else {
-                &mut game_of_life_pipeline.init_pipeline
-            },
-            assets.into_inner(),
-            task_pool.into_inner(),
-        );
-        if let Some(pipeline) = pipeline && (frame_index.0 % 30 == 0 || !*initialized) {
+        if let Some(GameOfLifePipelines { run_pipeline, init_pipeline, layout, descriptors }) =
+            &mut game_of_life_pipeline.pipelines
+            && let Some(pipeline) = pipeline_cache.retrieve(
+                if *initialized { run_pipeline } else { init_pipeline },
+                assets.into_inner(),
+                task_pool.into_inner(),
+            )
+            && (frame_index.0 % 30 == 0 || !*initialized)
+        {
             *initialized = true;
             record_commands(
                 &mut game_of_life_pipeline.game,
                 |mut ctx, game| unsafe {
                     ctx.bind_pipeline(pipeline.deref());
-                    ctx.device.extension::<ash::khr::push_descriptor::Meta>()
-                        .cmd_push_descriptor_set(
-                            ctx.command_buffer,
-                            vk::PipelineBindPoint::COMPUTE,
-                            game_of_life_pipeline.layout.raw(),
-                            0,
-                            &[vk::WriteDescriptorSet {
-                                dst_binding: 0,
-                                descriptor_count: 1,
-                                descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
-                                p_image_info: [vk::DescriptorImageInfo {
-                                    image_view: game.raw_image_view(),
-                                    image_layout: vk::ImageLayout::GENERAL,
-                                    ..Default::default()
-                                }]
-                                .as_ptr(),
-                                ..Default::default()
-                            }],
-                        );
+                    let layout = layout.raw();
+                    match &*descriptors {
+                        GameOfLifeDescriptors::Buffer(descriptor_buffer, offset) => {
+                            ctx.bind_descriptor_buffers(&[descriptor_buffer.binding_info()]);
+                            ctx.set_descriptor_buffer_offsets(
+                                vk::PipelineBindPoint::COMPUTE,
+                                layout,
+                                0,
+                                &[0],
+                                &[*offset],
+                            );
+                        }
+                        GameOfLifeDescriptors::Push => {
+                            ctx.push_descriptor_set(
+                                vk::PipelineBindPoint::COMPUTE,
+                                layout,
+                                0,
+                                &DescriptorSetWriter::new()
+                                    .storage_image(0, &**game)
+                                    .writes(vk::DescriptorSet::null()),
+                            );
+                        }
+                        GameOfLifeDescriptors::Set(descriptor_set, _) => {
+                            ctx.bind_descriptor_sets(
+                                vk::PipelineBindPoint::COMPUTE,
+                                layout,
+                                0,
+                                &[*descriptor_set],
+                                &[],
+                            );
+                        }
+                    }
                     ctx.device.cmd_dispatch(ctx.command_buffer, 192, 108, 1);
             }, |mut ctx, game| {
                 ctx.use_image_resource(
@@ -0,0 +1,151 @@
+use ash::vk;
+use bevy::{
+    app::{App, First, Plugin},
+    diagnostic::{Diagnostic, DiagnosticPath, Diagnostics, RegisterDiagnostic},
+    ecs::{
+        event::{Event, EventWriter},
+        system::{Local, Res, ResMut, Resource},
+    },
+};
+
+use crate::{plugin::RhyoliteApp, Allocator, HasDevice, PhysicalDevice};
+
+/// Publishes the per-heap memory budget and allocator statistics as bevy diagnostics.
+///
+/// Enables `VK_EXT_memory_budget` when available. Without it, the budget is estimated by vk_mem
+/// from the heap sizes. Must be added after [`RhyolitePlugin`](crate::RhyolitePlugin).
+pub struct MemoryBudgetPlugin {
+    /// [`MemoryPressure`] will be fired when the usage of a heap crosses this fraction of its budget.
+    pub pressure_threshold: f32,
+}
+impl Default for MemoryBudgetPlugin {
+    fn default() -> Self {
+        Self {
+            pressure_threshold: 0.9,
+        }
+    }
+}
+
+/// Fired when the usage of a memory heap crosses [`MemoryBudgetPlugin::pressure_threshold`]
+/// of its budget. Streaming systems may want to release resources in response.
+#[derive(Event, Debug, Clone)]
+pub struct MemoryPressure {
+    pub heap_index: u32,
+    pub heap_flags: vk::MemoryHeapFlags,
+    /// Estimated memory usage of the heap, in bytes, including usage by other processes.
+    pub usage: vk::DeviceSize,
+    /// Estimated amount of memory available to this process, in bytes.
+    pub budget: vk::DeviceSize,
+}
+
+struct HeapDiagnosticPaths {
+    usage: DiagnosticPath,
+    budget: DiagnosticPath,
+    block_bytes: DiagnosticPath,
+    allocation_bytes: DiagnosticPath,
+    allocation_count: DiagnosticPath,
+}
+impl HeapDiagnosticPaths {
+    fn new(heap_index: usize) -> Self {
+        let path = |name: &str| DiagnosticPath::new(format!("rhyolite/heap_{heap_index}/{name}"));
+        Self {
+            usage: path("usage"),
+            budget: path("budget"),
+            block_bytes: path("block_bytes"),
+            allocation_bytes: path("allocation_bytes"),
+            allocation_count: path("allocation_count"),
+        }
+    }
+}
+
+#[derive(Resource)]
+struct MemoryBudgetState {
+    pressure_threshold: f32,
+    heaps: Vec<HeapDiagnosticPaths>,
+    under_pressure: Vec<bool>,
+}
+
+impl Plugin for MemoryBudgetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_device_extension_named(vk::EXT_MEMORY_BUDGET_NAME)
+            .ok();
+        app.add_event::<MemoryPressure>();
+
+        let heap_count = app
+            .world()
+            .resource::<PhysicalDevice>()
+            .properties()
+            .memory_heaps()
+            .len();
+        let heaps: Vec<_> = (0..heap_count).map(HeapDiagnosticPaths::new).collect();
+        for heap in heaps.iter() {
+            app.register_diagnostic(Diagnostic::new(heap.usage.clone()).with_suffix(" MiB"))
+                .register_diagnostic(Diagnostic::new(heap.budget.clone()).with_suffix(" MiB"))
+                .register_diagnostic(Diagnostic::new(heap.block_bytes.clone()).with_suffix(" MiB"))
+                .register_diagnostic(
+                    Diagnostic::new(heap.allocation_bytes.clone()).with_suffix(" MiB"),
+                )
+                .register_diagnostic(Diagnostic::new(heap.allocation_count.clone()));
+        }
+        app.insert_resource(MemoryBudgetState {
+            pressure_threshold: self.pressure_threshold,
+            heaps,
+            under_pressure: vec![false; heap_count],
+        });
+        app.add_systems(First, memory_budget_system);
+    }
+}
+
+fn memory_budget_system(
+    allocator: Res<Allocator>,
+    mut state: ResMut<MemoryBudgetState>,
+    mut diagnostics: Diagnostics,
+    mut events: EventWriter<MemoryPressure>,
+    mut frame_index: Local<u32>,
+) {
+    // vk_mem refreshes its cached budget when the frame index changes.
+    *frame_index = frame_index.wrapping_add(1);
+    allocator.set_current_frame_index(*frame_index);
+
+    let budgets = match allocator.get_heap_budgets() {
+        Ok(budgets) => budgets,
+        Err(err) => {
+            tracing::warn!(?err, "Failed to query memory budget");
+            return;
+        }
+    };
+    let memory_heaps = allocator.physical_device().properties().memory_heaps();
+    let state = &mut *state;
+    for (heap_index, (budget, heap)) in budgets.iter().zip(state.heaps.iter()).enumerate() {
+        const MIB: f64 = (1024 * 1024) as f64;
+        diagnostics.add_measurement(&heap.usage, || budget.usage as f64 / MIB);
+        diagnostics.add_measurement(&heap.budget, || budget.budget as f64 / MIB);
+        diagnostics.add_measurement(&heap.block_bytes, || {
+            budget.statistics.blockBytes as f64 / MIB
+        });
+        diagnostics.add_measurement(&heap.allocation_bytes, || {
+            budget.statistics.allocationBytes as f64 / MIB
+        });
+        diagnostics.add_measurement(&heap.allocation_count, || {
+            budget.statistics.allocationCount as f64
+        });
+
+        let under_pressure = budget.budget > 0
+            && budget.usage as f64 >= budget.budget as f64 * state.pressure_threshold as f64;
+        if under_pressure && !state.under_pressure[heap_index] {
+            tracing::warn!(
+                heap_index,
+                usage = budget.usage,
+                budget = budget.budget,
+                "Memory heap is under pressure"
+            );
+            events.send(MemoryPressure {
+                heap_index: heap_index as u32,
+                heap_flags: memory_heaps[heap_index].flags,
+                usage: budget.usage,
+                budget: budget.budget,
+            });
+        }
+        state.under_pressure[heap_index] = under_pressure;
+    }
+}
@@ -0,0 +1,628 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Condvar, Mutex, OnceLock, Weak,
+    },
+    thread::JoinHandle,
+};
+
+use ash::{
+    prelude::VkResult,
+    vk::{self, Handle},
+};
+use bevy::{
+    app::{App, First, Plugin},
+    ecs::{
+        system::{Local, ResMut, Resource},
+        world::FromWorld,
+    },
+};
+
+use super::Allocator;
+use crate::{Device, HasDevice};
+use thiserror::Error;
+use vk_mem::ffi::VmaDefragmentationMoveOperation::{
+    VMA_DEFRAGMENTATION_MOVE_OPERATION_DESTROY as MOVE_DESTROY,
+    VMA_DEFRAGMENTATION_MOVE_OPERATION_IGNORE as MOVE_IGNORE,
+};
+
+/// Incrementally defragments device memory allocated through [`Allocator`].
+///
+/// Resources are not moved unless they opt in with `Buffer::enable_defragmentation` or
+/// `Image::enable_defragmentation`. Every `interval` frames, the plugin starts one defragmentation
+/// pass that moves at most `max_bytes_per_pass` bytes.
+///
+/// A pass only proposes moves. Each moved resource gets a new handle bound to the new memory,
+/// but the resource keeps using its old handle until its owner awaits
+/// [`relocate_buffer`](crate::commands::relocate_buffer) or
+/// [`relocate_image`](crate::commands::relocate_image) in a render system. That future copies the
+/// contents on the GPU, publishes the new handle, and retires the old handle once the frame
+/// completes. Moves that were not applied within `interval` frames are cancelled.
+///
+/// vk_mem frees the old memory when the pass ends, so the pass waits for all of its moves to be
+/// retired. This happens on a worker thread and never stalls the frame.
+pub struct DefragmentationPlugin {
+    pub max_bytes_per_pass: vk::DeviceSize,
+    pub max_allocations_per_pass: u32,
+    /// Number of frames between defragmentation passes.
+    pub interval: u32,
+}
+impl Default for DefragmentationPlugin {
+    fn default() -> Self {
+        Self {
+            max_bytes_per_pass: 16 * 1024 * 1024,
+            max_allocations_per_pass: 64,
+            interval: 60,
+        }
+    }
+}
+
+impl Plugin for DefragmentationPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(First, defragmentation_system);
+    }
+    fn finish(&self, app: &mut App) {
+        let mut defragmenter = Defragmenter::from_world(app.world_mut());
+        defragmenter.max_bytes_per_pass = self.max_bytes_per_pass;
+        defragmenter.max_allocations_per_pass = self.max_allocations_per_pass;
+        defragmenter.interval = self.interval;
+        app.insert_resource(defragmenter);
+    }
+}
+
+/// Reasons a resource cannot opt into defragmentation.
+#[derive(Debug, Error)]
+pub enum DefragmentationError {
+    #[error("Buffers with a device address cannot be moved")]
+    DeviceAddress,
+    #[error("Host visible memory may be accessed through a mapped pointer and cannot be moved")]
+    HostVisible,
+    #[error("Images with concurrent sharing cannot be moved")]
+    ConcurrentImage,
+}
+
+pub(crate) enum RelocationKind {
+    Buffer {
+        /// `p_next` and the queue family indices are cleared.
+        info: vk::BufferCreateInfo<'static>,
+        queue_family_indices: Box<[u32]>,
+    },
+    Image {
+        info: vk::ImageCreateInfo<'static>,
+        aspect_mask: vk::ImageAspectFlags,
+    },
+}
+
+type SlotRegistry = Mutex<HashMap<usize, Weak<RelocationSlot>>>;
+
+/// Shared between a resource that opted into defragmentation and the [`Defragmenter`].
+/// Holds the current raw handle of the resource, which changes when a move was applied.
+pub(crate) struct RelocationSlot {
+    device: Device,
+    handle: AtomicU64,
+    kind: RelocationKind,
+    registry: Arc<SlotRegistry>,
+    /// The move proposed for this resource by the pass in progress.
+    relocation: Mutex<Option<Relocation>>,
+}
+// Safety: The create info in `kind` does not point to any other structures.
+unsafe impl Send for RelocationSlot {}
+unsafe impl Sync for RelocationSlot {}
+
+struct Relocation {
+    pass: Arc<RelocationPass>,
+    index: usize,
+    new_handle: u64,
+    /// Set once the contents were copied and the new handle was published.
+    applied: bool,
+    /// Set when the resource was dropped after the move was applied.
+    destroy: bool,
+}
+
+/// Transitions of the move proposed for a slot. Every move is resolved exactly once,
+/// which lets the pass waiting in [`RelocationPass::wait`] complete.
+impl Relocation {
+    /// Mark a pending move as applied. Returns the new handle.
+    fn apply(relocation: &mut Option<Self>) -> Option<u64> {
+        let relocation = relocation.as_mut().filter(|r| !r.applied)?;
+        relocation.applied = true;
+        Some(relocation.new_handle)
+    }
+    /// The resource was dropped. Returns the new handle if it was never used.
+    fn release(relocation: &mut Option<Self>) -> Option<u64> {
+        let r = relocation.as_mut()?;
+        if r.applied {
+            // The old handle is retired together with the copy.
+            r.destroy = true;
+            return None;
+        }
+        let r = relocation.take().unwrap();
+        r.pass.resolve(r.index, MoveOutcome::Destroyed);
+        Some(r.new_handle)
+    }
+    /// Cancel a move that was not applied yet. Returns the new handle, which was never used.
+    fn withdraw(relocation: &mut Option<Self>) -> Option<u64> {
+        if !relocation.as_ref().is_some_and(|r| !r.applied) {
+            return None;
+        }
+        let r = relocation.take().unwrap();
+        r.pass.resolve(r.index, MoveOutcome::Ignored);
+        Some(r.new_handle)
+    }
+    /// The old handle of an applied move was destroyed.
+    fn retire(relocation: &mut Option<Self>) {
+        if let Some(r) = relocation.take() {
+            let outcome = if r.destroy {
+                MoveOutcome::Destroyed
+            } else {
+                MoveOutcome::Applied
+            };
+            r.pass.resolve(r.index, outcome);
+        }
+    }
+}
+
+impl RelocationSlot {
+    pub fn handle<T: Handle>(&self) -> T {
+        T::from_raw(self.handle.load(Ordering::Acquire))
+    }
+    pub fn kind(&self) -> &RelocationKind {
+        &self.kind
+    }
+    /// Returns true if a move was proposed for the resource but not yet applied.
+    pub fn is_pending(&self) -> bool {
+        self.relocation
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|relocation| !relocation.applied)
+    }
+    /// Publish the new handle of a pending move. Returns the old handle and the new handle.
+    ///
+    /// The copy into the new handle must be recorded right away, and the old handle must be
+    /// retired with [`RetiredRelocation`].
+    pub fn apply<T: Handle>(&self) -> Option<(T, T)> {
+        let new_handle = Relocation::apply(&mut self.relocation.lock().unwrap())?;
+        let old_handle = self.handle.swap(new_handle, Ordering::AcqRel);
+        Some((T::from_raw(old_handle), T::from_raw(new_handle)))
+    }
+    /// Remove the slot from the [`Defragmenter`] when the resource is dropped.
+    ///
+    /// Returns true if the allocation is part of a defragmentation pass in progress. The pass
+    /// frees the allocation, so the caller must only destroy the current handle.
+    pub fn release(&self) -> bool {
+        // Taking the registry lock waits for a pass that is still proposing moves.
+        self.registry
+            .lock()
+            .unwrap()
+            .remove(&(self as *const Self as usize));
+        let mut relocation = self.relocation.lock().unwrap();
+        let handed_over = relocation.is_some();
+        if let Some(new_handle) = Relocation::release(&mut relocation) {
+            destroy_handle(&self.device, &self.kind, new_handle);
+        }
+        handed_over
+    }
+    /// Cancel the pending move, if it was not applied yet.
+    fn withdraw(&self) {
+        if let Some(new_handle) = Relocation::withdraw(&mut self.relocation.lock().unwrap()) {
+            destroy_handle(&self.device, &self.kind, new_handle);
+        }
+    }
+}
+
+/// Keeps the old handle of a moved resource alive until the GPU has finished the copy and
+/// all earlier uses of the resource. Retain it in the GPU future that applied the move.
+pub struct RetiredRelocation {
+    slot: Arc<RelocationSlot>,
+    old_handle: u64,
+}
+impl RetiredRelocation {
+    pub(crate) fn new(slot: Arc<RelocationSlot>, old_handle: impl Handle) -> Self {
+        Self {
+            slot,
+            old_handle: old_handle.as_raw(),
+        }
+    }
+}
+impl Drop for RetiredRelocation {
+    fn drop(&mut self) {
+        destroy_handle(&self.slot.device, &self.slot.kind, self.old_handle);
+        Relocation::retire(&mut self.slot.relocation.lock().unwrap());
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum MoveOutcome {
+    Pending,
+    Applied,
+    Ignored,
+    Destroyed,
+}
+
+/// The moves proposed by a pass in progress.
+struct RelocationPass {
+    outcomes: Mutex<Vec<MoveOutcome>>,
+    resolved: Condvar,
+    slots: Mutex<Vec<Weak<RelocationSlot>>>,
+}
+impl RelocationPass {
+    fn resolve(&self, index: usize, outcome: MoveOutcome) {
+        self.outcomes.lock().unwrap()[index] = outcome;
+        self.resolved.notify_all();
+    }
+    /// Block until every move was applied, cancelled or destroyed.
+    fn wait(&self) -> Vec<MoveOutcome> {
+        let mut outcomes = self.outcomes.lock().unwrap();
+        while outcomes.contains(&MoveOutcome::Pending) {
+            outcomes = self.resolved.wait(outcomes).unwrap();
+        }
+        outcomes.clone()
+    }
+    /// Cancel all moves that were not applied yet.
+    fn withdraw(&self) {
+        let slots = std::mem::take(&mut *self.slots.lock().unwrap());
+        for slot in slots.iter().filter_map(Weak::upgrade) {
+            slot.withdraw();
+        }
+    }
+}
+
+struct PassInFlight {
+    started_frame: u32,
+    pass: Arc<OnceLock<Arc<RelocationPass>>>,
+    worker: JoinHandle<VkResult<usize>>,
+}
+
+#[derive(Resource)]
+pub struct Defragmenter {
+    allocator: Allocator,
+    /// Keyed by the allocation user data, which is the address of the slot.
+    /// Locked while a pass is proposing moves.
+    slots: Arc<SlotRegistry>,
+    in_flight: Option<PassInFlight>,
+    pub max_bytes_per_pass: vk::DeviceSize,
+    pub max_allocations_per_pass: u32,
+    pub interval: u32,
+    /// Set to false to pause defragmentation.
+    pub enabled: bool,
+}
+
+impl FromWorld for Defragmenter {
+    fn from_world(world: &mut bevy::ecs::world::World) -> Self {
+        let allocator = world.resource::<Allocator>().clone();
+        Self {
+            allocator,
+            slots: Default::default(),
+            in_flight: None,
+            max_bytes_per_pass: 16 * 1024 * 1024,
+            max_allocations_per_pass: 64,
+            interval: 60,
+            enabled: true,
+        }
+    }
+}
+
+impl Drop for Defragmenter {
+    fn drop(&mut self) {
+        // Let the worker finish once the applied moves were retired.
+        if let Some(pass) = self.in_flight.as_ref().and_then(|p| p.pass.get()) {
+            pass.withdraw();
+        }
+    }
+}
+
+impl Defragmenter {
+    pub(crate) fn register(
+        &self,
+        allocation: &mut vk_mem::Allocation,
+        handle: u64,
+        kind: RelocationKind,
+    ) -> Arc<RelocationSlot> {
+        let slot = Arc::new(RelocationSlot {
+            device: self.allocator.device().clone(),
+            handle: AtomicU64::new(handle),
+            kind,
+            registry: self.slots.clone(),
+            relocation: Mutex::new(None),
+        });
+        let key = Arc::as_ptr(&slot) as usize;
+        unsafe {
+            self.allocator
+                .set_allocation_user_data(allocation, key as *mut std::ffi::c_void);
+        }
+        self.slots
+            .lock()
+            .unwrap()
+            .insert(key, Arc::downgrade(&slot));
+        slot
+    }
+
+    fn start_pass(&mut self, frame_index: u32) {
+        let allocator = self.allocator.clone();
+        let slots = self.slots.clone();
+        let pass = Arc::new(OnceLock::new());
+        let published = pass.clone();
+        let info = vk_mem::ffi::VmaDefragmentationInfo {
+            maxBytesPerPass: self.max_bytes_per_pass,
+            maxAllocationsPerPass: self.max_allocations_per_pass,
+            ..unsafe { std::mem::zeroed() }
+        };
+        let worker = std::thread::Builder::new()
+            .name("Defragmentation".into())
+            .spawn(move || run_pass(&allocator, &slots, &info, &published))
+            .expect("Failed to spawn the defragmentation thread");
+        self.in_flight = Some(PassInFlight {
+            started_frame: frame_index,
+            pass,
+            worker,
+        });
+    }
+}
+
+/// Run one defragmentation pass. Returns the number of moved allocations.
+fn run_pass(
+    allocator: &Allocator,
+    registry: &SlotRegistry,
+    info: &vk_mem::ffi::VmaDefragmentationInfo,
+    published: &OnceLock<Arc<RelocationPass>>,
+) -> VkResult<usize> {
+    let mut slots = Some(registry.lock().unwrap());
+    if slots.as_ref().unwrap().is_empty() {
+        return Ok(0);
+    }
+    let device = allocator.device().clone();
+    let context = unsafe { allocator.begin_defragmentation(info)? };
+    let mut moved = 0;
+    let mut result = Ok(());
+    unsafe {
+        context.begin_pass(|moves| {
+            let pass = Arc::new(RelocationPass {
+                outcomes: Mutex::new(vec![MoveOutcome::Ignored; moves.len()]),
+                resolved: Condvar::new(),
+                slots: Mutex::new(Vec::new()),
+            });
+            for (index, mv) in moves.iter_mut().enumerate() {
+                let src = borrow_allocation(&mv.srcAllocation);
+                let dst = borrow_allocation(&mv.dstTmpAllocation);
+                let key = allocator.get_allocation_info(src).user_data;
+                let Some(slot) = slots.as_ref().unwrap().get(&key).and_then(Weak::upgrade) else {
+                    // Not opted into defragmentation.
+                    mv.operation = MOVE_IGNORE;
+                    continue;
+                };
+                let new_handle = match create_relocated(&device, allocator, dst, &slot.kind) {
+                    Ok(handle) => handle,
+                    Err(err) => {
+                        mv.operation = MOVE_IGNORE;
+                        result = Err(err);
+                        continue;
+                    }
+                };
+                pass.outcomes.lock().unwrap()[index] = MoveOutcome::Pending;
+                *slot.relocation.lock().unwrap() = Some(Relocation {
+                    pass: pass.clone(),
+                    index,
+                    new_handle,
+                    applied: false,
+                    destroy: false,
+                });
+                pass.slots.lock().unwrap().push(Arc::downgrade(&slot));
+            }
+            // Resources may be dropped again while their moves are pending.
+            drop(slots.take());
+            let _ = published.set(pass.clone());
+
+            let outcomes = pass.wait();
+            for (mv, outcome) in moves.iter_mut().zip(outcomes) {
+                match outcome {
+                    MoveOutcome::Applied => moved += 1,
+                    MoveOutcome::Ignored => mv.operation = MOVE_IGNORE,
+                    MoveOutcome::Destroyed => mv.operation = MOVE_DESTROY,
+                    MoveOutcome::Pending => unreachable!(),
+                }
+            }
+        });
+    }
+    let stats = context.end();
+    result?;
+    tracing::debug!(
+        moved,
+        bytes_moved = stats.bytesMoved,
+        bytes_freed = stats.bytesFreed,
+        "Defragmentation pass completed"
+    );
+    Ok(moved)
+}
+
+/// Borrow an allocation of a defragmentation move as a [`vk_mem::Allocation`].
+///
+/// vk_mem has no constructor for an allocation handed out by a defragmentation pass, but
+/// [`vk_mem::Allocation`] only wraps the `VmaAllocation` handle. The layout is checked at
+/// compile time so that a vk_mem update changing it fails to build.
+unsafe fn borrow_allocation(raw: &vk_mem::ffi::VmaAllocation) -> &vk_mem::Allocation {
+    const _: () = {
+        assert!(
+            std::mem::size_of::<vk_mem::Allocation>()
+                == std::mem::size_of::<vk_mem::ffi::VmaAllocation>()
+        );
+        assert!(
+            std::mem::align_of::<vk_mem::Allocation>()
+                == std::mem::align_of::<vk_mem::ffi::VmaAllocation>()
+        );
+    };
+    &*(raw as *const vk_mem::ffi::VmaAllocation).cast::<vk_mem::Allocation>()
+}
+
+unsafe fn create_relocated(
+    device: &Device,
+    allocator: &Allocator,
+    dst: &vk_mem::Allocation,
+    kind: &RelocationKind,
+) -> VkResult<u64> {
+    match kind {
+        RelocationKind::Buffer {
+            info,
+            queue_family_indices,
+        } => {
+            let info = vk::BufferCreateInfo {
+                queue_family_index_count: queue_family_indices.len() as u32,
+                p_queue_family_indices: queue_family_indices.as_ptr(),
+                ..*info
+            };
+            let buffer = device.create_buffer(&info, None)?;
+            if let Err(err) = allocator.bind_buffer_memory(dst, buffer) {
+                device.destroy_buffer(buffer, None);
+                return Err(err);
+            }
+            Ok(buffer.as_raw())
+        }
+        RelocationKind::Image { info, .. } => {
+            let image = device.create_image(info, None)?;
+            if let Err(err) = allocator.bind_image_memory(dst, image) {
+                device.destroy_image(image, None);
+                return Err(err);
+            }
+            Ok(image.as_raw())
+        }
+    }
+}
+
+fn destroy_handle(device: &Device, kind: &RelocationKind, handle: u64) {
+    unsafe {
+        match kind {
+            RelocationKind::Buffer { .. } => {
+                device.destroy_buffer(vk::Buffer::from_raw(handle), None);
+            }
+            RelocationKind::Image { .. } => {
+                device.destroy_image(vk::Image::from_raw(handle), None);
+            }
+        }
+    }
+}
+
+fn defragmentation_system(mut defragmenter: ResMut<Defragmenter>, mut frame_index: Local<u32>) {
+    *frame_index = frame_index.wrapping_add(1);
+    if let Some(in_flight) = defragmenter.in_flight.as_ref() {
+        if in_flight.worker.is_finished() {
+            let in_flight = defragmenter.in_flight.take().unwrap();
+            match in_flight.worker.join() {
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => tracing::warn!(?err, "Defragmentation pass failed"),
+                Err(_) => tracing::error!("Defragmentation thread panicked"),
+            }
+        } else if frame_index.wrapping_sub(in_flight.started_frame) >= defragmenter.interval {
+            // Owners had enough time to apply their moves.
+            if let Some(pass) = in_flight.pass.get() {
+                pass.withdraw();
+            }
+        }
+        return;
+    }
+    if !defragmenter.enabled || defragmenter.interval == 0 {
+        return;
+    }
+    if *frame_index % defragmenter.interval != 0 {
+        return;
+    }
+    defragmenter.start_pass(*frame_index);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pass(len: usize) -> Arc<RelocationPass> {
+        Arc::new(RelocationPass {
+            outcomes: Mutex::new(vec![MoveOutcome::Pending; len]),
+            resolved: Condvar::new(),
+            slots: Mutex::new(Vec::new()),
+        })
+    }
+
+    fn relocation(pass: &Arc<RelocationPass>, index: usize) -> Option<Relocation> {
+        Some(Relocation {
+            pass: pass.clone(),
+            index,
+            new_handle: 100 + index as u64,
+            applied: false,
+            destroy: false,
+        })
+    }
+
+    #[test]
+    fn test_apply_and_retire() {
+        let pass = pass(1);
+        let mut r = relocation(&pass, 0);
+        assert_eq!(Relocation::apply(&mut r), Some(100));
+        // A move is only applied once, and can no longer be withdrawn.
+        assert_eq!(Relocation::apply(&mut r), None);
+        assert_eq!(Relocation::withdraw(&mut r), None);
+        assert_eq!(pass.outcomes.lock().unwrap()[0], MoveOutcome::Pending);
+
+        Relocation::retire(&mut r);
+        assert!(r.is_none());
+        assert_eq!(pass.wait(), vec![MoveOutcome::Applied]);
+    }
+
+    #[test]
+    fn test_release_after_apply() {
+        let pass = pass(1);
+        let mut r = relocation(&pass, 0);
+        Relocation::apply(&mut r);
+        // The new handle is in use, so it is destroyed by the owner.
+        assert_eq!(Relocation::release(&mut r), None);
+        assert_eq!(pass.outcomes.lock().unwrap()[0], MoveOutcome::Pending);
+
+        Relocation::retire(&mut r);
+        assert_eq!(pass.wait(), vec![MoveOutcome::Destroyed]);
+    }
+
+    #[test]
+    fn test_release_and_withdraw_pending() {
+        let pass = pass(3);
+        let mut released = relocation(&pass, 0);
+        let mut withdrawn = relocation(&pass, 1);
+        let mut untouched: Option<Relocation> = None;
+
+        assert_eq!(Relocation::release(&mut released), Some(100));
+        assert_eq!(Relocation::withdraw(&mut withdrawn), Some(101));
+        assert_eq!(Relocation::release(&mut untouched), None);
+        assert!(released.is_none() && withdrawn.is_none());
+        // Resolving twice is impossible once the relocation was taken.
+        assert_eq!(Relocation::withdraw(&mut released), None);
+        Relocation::retire(&mut withdrawn);
+
+        pass.resolve(2, MoveOutcome::Ignored);
+        assert_eq!(
+            pass.wait(),
+            vec![
+                MoveOutcome::Destroyed,
+                MoveOutcome::Ignored,
+                MoveOutcome::Ignored
+            ]
+        );
+    }
+
+    #[test]
+    fn test_wait_handoff() {
+        let pass = pass(2);
+        let mut first = relocation(&pass, 0);
+        let mut second = relocation(&pass, 1);
+        let waiter = {
+            let pass = pass.clone();
+            std::thread::spawn(move || pass.wait())
+        };
+        Relocation::apply(&mut first);
+        Relocation::retire(&mut first);
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        assert!(!waiter.is_finished());
+
+        Relocation::withdraw(&mut second);
+        assert_eq!(
+            waiter.join().unwrap(),
+            vec![MoveOutcome::Applied, MoveOutcome::Ignored]
+        );
+    }
+}
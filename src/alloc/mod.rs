@@ -5,6 +5,14 @@ use bevy::ecs::{system::Resource, world::FromWorld};
 
 use crate::{Device, HasDevice};
 
+mod budget;
+mod defrag;
+mod pool;
+pub use budget::{MemoryBudgetPlugin, MemoryPressure};
+pub use defrag::{DefragmentationError, DefragmentationPlugin, Defragmenter, RetiredRelocation};
+pub(crate) use defrag::{RelocationKind, RelocationSlot};
+pub use pool::{AllocatorPool, AllocatorPoolAlgorithm, AllocatorPoolCreateInfo};
+
 #[derive(Resource, Clone)]
 pub struct Allocator(Arc<AllocatorInner>);
 struct AllocatorInner {
@@ -41,6 +49,9 @@ impl Allocator {
         if buffer_device_address_enabled {
             info.flags |= vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS;
         }
+        if device.has_extension_named(vk::EXT_MEMORY_BUDGET_NAME) {
+            info.flags |= vk_mem::AllocatorCreateFlags::EXT_MEMORY_BUDGET;
+        }
         let alloc = unsafe { vk_mem::Allocator::new(info)? };
         Ok(Self(Arc::new(AllocatorInner {
             device,
@@ -0,0 +1,130 @@
+use std::{ffi::CString, sync::Arc};
+
+use ash::{prelude::VkResult, vk};
+
+use super::Allocator;
+use crate::{Device, HasDevice};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AllocatorPoolAlgorithm {
+    /// The default general purpose TLSF algorithm.
+    #[default]
+    Default,
+    /// Allocations are placed one after another. Suitable for stack-like or free-at-once
+    /// allocation patterns, such as transient per-frame resources.
+    Linear,
+    /// Linear allocator on a single block, with allocations freed in FIFO order.
+    /// Suitable for streaming data with a bounded lifetime.
+    Ring,
+}
+
+#[derive(Clone, Debug)]
+pub struct AllocatorPoolCreateInfo {
+    pub algorithm: AllocatorPoolAlgorithm,
+    /// Size of each memory block. 0 lets vk_mem choose the block size.
+    pub block_size: vk::DeviceSize,
+    pub min_block_count: usize,
+    /// 0 means unlimited. Ignored for [`AllocatorPoolAlgorithm::Ring`], which always uses one block.
+    pub max_block_count: usize,
+    /// Memory types that the pool may be placed on.
+    pub memory_type_bits: u32,
+    pub required_flags: vk::MemoryPropertyFlags,
+    pub preferred_flags: vk::MemoryPropertyFlags,
+}
+impl Default for AllocatorPoolCreateInfo {
+    fn default() -> Self {
+        Self {
+            algorithm: AllocatorPoolAlgorithm::Default,
+            block_size: 0,
+            min_block_count: 0,
+            max_block_count: 0,
+            memory_type_bits: u32::MAX,
+            required_flags: vk::MemoryPropertyFlags::empty(),
+            preferred_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        }
+    }
+}
+
+/// A named vk_mem pool with its own memory blocks.
+///
+/// Resources with different lifetimes, for example transient per-frame allocations, streaming
+/// textures and long-lived meshes, can be placed in separate pools so that they don't fragment each other.
+/// Resources created in a pool keep the pool alive.
+#[derive(Clone)]
+pub struct AllocatorPool(Arc<AllocatorPoolInner>);
+struct AllocatorPoolInner {
+    // Must be dropped before the allocator.
+    pool: vk_mem::AllocatorPool,
+    allocator: Allocator,
+    name: CString,
+    memory_type_index: u32,
+    algorithm: AllocatorPoolAlgorithm,
+}
+unsafe impl Send for AllocatorPoolInner {}
+unsafe impl Sync for AllocatorPoolInner {}
+
+impl AllocatorPool {
+    pub fn new(allocator: Allocator, name: &str, info: &AllocatorPoolCreateInfo) -> VkResult<Self> {
+        let memory_type_index = allocator.find_memory_type_index(
+            info.memory_type_bits,
+            &vk_mem::AllocationCreateInfo {
+                usage: vk_mem::MemoryUsage::Unknown,
+                required_flags: info.required_flags,
+                preferred_flags: info.preferred_flags,
+                ..Default::default()
+            },
+        )?;
+        let (flags, max_block_count) = match info.algorithm {
+            AllocatorPoolAlgorithm::Default => (
+                vk_mem::AllocatorPoolCreateFlags::empty(),
+                info.max_block_count,
+            ),
+            AllocatorPoolAlgorithm::Linear => (
+                vk_mem::AllocatorPoolCreateFlags::LINEAR_ALGORITHM,
+                info.max_block_count,
+            ),
+            AllocatorPoolAlgorithm::Ring => (vk_mem::AllocatorPoolCreateFlags::LINEAR_ALGORITHM, 1),
+        };
+        let pool = allocator.create_pool(&vk_mem::PoolCreateInfo {
+            memory_type_index,
+            flags,
+            block_size: info.block_size,
+            min_block_count: info.min_block_count,
+            max_block_count,
+            ..Default::default()
+        })?;
+        let name = CString::new(name).unwrap();
+        pool.set_name(Some(&name));
+        Ok(Self(Arc::new(AllocatorPoolInner {
+            pool,
+            allocator,
+            name,
+            memory_type_index,
+            algorithm: info.algorithm,
+        })))
+    }
+    pub fn name(&self) -> &std::ffi::CStr {
+        &self.0.name
+    }
+    pub fn memory_type_index(&self) -> u32 {
+        self.0.memory_type_index
+    }
+    pub fn algorithm(&self) -> AllocatorPoolAlgorithm {
+        self.0.algorithm
+    }
+    pub fn allocator(&self) -> &Allocator {
+        &self.0.allocator
+    }
+    pub fn statistics(&self) -> VkResult<vk_mem::ffi::VmaStatistics> {
+        self.0.pool.get_statistics()
+    }
+    pub(crate) fn raw(&self) -> &vk_mem::AllocatorPool {
+        &self.0.pool
+    }
+}
+
+impl HasDevice for AllocatorPool {
+    fn device(&self) -> &Device {
+        self.0.allocator.device()
+    }
+}
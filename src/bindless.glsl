@@ -0,0 +1,53 @@
+// Declarations for the descriptor set of the BindlessHeap.
+// Include with `#include <embedded://rhyolite/bindless.glsl>`.
+// Define BINDLESS_SET before including this file if the heap isn't bound to set 0.
+// The features required by this file are checked by the BindlessPlugin, which only creates the
+// heap when all of them are supported.
+#ifndef RHYOLITE_BINDLESS_GLSL
+#define RHYOLITE_BINDLESS_GLSL
+
+#extension GL_EXT_nonuniform_qualifier : require
+#extension GL_EXT_shader_image_load_formatted : require
+
+#ifndef BINDLESS_SET
+#define BINDLESS_SET 0
+#endif
+
+#define BINDLESS_SAMPLED_IMAGE_BINDING 0
+#define BINDLESS_STORAGE_IMAGE_BINDING 1
+#define BINDLESS_SAMPLER_BINDING 2
+#define BINDLESS_STORAGE_BUFFER_BINDING 3
+
+// Sampled images of all dimensionalities share the same binding.
+layout(set = BINDLESS_SET, binding = BINDLESS_SAMPLED_IMAGE_BINDING) uniform texture1D bindless_textures_1d[];
+layout(set = BINDLESS_SET, binding = BINDLESS_SAMPLED_IMAGE_BINDING) uniform texture2D bindless_textures_2d[];
+layout(set = BINDLESS_SET, binding = BINDLESS_SAMPLED_IMAGE_BINDING) uniform texture2DArray bindless_textures_2d_array[];
+layout(set = BINDLESS_SET, binding = BINDLESS_SAMPLED_IMAGE_BINDING) uniform textureCube bindless_textures_cube[];
+layout(set = BINDLESS_SET, binding = BINDLESS_SAMPLED_IMAGE_BINDING) uniform textureCubeArray bindless_textures_cube_array[];
+layout(set = BINDLESS_SET, binding = BINDLESS_SAMPLED_IMAGE_BINDING) uniform texture3D bindless_textures_3d[];
+
+// Storage images are declared without a format, which requires the
+// shaderStorageImageReadWithoutFormat and shaderStorageImageWriteWithoutFormat features.
+layout(set = BINDLESS_SET, binding = BINDLESS_STORAGE_IMAGE_BINDING) uniform image2D bindless_images_2d[];
+layout(set = BINDLESS_SET, binding = BINDLESS_STORAGE_IMAGE_BINDING) uniform image2DArray bindless_images_2d_array[];
+layout(set = BINDLESS_SET, binding = BINDLESS_STORAGE_IMAGE_BINDING) uniform image3D bindless_images_3d[];
+
+layout(set = BINDLESS_SET, binding = BINDLESS_SAMPLER_BINDING) uniform sampler bindless_samplers[];
+
+layout(set = BINDLESS_SET, binding = BINDLESS_STORAGE_BUFFER_BINDING) buffer BindlessStorageBuffer {
+    uint data[];
+} bindless_buffers[];
+
+// Indices may be non-uniform across invocations.
+#define bindless_texture_2d(index) bindless_textures_2d[nonuniformEXT(index)]
+#define bindless_texture_cube(index) bindless_textures_cube[nonuniformEXT(index)]
+#define bindless_texture_3d(index) bindless_textures_3d[nonuniformEXT(index)]
+#define bindless_image_2d(index) bindless_images_2d[nonuniformEXT(index)]
+#define bindless_sampler(index) bindless_samplers[nonuniformEXT(index)]
+#define bindless_buffer(index) bindless_buffers[nonuniformEXT(index)].data
+
+#define bindless_sampler_2d(texture, sampler) sampler2D(bindless_texture_2d(texture), bindless_sampler(sampler))
+#define bindless_sampler_cube(texture, sampler) samplerCube(bindless_texture_cube(texture), bindless_sampler(sampler))
+#define bindless_sampler_3d(texture, sampler) sampler3D(bindless_texture_3d(texture), bindless_sampler(sampler))
+
+#endif
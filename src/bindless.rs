@@ -0,0 +1,437 @@
+use std::sync::Arc;
+
+use ash::{ext, prelude::VkResult, vk};
+use bevy::{
+    app::{App, Plugin, PostUpdate},
+    ecs::system::{ResMut, Resource},
+};
+
+use crate::{
+    buffer::BufferLike,
+    ecs::{IntoRenderSystem, RenderSystemCtx},
+    future::{gpu_future, GPUFutureBlock},
+    pipeline::DescriptorSetLayout,
+    selectors::Graphics,
+    Device, HasDevice, ImageViewLike, RhyoliteApp, Sampler,
+};
+
+/// Creates the [`BindlessHeap`].
+///
+/// Requires the `descriptorIndexing` features for update-after-bind, partially bound and
+/// non-uniformly indexed descriptors, and the `shaderStorageImage{Read,Write}WithoutFormat`
+/// features. The heap is not created when the device lacks any of them, so systems should access it
+/// with `Option<Res<BindlessHeap>>`. Shaders may access the heap by including
+/// `<embedded://rhyolite/bindless.glsl>`.
+pub struct BindlessPlugin {
+    /// Size of the sampled image array. Clamped to the device limits.
+    pub sampled_images: u32,
+    /// Size of the storage image array. Clamped to the device limits.
+    pub storage_images: u32,
+    /// Size of the sampler array. Clamped to the device limits.
+    pub samplers: u32,
+    /// Size of the storage buffer array. Clamped to the device limits.
+    pub storage_buffers: u32,
+}
+
+impl Default for BindlessPlugin {
+    fn default() -> Self {
+        Self {
+            sampled_images: 65536,
+            storage_images: 8192,
+            samplers: 1024,
+            storage_buffers: 8192,
+        }
+    }
+}
+
+#[derive(Resource)]
+struct BindlessSupported;
+
+impl Plugin for BindlessPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_device_extension::<ext::descriptor_indexing::Meta>()
+            .ok();
+        // Every feature the heap and `bindless.glsl` rely on, including non-uniform indexing of
+        // all arrays and storage images declared without a format. Without them, the heap is not
+        // created.
+        let supported = app
+            .enable_feature::<vk::PhysicalDeviceDescriptorIndexingFeatures>(|f| {
+                &mut f.runtime_descriptor_array
+            })
+            .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceDescriptorIndexingFeatures>(|f| {
+                    &mut f.descriptor_binding_partially_bound
+                })
+                .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceDescriptorIndexingFeatures>(|f| {
+                    &mut f.descriptor_binding_update_unused_while_pending
+                })
+                .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceDescriptorIndexingFeatures>(|f| {
+                    &mut f.descriptor_binding_sampled_image_update_after_bind
+                })
+                .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceDescriptorIndexingFeatures>(|f| {
+                    &mut f.descriptor_binding_storage_image_update_after_bind
+                })
+                .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceDescriptorIndexingFeatures>(|f| {
+                    &mut f.descriptor_binding_storage_buffer_update_after_bind
+                })
+                .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceDescriptorIndexingFeatures>(|f| {
+                    &mut f.shader_sampled_image_array_non_uniform_indexing
+                })
+                .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceDescriptorIndexingFeatures>(|f| {
+                    &mut f.shader_storage_image_array_non_uniform_indexing
+                })
+                .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceDescriptorIndexingFeatures>(|f| {
+                    &mut f.shader_storage_buffer_array_non_uniform_indexing
+                })
+                .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceFeatures>(|f| {
+                    &mut f.shader_storage_image_read_without_format
+                })
+                .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceFeatures>(|f| {
+                    &mut f.shader_storage_image_write_without_format
+                })
+                .exists();
+        if !supported {
+            tracing::warn!("Bindless descriptors are unsupported on this device");
+            return;
+        }
+
+        bevy::asset::embedded_asset!(app, "bindless.glsl");
+        app.insert_resource(BindlessSupported);
+        app.add_systems(
+            PostUpdate,
+            recycle_bindless_slots.into_render_system::<Graphics>(),
+        );
+    }
+    fn finish(&self, app: &mut App) {
+        if !app.world().contains_resource::<BindlessSupported>() {
+            return;
+        }
+        let device = app.world().resource::<Device>().clone();
+        let heap = BindlessHeap::new(
+            device,
+            [
+                self.sampled_images,
+                self.storage_images,
+                self.samplers,
+                self.storage_buffers,
+            ],
+        );
+        match heap {
+            Ok(heap) => {
+                app.insert_resource(heap);
+            }
+            Err(err) => tracing::error!(?err, "Failed to create the bindless heap"),
+        }
+    }
+}
+
+/// The kinds of resources in the [`BindlessHeap`]. Each kind has its own array of descriptors,
+/// and indices are only unique within the same kind.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum BindlessResourceType {
+    SampledImage = 0,
+    StorageImage = 1,
+    Sampler = 2,
+    StorageBuffer = 3,
+}
+
+impl BindlessResourceType {
+    const ALL: [Self; 4] = [
+        Self::SampledImage,
+        Self::StorageImage,
+        Self::Sampler,
+        Self::StorageBuffer,
+    ];
+    /// The binding of the descriptor array in the heap's descriptor set.
+    pub fn binding(self) -> u32 {
+        self as u32
+    }
+    pub fn descriptor_type(self) -> vk::DescriptorType {
+        match self {
+            Self::SampledImage => vk::DescriptorType::SAMPLED_IMAGE,
+            Self::StorageImage => vk::DescriptorType::STORAGE_IMAGE,
+            Self::Sampler => vk::DescriptorType::SAMPLER,
+            Self::StorageBuffer => vk::DescriptorType::STORAGE_BUFFER,
+        }
+    }
+}
+
+#[derive(Default)]
+struct SlotAllocator {
+    capacity: u32,
+    /// Slots below this index have been handed out at least once.
+    next: u32,
+    free: Vec<u32>,
+}
+
+impl SlotAllocator {
+    fn allocate(&mut self) -> Option<u32> {
+        if let Some(index) = self.free.pop() {
+            return Some(index);
+        }
+        if self.next == self.capacity {
+            return None;
+        }
+        self.next += 1;
+        Some(self.next - 1)
+    }
+}
+
+/// One large descriptor set with arrays of sampled images, storage images, samplers and storage
+/// buffers, addressed in shaders by the `u32` index returned when the resource was added.
+///
+/// The descriptors are `UPDATE_AFTER_BIND`, so resources may be added while command buffers
+/// using the heap are pending. Removed slots are recycled once the frames which may have
+/// accessed them have completed on the GPU.
+///
+/// The heap does not own the resources. They must outlive their slot, which means they should
+/// be kept alive for a few frames after being removed from the heap.
+#[derive(Resource)]
+pub struct BindlessHeap {
+    device: Device,
+    layout: Arc<DescriptorSetLayout>,
+    pool: vk::DescriptorPool,
+    set: vk::DescriptorSet,
+    slots: [SlotAllocator; 4],
+    /// Slots removed since the last frame.
+    released: Vec<(BindlessResourceType, u32)>,
+}
+
+impl Drop for BindlessHeap {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_descriptor_pool(self.pool, None);
+        }
+    }
+}
+
+impl HasDevice for BindlessHeap {
+    fn device(&self) -> &Device {
+        &self.device
+    }
+}
+
+impl BindlessHeap {
+    /// `capacities` are indexed by [`BindlessResourceType`].
+    fn new(device: Device, mut capacities: [u32; 4]) -> VkResult<Self> {
+        let properties = device
+            .physical_device()
+            .properties()
+            .get::<vk::PhysicalDeviceDescriptorIndexingProperties>();
+        let limits = [
+            properties
+                .max_descriptor_set_update_after_bind_sampled_images
+                .min(properties.max_per_stage_descriptor_update_after_bind_sampled_images),
+            properties
+                .max_descriptor_set_update_after_bind_storage_images
+                .min(properties.max_per_stage_descriptor_update_after_bind_storage_images),
+            properties
+                .max_descriptor_set_update_after_bind_samplers
+                .min(properties.max_per_stage_descriptor_update_after_bind_samplers),
+            properties
+                .max_descriptor_set_update_after_bind_storage_buffers
+                .min(properties.max_per_stage_descriptor_update_after_bind_storage_buffers),
+        ];
+        for (capacity, limit) in capacities.iter_mut().zip(limits) {
+            *capacity = (*capacity).min(limit);
+        }
+
+        let bindings = BindlessResourceType::ALL.map(|ty| vk::DescriptorSetLayoutBinding {
+            binding: ty.binding(),
+            descriptor_type: ty.descriptor_type(),
+            descriptor_count: capacities[ty as usize],
+            stage_flags: vk::ShaderStageFlags::ALL,
+            ..Default::default()
+        });
+        let binding_flags = [vk::DescriptorBindingFlags::PARTIALLY_BOUND
+            | vk::DescriptorBindingFlags::UPDATE_AFTER_BIND
+            | vk::DescriptorBindingFlags::UPDATE_UNUSED_WHILE_PENDING;
+            4];
+        let layout = Arc::new(DescriptorSetLayout::new_with_binding_flags(
+            device.clone(),
+            &bindings,
+            &binding_flags,
+            vk::DescriptorSetLayoutCreateFlags::UPDATE_AFTER_BIND_POOL,
+        )?);
+
+        let pool_sizes = BindlessResourceType::ALL.map(|ty| vk::DescriptorPoolSize {
+            ty: ty.descriptor_type(),
+            descriptor_count: capacities[ty as usize],
+        });
+        let pool = unsafe {
+            device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .flags(vk::DescriptorPoolCreateFlags::UPDATE_AFTER_BIND)
+                    .max_sets(1)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )
+        }?;
+        let set = unsafe {
+            device.allocate_descriptor_sets(
+                &vk::DescriptorSetAllocateInfo::default()
+                    .descriptor_pool(pool)
+                    .set_layouts(&[layout.raw()]),
+            )
+        };
+        let set = match set {
+            Ok(sets) => sets[0],
+            Err(err) => {
+                unsafe { device.destroy_descriptor_pool(pool, None) };
+                return Err(err);
+            }
+        };
+        Ok(Self {
+            device,
+            layout,
+            pool,
+            set,
+            slots: capacities.map(|capacity| SlotAllocator {
+                capacity,
+                ..Default::default()
+            }),
+            released: Vec::new(),
+        })
+    }
+
+    /// The layout of the heap's descriptor set, to be included in pipeline layouts.
+    pub fn layout(&self) -> &Arc<DescriptorSetLayout> {
+        &self.layout
+    }
+    pub fn raw_descriptor_set(&self) -> vk::DescriptorSet {
+        self.set
+    }
+    /// Number of descriptors available for the resource type.
+    pub fn capacity(&self, ty: BindlessResourceType) -> u32 {
+        self.slots[ty as usize].capacity
+    }
+
+    /// Returns None if the heap is full.
+    pub fn add_sampled_image(
+        &mut self,
+        view: &impl ImageViewLike,
+        layout: vk::ImageLayout,
+    ) -> Option<u32> {
+        let index = self.slots[BindlessResourceType::SampledImage as usize].allocate()?;
+        self.write_image(
+            BindlessResourceType::SampledImage,
+            index,
+            vk::DescriptorImageInfo {
+                image_view: view.raw_image_view(),
+                image_layout: layout,
+                ..Default::default()
+            },
+        );
+        Some(index)
+    }
+
+    /// The image must be in the `GENERAL` layout when accessed. Returns None if the heap is full.
+    pub fn add_storage_image(&mut self, view: &impl ImageViewLike) -> Option<u32> {
+        let index = self.slots[BindlessResourceType::StorageImage as usize].allocate()?;
+        self.write_image(
+            BindlessResourceType::StorageImage,
+            index,
+            vk::DescriptorImageInfo {
+                image_view: view.raw_image_view(),
+                image_layout: vk::ImageLayout::GENERAL,
+                ..Default::default()
+            },
+        );
+        Some(index)
+    }
+
+    /// Returns None if the heap is full.
+    pub fn add_sampler(&mut self, sampler: &Sampler) -> Option<u32> {
+        let index = self.slots[BindlessResourceType::Sampler as usize].allocate()?;
+        self.write_image(
+            BindlessResourceType::Sampler,
+            index,
+            vk::DescriptorImageInfo {
+                sampler: sampler.raw(),
+                ..Default::default()
+            },
+        );
+        Some(index)
+    }
+
+    /// Returns None if the heap is full.
+    pub fn add_storage_buffer(&mut self, buffer: &impl BufferLike) -> Option<u32> {
+        let index = self.slots[BindlessResourceType::StorageBuffer as usize].allocate()?;
+        unsafe {
+            self.device.update_descriptor_sets(
+                &[vk::WriteDescriptorSet::default()
+                    .dst_set(self.set)
+                    .dst_binding(BindlessResourceType::StorageBuffer.binding())
+                    .dst_array_element(index)
+                    .descriptor_type(vk::DescriptorType::STORAGE_BUFFER)
+                    .buffer_info(&[vk::DescriptorBufferInfo {
+                        buffer: buffer.raw_buffer(),
+                        offset: buffer.offset(),
+                        range: buffer.size(),
+                    }])],
+                &[],
+            );
+        }
+        Some(index)
+    }
+
+    /// Release the slot. The index will not be reused until the GPU has finished
+    /// all frames submitted so far.
+    pub fn remove(&mut self, ty: BindlessResourceType, index: u32) {
+        debug_assert!(index < self.slots[ty as usize].next);
+        self.released.push((ty, index));
+    }
+
+    fn write_image(&mut self, ty: BindlessResourceType, index: u32, info: vk::DescriptorImageInfo) {
+        unsafe {
+            self.device.update_descriptor_sets(
+                &[vk::WriteDescriptorSet::default()
+                    .dst_set(self.set)
+                    .dst_binding(ty.binding())
+                    .dst_array_element(index)
+                    .descriptor_type(ty.descriptor_type())
+                    .image_info(&[info])],
+                &[],
+            );
+        }
+    }
+}
+
+/// Slots released in a frame are returned by this render system, and handed back to it
+/// once that frame has completed on the GPU.
+fn recycle_bindless_slots<'w, 's>(
+    mut ctx: RenderSystemCtx<Vec<(BindlessResourceType, u32)>>,
+    heap: Option<ResMut<'w, BindlessHeap>>,
+) -> impl GPUFutureBlock<Returned = Vec<(BindlessResourceType, u32)>> + use<'w, 's> {
+    // The heap is missing if it failed to be created.
+    let released = heap.map(|mut heap| {
+        if let Some(completed) = ctx.take() {
+            for (ty, index) in completed {
+                heap.slots[ty as usize].free.push(index);
+            }
+        }
+        std::mem::take(&mut heap.released)
+    });
+    gpu_future! { move
+        released.unwrap_or_default()
+    }
+}
@@ -0,0 +1,288 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+use ash::{prelude::VkResult, vk};
+
+use super::{Buffer, BufferLike};
+use crate::{ecs::FRAMES_IN_FLIGHT, Allocator, HasDevice};
+
+/// Best-fit free list allocator over a linear address range.
+///
+/// This only does the bookkeeping. It never touches any memory, so it can be used without a device.
+#[derive(Debug)]
+pub(crate) struct FreeList {
+    size: u64,
+    /// Free ranges keyed by their offsets. Adjacent free ranges are always coalesced.
+    free: BTreeMap<u64, u64>,
+}
+
+impl FreeList {
+    pub fn new(size: u64) -> Self {
+        let mut free = BTreeMap::new();
+        if size > 0 {
+            free.insert(0, size);
+        }
+        Self { size, free }
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    pub fn free_size(&self) -> u64 {
+        self.free.values().sum()
+    }
+
+    /// Returns the offset of the allocated range, or None if no free range is large enough.
+    /// `alignment` must be a power of two.
+    pub fn allocate(&mut self, size: u64, alignment: u64) -> Option<u64> {
+        assert!(alignment.is_power_of_two());
+        if size == 0 {
+            return None;
+        }
+        let mut best: Option<(u64, u64, u64)> = None;
+        for (&offset, &free_size) in self.free.iter() {
+            let aligned_offset = offset.next_multiple_of(alignment);
+            let padding = aligned_offset - offset;
+            if free_size < padding + size {
+                continue;
+            }
+            if best.is_none_or(|(_, _, best_size)| free_size < best_size) {
+                best = Some((offset, aligned_offset, free_size));
+            }
+        }
+        let (offset, aligned_offset, free_size) = best?;
+        self.free.remove(&offset);
+        if aligned_offset > offset {
+            self.free.insert(offset, aligned_offset - offset);
+        }
+        let end = aligned_offset + size;
+        let free_end = offset + free_size;
+        if free_end > end {
+            self.free.insert(end, free_end - end);
+        }
+        Some(aligned_offset)
+    }
+
+    /// Return a range previously returned by [`FreeList::allocate`].
+    pub fn free(&mut self, mut offset: u64, mut size: u64) {
+        debug_assert!(offset + size <= self.size);
+        if let Some((&prev_offset, &prev_size)) = self.free.range(..offset).next_back() {
+            debug_assert!(prev_offset + prev_size <= offset, "double free");
+            if prev_offset + prev_size == offset {
+                self.free.remove(&prev_offset);
+                offset = prev_offset;
+                size += prev_size;
+            }
+        }
+        if let Some(&next_size) = self.free.get(&(offset + size)) {
+            self.free.remove(&(offset + size));
+            size += next_size;
+        }
+        self.free.insert(offset, size);
+    }
+}
+
+/// Ranges freed during the last few frames, waiting for the GPU to finish using them.
+#[derive(Debug, Default)]
+struct PendingFrees {
+    frames: VecDeque<Vec<(u64, u64)>>,
+}
+
+impl PendingFrees {
+    /// Start a new frame with the ranges freed since the last call, and return the ranges
+    /// freed [`FRAMES_IN_FLIGHT`] frames ago to `free_list`.
+    fn advance(&mut self, freed: Vec<(u64, u64)>, free_list: &mut FreeList) {
+        self.frames.push_back(freed);
+        while self.frames.len() > FRAMES_IN_FLIGHT {
+            for (offset, size) in self.frames.pop_front().unwrap() {
+                free_list.free(offset, size);
+            }
+        }
+    }
+}
+
+struct BufferArenaShared {
+    buffer: Buffer,
+    /// Ranges of slices dropped during the current frame.
+    freed: Mutex<Vec<(u64, u64)>>,
+}
+
+/// Sub-allocates many small buffers from a single [`Buffer`].
+///
+/// Dropped [`BufferSlice`]s are retired with the frame they were dropped in, and their ranges
+/// only become available again once that frame's GPU work has completed. [`BufferArena::cleanup`]
+/// must be called once per frame for this to happen, so a slice may be dropped in the same frame
+/// as its last GPU use.
+pub struct BufferArena {
+    shared: Arc<BufferArenaShared>,
+    free_list: FreeList,
+    pending: PendingFrees,
+    alignment: vk::DeviceSize,
+}
+
+impl BufferArena {
+    /// Create an arena backed by a new buffer with DEVICE_LOCAL memory.
+    pub fn new_resource(
+        allocator: Allocator,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+    ) -> VkResult<Self> {
+        let buffer = Buffer::new_resource(allocator, size, alignment, usage)?;
+        Ok(Self::from_buffer(buffer, alignment))
+    }
+
+    /// Create an arena that sub-allocates from `buffer`. Each slice will be aligned to at least `alignment`.
+    pub fn from_buffer(buffer: Buffer, alignment: vk::DeviceSize) -> Self {
+        Self {
+            free_list: FreeList::new(buffer.size),
+            pending: PendingFrees::default(),
+            shared: Arc::new(BufferArenaShared {
+                buffer,
+                freed: Mutex::new(Vec::new()),
+            }),
+            alignment: alignment.max(1),
+        }
+    }
+
+    pub fn buffer(&self) -> &Buffer {
+        &self.shared.buffer
+    }
+
+    pub fn free_size(&self) -> vk::DeviceSize {
+        self.free_list.free_size()
+    }
+
+    /// Needs to be called once per frame, for example by a system in the First stage.
+    ///
+    /// Ranges of slices dropped since the last call are retired with the current frame. Ranges
+    /// retired [`FRAMES_IN_FLIGHT`] frames ago are returned to the free list.
+    pub fn cleanup(&mut self) {
+        let freed = std::mem::take(&mut *self.shared.freed.lock().unwrap());
+        self.pending.advance(freed, &mut self.free_list);
+    }
+
+    /// Returns None if the arena does not have a large enough free range.
+    pub fn allocate(&mut self, size: vk::DeviceSize) -> Option<BufferSlice> {
+        self.allocate_aligned(size, self.alignment)
+    }
+
+    pub fn allocate_aligned(
+        &mut self,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+    ) -> Option<BufferSlice> {
+        let offset = self
+            .free_list
+            .allocate(size, alignment.max(self.alignment))?;
+        Some(BufferSlice {
+            arena: self.shared.clone(),
+            offset,
+            size,
+        })
+    }
+}
+
+impl HasDevice for BufferArena {
+    fn device(&self) -> &crate::Device {
+        self.shared.buffer.device()
+    }
+}
+
+/// A range of a [`BufferArena`].
+pub struct BufferSlice {
+    arena: Arc<BufferArenaShared>,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+}
+
+impl Drop for BufferSlice {
+    fn drop(&mut self) {
+        self.arena
+            .freed
+            .lock()
+            .unwrap()
+            .push((self.offset, self.size));
+    }
+}
+
+impl BufferLike for BufferSlice {
+    fn raw_buffer(&self) -> vk::Buffer {
+        self.arena.buffer.raw_buffer()
+    }
+    fn offset(&self) -> vk::DeviceSize {
+        self.offset
+    }
+    fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+    fn device_address(&self) -> vk::DeviceAddress {
+        if self.arena.buffer.device_address == 0 {
+            panic!("Buffer was not created with SHADER_DEVICE_ADDRESS");
+        }
+        self.arena.buffer.device_address + self.offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FreeList, PendingFrees, FRAMES_IN_FLIGHT};
+
+    #[test]
+    fn test_allocate_and_free() {
+        let mut list = FreeList::new(1024);
+        let a = list.allocate(100, 1).unwrap();
+        let b = list.allocate(100, 1).unwrap();
+        assert_eq!(a, 0);
+        assert_eq!(b, 100);
+        assert_eq!(list.free_size(), 824);
+        list.free(a, 100);
+        list.free(b, 100);
+        assert_eq!(list.free_size(), 1024);
+        // Everything was coalesced back into one range
+        assert_eq!(list.allocate(1024, 1), Some(0));
+        assert_eq!(list.allocate(1, 1), None);
+    }
+
+    #[test]
+    fn test_alignment() {
+        let mut list = FreeList::new(1024);
+        assert_eq!(list.allocate(3, 1), Some(0));
+        assert_eq!(list.allocate(16, 256), Some(256));
+        // The padding before the aligned allocation remains usable.
+        assert_eq!(list.allocate(200, 1), Some(3));
+        assert_eq!(list.allocate(16, 16), Some(208));
+    }
+
+    #[test]
+    fn test_best_fit() {
+        let mut list = FreeList::new(1000);
+        let a = list.allocate(300, 1).unwrap();
+        let _b = list.allocate(100, 1).unwrap();
+        let c = list.allocate(50, 1).unwrap();
+        let _d = list.allocate(100, 1).unwrap();
+        list.free(a, 300);
+        list.free(c, 50);
+        // The 50 byte hole is a better fit than the 300 byte hole or the tail.
+        assert_eq!(list.allocate(40, 1), Some(c));
+        assert_eq!(list.allocate(250, 1), Some(a));
+    }
+
+    #[test]
+    fn test_pending_frees() {
+        let mut list = FreeList::new(1024);
+        let mut pending = PendingFrees::default();
+        let a = list.allocate(1024, 1).unwrap();
+        pending.advance(vec![(a, 1024)], &mut list);
+        for _ in 1..FRAMES_IN_FLIGHT {
+            pending.advance(Vec::new(), &mut list);
+            // The range might still be in use by the GPU.
+            assert_eq!(list.allocate(1, 1), None);
+        }
+        pending.advance(Vec::new(), &mut list);
+        assert_eq!(list.free_size(), 1024);
+    }
+}
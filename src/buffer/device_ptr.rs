@@ -0,0 +1,268 @@
+use std::{
+    fmt::Debug,
+    marker::PhantomData,
+    ops::{Deref, Range},
+};
+
+use ash::vk;
+use bytemuck::{Pod, Zeroable};
+
+use super::{Buffer, BufferLike, BufferVec, StagingBeltSuballocation};
+
+/// A typed device address of `T`, for use with `GL_EXT_buffer_reference`.
+///
+/// Has the same layout as a `uint64_t`, so it can be embedded in `#[repr(C)]` push constants
+/// and uniforms. It can only be obtained from buffers created with `SHADER_DEVICE_ADDRESS`,
+/// so it always points to memory accessible by the device.
+#[repr(transparent)]
+pub struct DevicePtr<T: Pod> {
+    addr: vk::DeviceAddress,
+    _marker: PhantomData<T>,
+}
+impl<T: Pod> Clone for DevicePtr<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T: Pod> Copy for DevicePtr<T> {}
+unsafe impl<T: Pod> Zeroable for DevicePtr<T> {}
+unsafe impl<T: Pod> Pod for DevicePtr<T> {}
+impl<T: Pod> PartialEq for DevicePtr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.addr == other.addr
+    }
+}
+impl<T: Pod> Eq for DevicePtr<T> {}
+impl<T: Pod> Debug for DevicePtr<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("DevicePtr")
+            .field(&format_args!("{:#x}", self.addr))
+            .finish()
+    }
+}
+
+impl<T: Pod> DevicePtr<T> {
+    /// # Safety
+    /// `addr` must be a device address of a `T` in a buffer accessible by the device.
+    pub unsafe fn from_raw(addr: vk::DeviceAddress) -> Self {
+        Self {
+            addr,
+            _marker: PhantomData,
+        }
+    }
+    pub fn raw(self) -> vk::DeviceAddress {
+        self.addr
+    }
+}
+
+/// A typed device address of `len` consecutive `T`s.
+///
+/// Has the same layout as `{ uint64_t ptr; uint64_t len; }` on the GPU.
+/// Indexing and sub-slicing is bounds checked in debug builds.
+#[repr(C)]
+pub struct DeviceSlice<T: Pod> {
+    ptr: DevicePtr<T>,
+    len: u64,
+}
+impl<T: Pod> Clone for DeviceSlice<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+impl<T: Pod> Copy for DeviceSlice<T> {}
+unsafe impl<T: Pod> Zeroable for DeviceSlice<T> {}
+unsafe impl<T: Pod> Pod for DeviceSlice<T> {}
+impl<T: Pod> Debug for DeviceSlice<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DeviceSlice")
+            .field("ptr", &self.ptr)
+            .field("len", &self.len)
+            .finish()
+    }
+}
+
+impl<T: Pod> DeviceSlice<T> {
+    /// # Safety
+    /// `ptr` must point to `len` consecutive `T`s in a buffer accessible by the device.
+    pub unsafe fn from_raw_parts(ptr: DevicePtr<T>, len: u64) -> Self {
+        Self { ptr, len }
+    }
+    /// Returns None if the buffer was not created with `SHADER_DEVICE_ADDRESS`.
+    fn from_device_address(
+        device_address: vk::DeviceAddress,
+        size: vk::DeviceSize,
+    ) -> Option<Self> {
+        if device_address == 0 {
+            return None;
+        }
+        debug_assert_eq!(
+            device_address % std::mem::align_of::<T>() as u64,
+            0,
+            "Misaligned device address"
+        );
+        Some(Self {
+            ptr: DevicePtr {
+                addr: device_address,
+                _marker: PhantomData,
+            },
+            len: size / std::mem::size_of::<T>() as u64,
+        })
+    }
+    pub fn as_ptr(self) -> DevicePtr<T> {
+        self.ptr
+    }
+    pub fn len(self) -> u64 {
+        self.len
+    }
+    pub fn is_empty(self) -> bool {
+        self.len == 0
+    }
+    pub fn get(self, index: u64) -> DevicePtr<T> {
+        debug_assert!(
+            index < self.len,
+            "index out of bounds: the len is {} but the index is {}",
+            self.len,
+            index
+        );
+        DevicePtr {
+            addr: self.ptr.addr + index * std::mem::size_of::<T>() as u64,
+            _marker: PhantomData,
+        }
+    }
+    pub fn slice(self, range: Range<u64>) -> Self {
+        debug_assert!(
+            range.start <= range.end && range.end <= self.len,
+            "range {:?} out of bounds for slice of length {}",
+            range,
+            self.len
+        );
+        Self {
+            ptr: DevicePtr {
+                addr: self.ptr.addr + range.start * std::mem::size_of::<T>() as u64,
+                _marker: PhantomData,
+            },
+            len: range.end - range.start,
+        }
+    }
+}
+
+impl Buffer {
+    /// Returns None if the buffer was not created with `SHADER_DEVICE_ADDRESS`.
+    ///
+    /// Like the other `device_slice` methods, this only depends on the buffer usage. The address
+    /// is valid regardless of the memory type, so host memory is read by the device over the bus.
+    pub fn device_slice<T: Pod>(&self) -> Option<DeviceSlice<T>> {
+        DeviceSlice::from_device_address(self.device_address, self.size)
+    }
+}
+
+/// A [`DeviceSlice`] borrowed from a container that may reallocate its buffer.
+///
+/// The device address becomes stale once the container grows, so the borrow keeps the container
+/// from being modified while the slice is in use.
+pub struct DeviceSliceRef<'a, T: Pod> {
+    slice: DeviceSlice<T>,
+    _marker: PhantomData<&'a ()>,
+}
+impl<T: Pod> Deref for DeviceSliceRef<'_, T> {
+    type Target = DeviceSlice<T>;
+    fn deref(&self) -> &Self::Target {
+        &self.slice
+    }
+}
+impl<T: Pod> Debug for DeviceSliceRef<'_, T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.slice.fmt(f)
+    }
+}
+
+impl<T: Pod> BufferVec<T> {
+    /// Returns a slice covering the initialized elements of the vector.
+    /// Returns None if the buffer was not created with `SHADER_DEVICE_ADDRESS`.
+    ///
+    /// The slice borrows the vector, because growing the vector moves it to a new buffer.
+    pub fn device_slice(&self) -> Option<DeviceSliceRef<'_, T>> {
+        let slice = DeviceSlice::from_device_address(
+            BufferLike::device_address(self),
+            (self.len() * std::mem::size_of::<T>()) as u64,
+        )?;
+        Some(DeviceSliceRef {
+            slice,
+            _marker: PhantomData,
+        })
+    }
+}
+
+impl<T: Pod> StagingBeltSuballocation<T> {
+    /// Returns None if the staging belt was not created with `SHADER_DEVICE_ADDRESS`.
+    /// Suballocations from [`UniformBelt`](super::UniformBelt) always have a device address.
+    pub fn device_ptr(&self) -> Option<DevicePtr<T>> {
+        DeviceSlice::from_device_address(self.raw_device_address(), self.size)
+            .map(DeviceSlice::as_ptr)
+    }
+}
+impl StagingBeltSuballocation<[u8]> {
+    /// Reinterpret the suballocation as a slice of `T`.
+    /// Returns None if the staging belt was not created with `SHADER_DEVICE_ADDRESS`.
+    pub fn device_slice<T: Pod>(&self) -> Option<DeviceSlice<T>> {
+        DeviceSlice::from_device_address(self.raw_device_address(), self.size)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DevicePtr, DeviceSlice};
+
+    fn slice(addr: u64, len: u64) -> DeviceSlice<u32> {
+        unsafe { DeviceSlice::from_raw_parts(DevicePtr::from_raw(addr), len) }
+    }
+
+    #[test]
+    fn test_get() {
+        let s = slice(0x1000, 4);
+        assert_eq!(s.get(0).raw(), 0x1000);
+        assert_eq!(s.get(3).raw(), 0x100c);
+    }
+
+    #[test]
+    fn test_slice() {
+        let s = slice(0x1000, 4).slice(1..3);
+        assert_eq!(s.as_ptr().raw(), 0x1004);
+        assert_eq!(s.len(), 2);
+        assert!(slice(0x1000, 4).slice(4..4).is_empty());
+    }
+
+    #[test]
+    fn test_from_device_address() {
+        assert!(DeviceSlice::<u32>::from_device_address(0, 16).is_none());
+        // Trailing bytes that do not fit a whole element are not part of the slice.
+        assert_eq!(
+            DeviceSlice::<u32>::from_device_address(0x1000, 18)
+                .unwrap()
+                .len(),
+            4
+        );
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "index out of bounds")]
+    fn test_get_out_of_bounds() {
+        slice(0x1000, 4).get(4);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "out of bounds")]
+    fn test_slice_out_of_bounds() {
+        slice(0x1000, 4).slice(2..5);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "out of bounds")]
+    fn test_slice_inverted_range() {
+        #[allow(clippy::reversed_empty_ranges)]
+        slice(0x1000, 4).slice(3..2);
+    }
+}
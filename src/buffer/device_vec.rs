@@ -0,0 +1,251 @@
+use std::{marker::PhantomData, ops::Range};
+
+use ash::{prelude::VkResult, vk};
+
+use super::{Buffer, BufferLike};
+use crate::Allocator;
+
+/// A growable array of `T` in DEVICE_LOCAL memory.
+///
+/// Unlike [`BufferVec`](super::BufferVec), the elements are never accessible on the host.
+/// The vector is grown on the GPU with [`reserve_device_vec`](crate::commands::reserve_device_vec)
+/// or [`resize_device_vec`](crate::commands::resize_device_vec), which copy the existing contents
+/// into the new buffer and keep the old buffer alive until the GPU has finished using it.
+pub struct DeviceVec<T> {
+    allocator: Allocator,
+    buffer: Option<Buffer>,
+    len: usize,
+    capacity: usize,
+    alignment: vk::DeviceSize,
+    usage: vk::BufferUsageFlags,
+    _marker: PhantomData<T>,
+}
+
+impl<T> DeviceVec<T> {
+    pub fn new(
+        allocator: Allocator,
+        alignment: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+    ) -> Self {
+        assert!(
+            std::mem::size_of::<T>() > 0,
+            "DeviceVec does not support zero-sized types"
+        );
+        Self {
+            allocator,
+            buffer: None,
+            len: 0,
+            capacity: 0,
+            alignment,
+            usage: usage | vk::BufferUsageFlags::TRANSFER_SRC | vk::BufferUsageFlags::TRANSFER_DST,
+            _marker: PhantomData,
+        }
+    }
+    /// Create a vector of `len` elements. The contents are undefined until written on the GPU.
+    pub fn with_len(
+        allocator: Allocator,
+        alignment: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        len: usize,
+    ) -> VkResult<Self> {
+        let mut this = Self::new(allocator, alignment, usage);
+        // Zero-sized buffers are invalid.
+        this.reallocate(len.max(1))?;
+        this.len = len;
+        Ok(this)
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+    /// Shortens the vector to `len` elements. Has no effect if the vector is already shorter.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+    pub fn buffer(&self) -> Option<&Buffer> {
+        self.buffer.as_ref()
+    }
+    /// The commands needed to make room for `additional` more elements, and to extend the
+    /// vector to `new_len` elements if given.
+    pub(crate) fn plan_growth(&self, additional: usize, new_len: Option<usize>) -> GrowPlan {
+        plan_growth(
+            self.len,
+            self.capacity,
+            additional,
+            new_len,
+            std::mem::size_of::<T>() as vk::DeviceSize,
+        )
+    }
+    /// Replace the buffer with a new buffer of `capacity` elements, without copying the
+    /// contents. Returns the old buffer, which must be kept alive until the GPU has finished
+    /// using it.
+    pub(crate) fn reallocate(&mut self, capacity: usize) -> VkResult<Option<Buffer>> {
+        debug_assert!(capacity > 0);
+        let buffer = Buffer::new_resource(
+            self.allocator.clone(),
+            (capacity * std::mem::size_of::<T>()) as vk::DeviceSize,
+            self.alignment,
+            self.usage,
+        )?;
+        self.capacity = capacity;
+        Ok(self.buffer.replace(buffer))
+    }
+    /// # Safety
+    /// `len` must not exceed the capacity, and elements `old_len..len` must be initialized on
+    /// the GPU before they are read.
+    pub(crate) unsafe fn set_len(&mut self, len: usize) {
+        debug_assert!(len <= self.capacity);
+        self.len = len;
+    }
+}
+
+impl<T> BufferLike for DeviceVec<T> {
+    fn raw_buffer(&self) -> vk::Buffer {
+        self.buffer
+            .as_ref()
+            .map_or(vk::Buffer::null(), BufferLike::raw_buffer)
+    }
+    fn size(&self) -> vk::DeviceSize {
+        self.buffer.as_ref().map_or(0, BufferLike::size)
+    }
+    fn device_address(&self) -> vk::DeviceAddress {
+        self.buffer.as_ref().map_or(0, BufferLike::device_address)
+    }
+}
+
+/// The commands recorded to grow a [`DeviceVec`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct GrowPlan {
+    /// The capacity of the new buffer. None if the elements fit into the current buffer.
+    pub reallocate: Option<usize>,
+    /// Number of bytes copied from the start of the old buffer into the new buffer.
+    pub copy_size: vk::DeviceSize,
+    /// Byte range zeroed for the newly added elements.
+    pub fill: Option<Range<vk::DeviceSize>>,
+    /// The length of the vector once grown.
+    pub len: usize,
+}
+
+fn plan_growth(
+    len: usize,
+    capacity: usize,
+    additional: usize,
+    new_len: Option<usize>,
+    item_size: vk::DeviceSize,
+) -> GrowPlan {
+    let required = len + additional;
+    // Grow geometrically, so that repeated pushes don't reallocate every time.
+    let reallocate = (required > capacity).then(|| required.max(capacity * 2));
+    let copy_size = if reallocate.is_some() {
+        len as vk::DeviceSize * item_size
+    } else {
+        0
+    };
+    let new_len = new_len.unwrap_or(len);
+    let fill = (new_len > len)
+        .then(|| len as vk::DeviceSize * item_size..new_len as vk::DeviceSize * item_size);
+    GrowPlan {
+        reallocate,
+        copy_size,
+        fill,
+        len: new_len,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reserve() {
+        // The first reservation allocates exactly what was asked for.
+        assert_eq!(
+            plan_growth(0, 0, 10, None, 4),
+            GrowPlan {
+                reallocate: Some(10),
+                copy_size: 0,
+                fill: None,
+                len: 0,
+            }
+        );
+        // Fits into the current buffer.
+        assert_eq!(
+            plan_growth(6, 10, 4, None, 4),
+            GrowPlan {
+                reallocate: None,
+                copy_size: 0,
+                fill: None,
+                len: 6,
+            }
+        );
+        // Capacity at least doubles, and the existing elements are copied.
+        assert_eq!(
+            plan_growth(6, 10, 5, None, 4),
+            GrowPlan {
+                reallocate: Some(20),
+                copy_size: 24,
+                fill: None,
+                len: 6,
+            }
+        );
+        // Reservations larger than double the capacity are honored.
+        assert_eq!(
+            plan_growth(10, 10, 40, None, 8),
+            GrowPlan {
+                reallocate: Some(50),
+                copy_size: 80,
+                fill: None,
+                len: 10,
+            }
+        );
+    }
+
+    #[test]
+    fn test_resize() {
+        // Growing within capacity zeroes the new elements only.
+        assert_eq!(
+            plan_growth(2, 8, 4, Some(6), 4),
+            GrowPlan {
+                reallocate: None,
+                copy_size: 0,
+                fill: Some(8..24),
+                len: 6,
+            }
+        );
+        // Growing past capacity copies the old elements and zeroes the new ones.
+        assert_eq!(
+            plan_growth(8, 8, 4, Some(12), 16),
+            GrowPlan {
+                reallocate: Some(16),
+                copy_size: 128,
+                fill: Some(128..192),
+                len: 12,
+            }
+        );
+        // Resizing an empty vector has nothing to copy.
+        assert_eq!(
+            plan_growth(0, 0, 3, Some(3), 4),
+            GrowPlan {
+                reallocate: Some(3),
+                copy_size: 0,
+                fill: Some(0..12),
+                len: 3,
+            }
+        );
+        // Resizing to the same length records nothing.
+        assert_eq!(
+            plan_growth(5, 5, 0, Some(5), 4),
+            GrowPlan {
+                reallocate: None,
+                copy_size: 0,
+                fill: None,
+                len: 5,
+            }
+        );
+    }
+}
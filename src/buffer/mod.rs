@@ -1,15 +1,26 @@
+mod arena;
+mod device_ptr;
+mod device_vec;
 pub(crate) mod staging;
 
 use std::{
     alloc::Layout,
-    cell::UnsafeCell,
+    cell::{Cell, UnsafeCell},
     ops::{Deref, DerefMut, RangeBounds},
     ptr::NonNull,
+    sync::Arc,
 };
 
 use ash::{prelude::VkResult, vk};
 
-use crate::{Allocator, HasDevice};
+use crate::{
+    alloc::{RelocationKind, RelocationSlot},
+    Allocator, AllocatorPool, DefragmentationError, Defragmenter, HasDevice,
+};
+pub use arena::{BufferArena, BufferSlice};
+pub use device_ptr::{DevicePtr, DeviceSlice, DeviceSliceRef};
+pub use device_vec::DeviceVec;
+pub(crate) use device_vec::GrowPlan;
 pub use staging::{StagingBelt, StagingBeltSuballocation, UniformBelt};
 use vk_mem::Alloc;
 
@@ -32,20 +43,31 @@ pub struct Buffer {
     buffer: vk::Buffer,
     size: vk::DeviceSize,
     device_address: u64,
+    usage: vk::BufferUsageFlags,
+    /// Keeps the pool alive for buffers allocated from a pool.
+    pool: Option<AllocatorPool>,
+    /// Set when the buffer opted into defragmentation. Holds the current handle.
+    relocation: Option<Arc<RelocationSlot>>,
 }
 unsafe impl Send for Buffer {}
 unsafe impl Sync for Buffer {}
 impl crate::utils::AsVkHandle for Buffer {
     fn vk_handle(&self) -> Self::Handle {
-        self.buffer
+        self.raw()
     }
     type Handle = vk::Buffer;
 }
 impl Drop for Buffer {
     fn drop(&mut self) {
+        // A defragmentation pass in progress takes over the allocation.
+        let handed_over = self.relocation.as_ref().is_some_and(|slot| slot.release());
+        let buffer = self.raw();
         unsafe {
-            self.allocator
-                .destroy_buffer(self.buffer, &mut self.allocation);
+            if handed_over {
+                self.allocator.device().destroy_buffer(buffer, None);
+            } else {
+                self.allocator.destroy_buffer(buffer, &mut self.allocation);
+            }
         }
     }
 }
@@ -76,6 +98,105 @@ impl Buffer {
             allocation,
             size: info.size,
             device_address,
+            usage,
+            pool: None,
+            relocation: None,
+        }
+    }
+    pub(crate) fn relocation_slot(&self) -> Option<&Arc<RelocationSlot>> {
+        self.relocation.as_ref()
+    }
+    fn raw(&self) -> vk::Buffer {
+        match self.relocation.as_ref() {
+            Some(relocation) => relocation.handle(),
+            None => self.buffer,
+        }
+    }
+    /// Allow the [`Defragmenter`] to move this buffer to a different memory location.
+    ///
+    /// `info` must be the create info that the buffer was created with. Its `p_next` chain is
+    /// not kept. Moves are only applied when the owner awaits
+    /// [`relocate_buffer`](crate::commands::relocate_buffer). The raw handle of the buffer
+    /// changes when that future returns true, so descriptors referencing the buffer must be
+    /// rewritten. Buffers with a device address and host visible buffers cannot be moved.
+    pub fn enable_defragmentation(
+        &mut self,
+        defragmenter: &Defragmenter,
+        info: &vk::BufferCreateInfo,
+    ) -> Result<(), DefragmentationError> {
+        assert_eq!(info.size, self.size);
+        assert_eq!(info.usage, self.usage);
+        if self
+            .usage
+            .contains(vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS)
+        {
+            return Err(DefragmentationError::DeviceAddress);
+        }
+        let memory_properties = self
+            .allocator
+            .get_allocation_memory_properties(&self.allocation);
+        if memory_properties.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            // Also covers mapped allocations.
+            return Err(DefragmentationError::HostVisible);
+        }
+        if self.relocation.is_some() {
+            return Ok(());
+        }
+        let queue_family_indices: Box<[u32]> = if info.sharing_mode == vk::SharingMode::CONCURRENT {
+            unsafe {
+                std::slice::from_raw_parts(
+                    info.p_queue_family_indices,
+                    info.queue_family_index_count as usize,
+                )
+            }
+            .into()
+        } else {
+            Box::default()
+        };
+        let info = vk::BufferCreateInfo {
+            p_next: std::ptr::null(),
+            queue_family_index_count: 0,
+            p_queue_family_indices: std::ptr::null(),
+            _marker: std::marker::PhantomData,
+            ..*info
+        };
+        let slot = defragmenter.register(
+            &mut self.allocation,
+            vk::Handle::as_raw(self.buffer),
+            RelocationKind::Buffer {
+                info,
+                queue_family_indices,
+            },
+        );
+        self.relocation = Some(slot);
+        Ok(())
+    }
+    /// Create a new buffer in a custom [`AllocatorPool`].
+    /// `flags` should include [`vk_mem::AllocationCreateFlags::MAPPED`] if the pool is host-visible
+    /// and the buffer needs to be accessed on the host.
+    pub fn new_in_pool(
+        pool: &AllocatorPool,
+        size: vk::DeviceSize,
+        alignment: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        flags: vk_mem::AllocationCreateFlags,
+    ) -> VkResult<Self> {
+        unsafe {
+            let (buffer, allocation) = pool.raw().create_buffer_with_alignment(
+                &vk::BufferCreateInfo {
+                    size,
+                    usage,
+                    ..Default::default()
+                },
+                &vk_mem::AllocationCreateInfo {
+                    flags,
+                    ..Default::default()
+                },
+                alignment,
+            )?;
+            let mut this = Self::from_raw(pool.allocator().clone(), buffer, allocation, usage);
+            this.pool = Some(pool.clone());
+            Ok(this)
         }
     }
     /// Create a buffer for small amount of host -> device dataflow.
@@ -288,6 +409,12 @@ impl Buffer {
             )
         }
     }
+    /// Make host writes to the mapped memory visible to the device.
+    /// Only required when the memory is not `HOST_COHERENT`.
+    pub fn flush(&self) -> VkResult<()> {
+        self.allocator
+            .flush_allocation(&self.allocation, 0, self.size)
+    }
 }
 impl HasDevice for Buffer {
     fn device(&self) -> &crate::Device {
@@ -296,7 +423,7 @@ impl HasDevice for Buffer {
 }
 impl BufferLike for Buffer {
     fn raw_buffer(&self) -> vk::Buffer {
-        self.buffer
+        self.raw()
     }
     fn size(&self) -> vk::DeviceSize {
         self.size
@@ -308,12 +435,38 @@ impl BufferLike for Buffer {
 
 /// A special allocator designed to be used for [`BufferVec`].
 /// Holds one allocation at any given time.
+///
+/// Buffers replaced during growth are freed right away, unless the vector was grown with
+/// [`reserve_buffer_vec`](crate::commands::reserve_buffer_vec), which keeps them alive until
+/// the GPU has finished using them.
 pub struct BufferAllocator {
     allocator: Allocator,
     buffer: UnsafeCell<Option<Buffer>>,
+    /// Set while growing through [`BufferVec::reserve_retiring`].
+    retiring: Cell<bool>,
+    retired: UnsafeCell<Vec<Buffer>>,
     usage: vk::BufferUsageFlags,
     create_info: vk_mem::AllocationCreateInfo,
 }
+impl BufferAllocator {
+    fn new(
+        allocator: Allocator,
+        usage: vk::BufferUsageFlags,
+        create_info: vk_mem::AllocationCreateInfo,
+    ) -> Self {
+        debug_assert!(create_info
+            .flags
+            .contains(vk_mem::AllocationCreateFlags::MAPPED));
+        Self {
+            allocator,
+            buffer: UnsafeCell::new(None),
+            retiring: Cell::new(false),
+            retired: UnsafeCell::new(Vec::new()),
+            usage,
+            create_info,
+        }
+    }
+}
 unsafe impl std::alloc::Allocator for BufferAllocator {
     fn allocate(&self, layout: Layout) -> Result<std::ptr::NonNull<[u8]>, std::alloc::AllocError> {
         unsafe {
@@ -358,10 +511,14 @@ unsafe impl std::alloc::Allocator for BufferAllocator {
             .map_err(|_| std::alloc::AllocError)?;
         let info = self.allocator.get_allocation_info(&allocation);
         let buffer = Buffer::from_raw(self.allocator.clone(), buffer, allocation, self.usage);
-        let old_buffer = (&mut *self.buffer.get()).replace(buffer);
-        drop(old_buffer);
-        let ptr = NonNull::new(info.mapped_data as *mut u8).unwrap();
-        Ok(NonNull::slice_from_raw_parts(ptr, info.size as usize))
+        let new_ptr = NonNull::new(info.mapped_data as *mut u8).unwrap();
+        std::ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_ptr(), old_layout.size());
+        let old_buffer = (&mut *self.buffer.get()).replace(buffer).unwrap();
+        if self.retiring.get() {
+            // The old buffer might still be in use on the GPU.
+            (&mut *self.retired.get()).push(old_buffer);
+        }
+        Ok(NonNull::slice_from_raw_parts(new_ptr, info.size as usize))
     }
 
     unsafe fn deallocate(&self, ptr: std::ptr::NonNull<u8>, layout: Layout) {
@@ -411,17 +568,16 @@ impl<T> BufferVec<T> {
         alignment: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
     ) -> Self {
-        Self(Vec::new_in(BufferAllocator {
+        Self(Vec::new_in(BufferAllocator::new(
             allocator,
-            buffer: UnsafeCell::new(None),
             usage,
-            create_info: vk_mem::AllocationCreateInfo {
+            vk_mem::AllocationCreateInfo {
                 usage: vk_mem::MemoryUsage::AutoPreferDevice,
                 flags: vk_mem::AllocationCreateFlags::MAPPED
                     | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
                 ..Default::default()
             },
-        }))
+        )))
     }
     /// Create a buffer for large amount of host -> device dataflow.
     /// Only difference with `new_dynamic` is that on GPUs with Bar, this creates the buffer on the host.
@@ -430,52 +586,64 @@ impl<T> BufferVec<T> {
         alignment: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
     ) -> Self {
-        Self(Vec::new_in(BufferAllocator {
+        Self(Vec::new_in(BufferAllocator::new(
             allocator,
-            buffer: UnsafeCell::new(None),
             usage,
-            create_info: vk_mem::AllocationCreateInfo {
+            vk_mem::AllocationCreateInfo {
                 usage: vk_mem::MemoryUsage::AutoPreferDevice,
                 flags: vk_mem::AllocationCreateFlags::MAPPED
                     | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
                 ..Default::default()
             },
-        }))
+        )))
     }
     pub fn new_host(
         allocator: Allocator,
         alignment: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
     ) -> Self {
-        Self(Vec::new_in(BufferAllocator {
+        Self(Vec::new_in(BufferAllocator::new(
             allocator,
-            buffer: UnsafeCell::new(None),
             usage,
-            create_info: vk_mem::AllocationCreateInfo {
+            vk_mem::AllocationCreateInfo {
                 usage: vk_mem::MemoryUsage::AutoPreferHost,
                 flags: vk_mem::AllocationCreateFlags::MAPPED
                     | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
                 ..Default::default()
             },
-        }))
+        )))
     }
     /// Create a new buffer with DEVICE_LOCAL memory.
+    ///
+    /// The memory is mapped so that the vector can be accessed on the host, which requires a
+    /// memory type that is both DEVICE_LOCAL and HOST_VISIBLE, as on integrated GPUs or with
+    /// resizable BAR. Allocation fails otherwise. For arrays that are only accessed on the GPU,
+    /// use [`DeviceVec`].
     pub fn new_resource(
         allocator: Allocator,
         alignment: vk::DeviceSize,
         usage: vk::BufferUsageFlags,
     ) -> Self {
-        Self(Vec::new_in(BufferAllocator {
+        Self(Vec::new_in(BufferAllocator::new(
             allocator,
-            buffer: UnsafeCell::new(None),
             usage,
-            create_info: vk_mem::AllocationCreateInfo {
+            vk_mem::AllocationCreateInfo {
                 usage: vk_mem::MemoryUsage::AutoPreferDevice,
+                flags: vk_mem::AllocationCreateFlags::MAPPED
+                    | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
                 required_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
                 preferred_flags: vk::MemoryPropertyFlags::DEVICE_LOCAL,
                 ..Default::default()
             },
-        }))
+        )))
+    }
+    /// Reserve capacity for at least `additional` more elements, and return the buffers replaced
+    /// in the process instead of freeing them.
+    pub(crate) fn reserve_retiring(&mut self, additional: usize) -> Vec<Buffer> {
+        self.0.allocator().retiring.set(true);
+        self.0.reserve(additional);
+        self.0.allocator().retiring.set(false);
+        std::mem::take(unsafe { &mut *self.0.allocator().retired.get() })
     }
 
     pub fn flush(&mut self) -> VkResult<()> {
@@ -489,6 +657,8 @@ impl<T> BufferVec<T> {
     }
 }
 
+/// A [`Vec`] backed by host-visible buffer memory.
+/// For arrays that live in device-only memory, use [`DeviceVec`].
 pub struct BufferVec<T>(Vec<T, BufferAllocator>);
 unsafe impl<T: Send> Send for BufferVec<T> {}
 unsafe impl<T: Sync> Sync for BufferVec<T> {}
@@ -135,6 +135,11 @@ impl StagingBelt {
         }
     }
 
+    /// The largest allocation that can be made from this belt.
+    pub fn chunk_size(&self) -> vk::DeviceSize {
+        self.chunk_size
+    }
+
     // Needs to be regularily called. By default, this is called by a system in the Last stage.
     pub fn cleanup(&mut self) {
         'pop_ready_jobs: while let Some(frame) = self.frames.front() {
@@ -344,6 +349,10 @@ impl<T: ?Sized> BufferLike for StagingBeltSuballocation<T> {
     }
 }
 impl<T: ?Sized> StagingBeltSuballocation<T> {
+    /// Returns 0 if the staging belt was not created with `SHADER_DEVICE_ADDRESS`.
+    pub(super) fn raw_device_address(&self) -> vk::DeviceAddress {
+        self.device_address
+    }
     pub fn write(&mut self, item: &T) {
         assert_eq!(std::mem::size_of_val(item), self.size as usize);
         unsafe {
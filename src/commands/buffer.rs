@@ -0,0 +1,143 @@
+use crate::buffer::{Buffer, BufferLike, BufferVec, DeviceVec, GrowPlan};
+use crate::define_future;
+use crate::future::{BarrierContext, GPUBorrowedResource, GPUFuture, RecordContext};
+use ash::{prelude::VkResult, vk};
+
+//region ReserveBufferVec
+define_future!(ReserveBufferVecFuture<'a, T>, 'a, T);
+/// Reserves capacity in a [`BufferVec`], keeping the replaced buffers alive until the GPU has
+/// finished using them.
+pub struct ReserveBufferVecFuture<'a, T> {
+    buffer: &'a mut BufferVec<T>,
+    additional: usize,
+}
+impl<T> GPUFuture for ReserveBufferVecFuture<'_, T> {
+    type Output = ();
+    type Retained = Vec<Buffer>;
+
+    fn barrier(&mut self, _ctx: BarrierContext) {}
+
+    fn record(self, _ctx: RecordContext) -> (Self::Output, Self::Retained) {
+        // The contents are copied on the host, so no commands need to be recorded.
+        ((), self.buffer.reserve_retiring(self.additional))
+    }
+}
+
+/// Reserve capacity for at least `additional` more elements in the [`BufferVec`].
+///
+/// Unlike [`Vec::reserve`], the buffers replaced during growth are retired through the timeline
+/// instead of being freed right away. Use this when the GPU might still be reading the vector.
+#[must_use]
+pub fn reserve_buffer_vec<T>(
+    buffer: &mut BufferVec<T>,
+    additional: usize,
+) -> ReserveBufferVecFuture<'_, T> {
+    ReserveBufferVecFuture { buffer, additional }
+}
+//endregion
+
+//region GrowDeviceVec
+define_future!(GrowDeviceVecFuture<'a, T>, 'a, T);
+/// Grows a [`DeviceVec`] while preserving its contents.
+///
+/// The contents are copied from the old buffer into the new buffer on the GPU.
+/// The old buffer is returned as a retained value so that it stays alive until the GPU has
+/// finished using it.
+pub struct GrowDeviceVecFuture<'a, T> {
+    buffer: &'a mut GPUBorrowedResource<DeviceVec<T>>,
+    plan: GrowPlan,
+}
+impl<T> GPUFuture for GrowDeviceVecFuture<'_, T> {
+    type Output = VkResult<()>;
+    type Retained = Option<Buffer>;
+
+    fn barrier(&mut self, mut ctx: BarrierContext) {
+        if self.plan.copy_size > 0 {
+            ctx.use_resource(
+                self.buffer,
+                vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::TRANSFER_READ,
+            );
+        }
+        if self.plan.copy_size > 0 || self.plan.fill.is_some() {
+            ctx.use_resource(
+                self.buffer,
+                vk::PipelineStageFlags2::COPY | vk::PipelineStageFlags2::CLEAR,
+                vk::AccessFlags2::TRANSFER_WRITE,
+            );
+        }
+    }
+
+    fn record(self, ctx: RecordContext) -> (Self::Output, Self::Retained) {
+        let plan = self.plan;
+        // The vector is only updated in place. Replaced buffers are retained below.
+        let buffer: &mut DeviceVec<T> = self.buffer.get_mut();
+
+        let mut retired = None;
+        if let Some(capacity) = plan.reallocate {
+            retired = match buffer.reallocate(capacity) {
+                Ok(retired) => retired,
+                Err(err) => return (Err(err), None),
+            };
+            if let Some(old_buffer) = retired.as_ref().filter(|_| plan.copy_size > 0) {
+                unsafe {
+                    ctx.device.cmd_copy_buffer(
+                        ctx.command_buffer,
+                        old_buffer.raw_buffer(),
+                        buffer.raw_buffer(),
+                        &[vk::BufferCopy {
+                            src_offset: 0,
+                            dst_offset: 0,
+                            size: plan.copy_size,
+                        }],
+                    );
+                }
+            }
+        }
+        if let Some(fill) = plan.fill {
+            assert!(
+                fill.start % 4 == 0 && fill.end % 4 == 0,
+                "Device-only vectors can only be zero-extended when the element size is a multiple of 4"
+            );
+            unsafe {
+                // Safety: The new elements are zeroed on the GPU below.
+                buffer.set_len(plan.len);
+                ctx.device.cmd_fill_buffer(
+                    ctx.command_buffer,
+                    buffer.raw_buffer(),
+                    fill.start,
+                    fill.end - fill.start,
+                    0,
+                );
+            }
+        }
+        (Ok(()), retired)
+    }
+}
+
+/// Reserve capacity for at least `additional` more elements in the [`DeviceVec`].
+///
+/// This preserves the contents of the vector by recording a buffer copy from the old buffer into
+/// the new buffer. The old buffer is retired through the timeline instead of being freed right away.
+#[must_use]
+pub fn reserve_device_vec<T>(
+    buffer: &mut GPUBorrowedResource<DeviceVec<T>>,
+    additional: usize,
+) -> GrowDeviceVecFuture<'_, T> {
+    let plan = buffer.plan_growth(additional, None);
+    GrowDeviceVecFuture { buffer, plan }
+}
+
+/// Resize the [`DeviceVec`] so that it contains `new_len` elements.
+/// Newly added elements are zeroed on the GPU.
+/// Shrinking the vector only truncates its length.
+#[must_use]
+pub fn resize_device_vec<T>(
+    buffer: &mut GPUBorrowedResource<DeviceVec<T>>,
+    new_len: usize,
+) -> GrowDeviceVecFuture<'_, T> {
+    buffer.get_mut().truncate(new_len);
+    let plan = buffer.plan_growth(new_len - buffer.len(), Some(new_len));
+    GrowDeviceVecFuture { buffer, plan }
+}
+//endregion
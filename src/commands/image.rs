@@ -2,6 +2,7 @@ use crate::buffer::BufferLike;
 use crate::future::{BarrierContext, GPUFuture, GPUResource, RecordContext};
 use crate::{define_future, ImageLike};
 use ash::vk;
+use bevy::math::{IVec3, UVec3};
 use std::ops::Deref;
 
 //region Blit
@@ -67,7 +68,7 @@ where
     }
 
     fn record(self, ctx: RecordContext) -> (Self::Output, Self::Retained) {
-        let image_blit;
+        let regions;
         unsafe {
             ctx.device.cmd_blit_image(
                 ctx.command_buffer,
@@ -76,51 +77,15 @@ where
                 self.dst_image.raw_image(),
                 self.dst_image_layout,
                 if self.regions.is_empty() {
-                    let src_subresource_range = self.src_image.subresource_range();
-                    let src_offset_min = self.src_image.offset();
-                    let src_offset_max = src_offset_min + self.src_image.extent().as_ivec3();
-                    let dst_subresource_range = self.dst_image.subresource_range();
-                    let dst_offset_min = self.dst_image.offset();
-                    let dst_offset_max = dst_offset_min + self.dst_image.extent().as_ivec3();
-                    image_blit = [vk::ImageBlit {
-                        src_subresource: vk::ImageSubresourceLayers {
-                            aspect_mask: src_subresource_range.aspect_mask,
-                            mip_level: 0,
-                            base_array_layer: src_subresource_range.base_array_layer,
-                            layer_count: src_subresource_range.layer_count,
-                        },
-                        src_offsets: [
-                            vk::Offset3D {
-                                x: src_offset_min.x as i32,
-                                y: src_offset_min.y as i32,
-                                z: src_offset_min.z as i32,
-                            },
-                            vk::Offset3D {
-                                x: src_offset_max.x as i32,
-                                y: src_offset_max.y as i32,
-                                z: src_offset_max.z as i32,
-                            },
-                        ],
-                        dst_subresource: vk::ImageSubresourceLayers {
-                            aspect_mask: dst_subresource_range.aspect_mask,
-                            mip_level: 0,
-                            base_array_layer: dst_subresource_range.base_array_layer,
-                            layer_count: dst_subresource_range.layer_count,
-                        },
-                        dst_offsets: [
-                            vk::Offset3D {
-                                x: dst_offset_min.x as i32,
-                                y: dst_offset_min.y as i32,
-                                z: dst_offset_min.z as i32,
-                            },
-                            vk::Offset3D {
-                                x: dst_offset_max.x as i32,
-                                y: dst_offset_max.y as i32,
-                                z: dst_offset_max.z as i32,
-                            },
-                        ],
-                    }];
-                    &image_blit
+                    regions = [image_blit(
+                        self.src_image.subresource_range(),
+                        self.src_image.offset(),
+                        self.src_image.extent(),
+                        self.dst_image.subresource_range(),
+                        self.dst_image.offset(),
+                        self.dst_image.extent(),
+                    )];
+                    &regions
                 } else {
                     self.regions
                 },
@@ -131,6 +96,40 @@ where
     }
 }
 
+/// A blit region covering the first mip level of the source and destination subresource ranges.
+pub(crate) fn image_blit(
+    src_range: vk::ImageSubresourceRange,
+    src_offset: IVec3,
+    src_extent: UVec3,
+    dst_range: vk::ImageSubresourceRange,
+    dst_offset: IVec3,
+    dst_extent: UVec3,
+) -> vk::ImageBlit {
+    let to_offset = |offset: IVec3| vk::Offset3D {
+        x: offset.x,
+        y: offset.y,
+        z: offset.z,
+    };
+    let to_layers = |range: vk::ImageSubresourceRange| vk::ImageSubresourceLayers {
+        aspect_mask: range.aspect_mask,
+        mip_level: range.base_mip_level,
+        base_array_layer: range.base_array_layer,
+        layer_count: range.layer_count,
+    };
+    vk::ImageBlit {
+        src_subresource: to_layers(src_range),
+        src_offsets: [
+            to_offset(src_offset),
+            to_offset(src_offset + src_extent.as_ivec3()),
+        ],
+        dst_subresource: to_layers(dst_range),
+        dst_offsets: [
+            to_offset(dst_offset),
+            to_offset(dst_offset + dst_extent.as_ivec3()),
+        ],
+    }
+}
+
 #[must_use]
 pub fn blit_image<'a, S, T, SI: ImageLike, TI: ImageLike>(
     src_image: &'a mut S,
@@ -211,6 +210,74 @@ where
         ranges,
     }
 }
+
+define_future!(ClearDepthStencilImageFuture<'a, T>, 'a, I: ImageLike, T: Unpin + GPUResource + Deref<Target = I>);
+pub struct ClearDepthStencilImageFuture<'a, T> {
+    dst_image: &'a mut T,
+    layout: vk::ImageLayout,
+    clear_value: vk::ClearDepthStencilValue,
+    ranges: &'a [vk::ImageSubresourceRange],
+}
+impl<T> ClearDepthStencilImageFuture<'_, T> {
+    pub fn with_layout(mut self, layout: vk::ImageLayout) -> Self {
+        self.layout = layout;
+        self
+    }
+}
+impl<I: ImageLike, T> GPUFuture for ClearDepthStencilImageFuture<'_, T>
+where
+    T: Unpin + GPUResource + Deref<Target = I>,
+{
+    type Output = ();
+
+    fn barrier(&mut self, mut ctx: BarrierContext) {
+        ctx.use_image_resource(
+            self.dst_image,
+            vk::PipelineStageFlags2::CLEAR,
+            vk::AccessFlags2::TRANSFER_WRITE,
+            self.layout,
+            true,
+        );
+    }
+
+    fn record(self, ctx: RecordContext) -> (Self::Output, Self::Retained) {
+        let range;
+        unsafe {
+            ctx.device.cmd_clear_depth_stencil_image(
+                ctx.command_buffer,
+                self.dst_image.raw_image(),
+                self.layout,
+                &self.clear_value,
+                if self.ranges.is_empty() {
+                    range = [self.dst_image.subresource_range()];
+                    &range
+                } else {
+                    self.ranges
+                },
+            );
+        }
+        Default::default()
+    }
+}
+
+/// Clear the depth and/or stencil aspects of an image.
+/// When `ranges` is empty, the entire subresource range of the image will be cleared.
+#[must_use]
+pub fn clear_depth_stencil_image<'a, T, I: ImageLike>(
+    dst_image: &'a mut T,
+    clear_value: vk::ClearDepthStencilValue,
+    ranges: &'a [vk::ImageSubresourceRange],
+) -> ClearDepthStencilImageFuture<'a, T>
+where
+    T: GPUResource + Deref<Target = I> + Unpin,
+{
+    ClearDepthStencilImageFuture {
+        dst_image,
+        layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+        clear_value,
+        ranges,
+    }
+}
 //endregion
 
 //region CopyBufferToImage
@@ -222,11 +289,17 @@ pub struct CopyBufferToImageFuture<'a, T, B> {
     regions: &'a [vk::BufferImageCopy],
     discard_contents: bool,
 }
-impl<T, B> CopyBufferToImageFuture<'_, T, B> {
+impl<'a, T, B> CopyBufferToImageFuture<'a, T, B> {
     pub fn with_layout(mut self, layout: vk::ImageLayout) -> Self {
         self.layout = layout;
         self
     }
+    /// Copy the given regions instead of the whole buffer into the whole image.
+    /// `buffer_offset` is relative to the start of the underlying `VkBuffer`.
+    pub fn with_regions(mut self, regions: &'a [vk::BufferImageCopy]) -> Self {
+        self.regions = regions;
+        self
+    }
     pub fn discard_image_contents(mut self) -> Self {
         self.discard_contents = true;
         self
@@ -0,0 +1,459 @@
+use std::{ops::Deref, sync::Arc};
+
+use ash::vk;
+use bevy::{
+    app::{App, First, Plugin},
+    asset::{AssetServer, Assets},
+    ecs::system::{Res, ResMut, Resource},
+};
+use thiserror::Error;
+
+use crate::{
+    define_future,
+    future::{BarrierContext, GPUFuture, GPUResource, RecordContext},
+    image::mip_extent,
+    pipeline::{
+        CachedPipeline, ComputePipeline, ComputePipelineCreateInfo, LayoutCache, PipelineCache,
+        PipelineLayout,
+    },
+    shader::{ShaderModule, SpecializedShader},
+    utils::{Format, FormatType},
+    DeferredOperationTaskPool, Device, HasDevice, ImageExt, ImageLike, ImageViewHandle,
+    RhyoliteApp,
+};
+
+use super::image::image_blit;
+
+//region GenerateMipmaps
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum MipmapMethod {
+    Blit,
+    Compute,
+}
+
+define_future!(GenerateMipmapsFuture<'a, T>, 'a, I: ImageLike + HasDevice, T: Unpin + GPUResource + Deref<Target = I>);
+pub struct GenerateMipmapsFuture<'a, T> {
+    image: &'a mut T,
+    filter: vk::Filter,
+    method: MipmapMethod,
+    compute_pipeline: Option<&'a MipmapPipeline>,
+}
+
+impl<I: ImageLike + HasDevice, T> GPUFuture for GenerateMipmapsFuture<'_, T>
+where
+    T: Unpin + GPUResource + Deref<Target = I>,
+{
+    type Output = ();
+    /// Image views used by the compute fallback.
+    type Retained = Vec<ImageViewHandle>;
+
+    fn barrier(&mut self, mut ctx: BarrierContext) {
+        match self.method {
+            MipmapMethod::Blit => ctx.use_image_resource(
+                self.image,
+                vk::PipelineStageFlags2::BLIT,
+                vk::AccessFlags2::TRANSFER_READ | vk::AccessFlags2::TRANSFER_WRITE,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                false,
+            ),
+            MipmapMethod::Compute => ctx.use_image_resource(
+                self.image,
+                vk::PipelineStageFlags2::COMPUTE_SHADER,
+                vk::AccessFlags2::SHADER_STORAGE_READ | vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                vk::ImageLayout::GENERAL,
+                false,
+            ),
+        }
+    }
+
+    fn record(self, ctx: RecordContext) -> (Self::Output, Self::Retained) {
+        match self.method {
+            MipmapMethod::Blit => {
+                self.record_blit(ctx);
+                ((), Vec::new())
+            }
+            MipmapMethod::Compute => {
+                let pipeline = self
+                    .compute_pipeline
+                    .expect("The compute pipeline was checked by generate_mipmaps");
+                ((), self.record_compute(pipeline, ctx))
+            }
+        }
+    }
+}
+
+impl<I: ImageLike + HasDevice, T> GenerateMipmapsFuture<'_, T>
+where
+    T: Unpin + GPUResource + Deref<Target = I>,
+{
+    /// Blit each level into the next one. Every level starts in `TRANSFER_DST_OPTIMAL`, and is
+    /// transitioned into `TRANSFER_SRC_OPTIMAL` once it has been written.
+    fn record_blit(&self, ctx: RecordContext) {
+        let image: &I = self.image;
+        let range = image.subresource_range();
+        let level_barrier = |level: u32,
+                             src_access: vk::AccessFlags2,
+                             dst_access: vk::AccessFlags2,
+                             old_layout: vk::ImageLayout,
+                             new_layout: vk::ImageLayout| {
+            vk::ImageMemoryBarrier2 {
+                src_stage_mask: vk::PipelineStageFlags2::BLIT,
+                src_access_mask: src_access,
+                dst_stage_mask: vk::PipelineStageFlags2::BLIT,
+                dst_access_mask: dst_access,
+                old_layout,
+                new_layout,
+                image: image.raw_image(),
+                subresource_range: vk::ImageSubresourceRange {
+                    base_mip_level: range.base_mip_level + level,
+                    level_count: 1,
+                    ..range
+                },
+                ..Default::default()
+            }
+        };
+        unsafe {
+            for level in 1..range.level_count {
+                ctx.device.cmd_pipeline_barrier2(
+                    ctx.command_buffer,
+                    &vk::DependencyInfo::default().image_memory_barriers(&[level_barrier(
+                        level - 1,
+                        vk::AccessFlags2::TRANSFER_WRITE,
+                        vk::AccessFlags2::TRANSFER_READ,
+                        vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                        vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    )]),
+                );
+                let mut src_range = range;
+                src_range.base_mip_level += level - 1;
+                let mut dst_range = range;
+                dst_range.base_mip_level += level;
+                ctx.device.cmd_blit_image(
+                    ctx.command_buffer,
+                    image.raw_image(),
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image.raw_image(),
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[image_blit(
+                        src_range,
+                        image.offset() >> (level - 1) as i32,
+                        mip_extent(image.extent(), level - 1),
+                        dst_range,
+                        image.offset() >> level as i32,
+                        mip_extent(image.extent(), level),
+                    )],
+                    self.filter,
+                );
+            }
+            // Return all levels to TRANSFER_DST_OPTIMAL, which is the layout known to the resource state.
+            if range.level_count > 1 {
+                let mut barrier = level_barrier(
+                    0,
+                    vk::AccessFlags2::empty(),
+                    vk::AccessFlags2::TRANSFER_WRITE,
+                    vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                );
+                barrier.subresource_range.level_count = range.level_count - 1;
+                ctx.device.cmd_pipeline_barrier2(
+                    ctx.command_buffer,
+                    &vk::DependencyInfo::default().image_memory_barriers(&[barrier]),
+                );
+            }
+        }
+    }
+
+    /// Downsample each level into the next one with a compute shader. All levels stay in `GENERAL`.
+    fn record_compute(
+        &self,
+        pipeline: &MipmapPipeline,
+        mut ctx: RecordContext,
+    ) -> Vec<ImageViewHandle> {
+        let compute_pipeline = pipeline
+            .get(self.filter)
+            .expect("The compute pipeline was checked by generate_mipmaps");
+        let image: &I = self.image;
+        let range = image.subresource_range();
+        let mut views = Vec::with_capacity(range.level_count as usize);
+        for level in 0..range.level_count {
+            let view = image
+                .view_builder()
+                .view_type(vk::ImageViewType::TYPE_2D_ARRAY)
+                .mip_levels(level..level + 1)
+                .build_handle()
+                .unwrap();
+            views.push(view);
+        }
+        ctx.bind_pipeline(compute_pipeline);
+        for level in 1..range.level_count {
+            let extent = mip_extent(image.extent(), level);
+            let image_info = |view: &ImageViewHandle| {
+                [vk::DescriptorImageInfo {
+                    image_view: view.raw(),
+                    image_layout: vk::ImageLayout::GENERAL,
+                    ..Default::default()
+                }]
+            };
+            let src_info = image_info(&views[level as usize - 1]);
+            let dst_info = image_info(&views[level as usize]);
+            unsafe {
+                if level > 1 {
+                    ctx.device.cmd_pipeline_barrier2(
+                        ctx.command_buffer,
+                        &vk::DependencyInfo::default().memory_barriers(&[vk::MemoryBarrier2 {
+                            src_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                            src_access_mask: vk::AccessFlags2::SHADER_STORAGE_WRITE,
+                            dst_stage_mask: vk::PipelineStageFlags2::COMPUTE_SHADER,
+                            dst_access_mask: vk::AccessFlags2::SHADER_STORAGE_READ,
+                            ..Default::default()
+                        }]),
+                    );
+                }
+                ctx.device
+                    .extension::<ash::khr::push_descriptor::Meta>()
+                    .cmd_push_descriptor_set(
+                        ctx.command_buffer,
+                        vk::PipelineBindPoint::COMPUTE,
+                        pipeline.layout.raw(),
+                        0,
+                        &[
+                            vk::WriteDescriptorSet::default()
+                                .dst_binding(0)
+                                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                                .image_info(&src_info),
+                            vk::WriteDescriptorSet::default()
+                                .dst_binding(1)
+                                .descriptor_type(vk::DescriptorType::STORAGE_IMAGE)
+                                .image_info(&dst_info),
+                        ],
+                    );
+                ctx.device.cmd_dispatch(
+                    ctx.command_buffer,
+                    extent.x.div_ceil(8),
+                    extent.y.div_ceil(8),
+                    range.layer_count,
+                );
+            }
+        }
+        views
+    }
+}
+
+/// Errors returned by [`generate_mipmaps`].
+#[derive(Debug, Error)]
+pub enum MipmapError {
+    #[error("format {0:?} supports neither blitting nor storage")]
+    UnsupportedFormat(vk::Format),
+
+    #[error("mipmap generation of 3D images is unsupported")]
+    Unsupported3D,
+
+    #[error("format {0:?} cannot be blitted, and no compute fallback was provided")]
+    ComputeFallbackRequired(vk::Format),
+
+    /// The compute pipeline is still being compiled. Try again in a later frame.
+    #[error("the mipmap compute pipeline is not ready")]
+    ComputePipelineNotReady,
+}
+
+fn select_method(
+    device: &Device,
+    format: vk::Format,
+    filter: vk::Filter,
+) -> Result<MipmapMethod, MipmapError> {
+    let features = device
+        .physical_device()
+        .format_properties(format)
+        .optimal_tiling_features;
+    let can_blit = features
+        .contains(vk::FormatFeatureFlags::BLIT_SRC | vk::FormatFeatureFlags::BLIT_DST)
+        && (filter == vk::Filter::NEAREST
+            || features.contains(vk::FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR));
+    if can_blit {
+        return Ok(MipmapMethod::Blit);
+    }
+    // The compute shader filters in floating point, so integer formats cannot use it.
+    let is_float = Format::from_vk(format)
+        .is_some_and(|format| !matches!(format.ty, FormatType::UInt | FormatType::SInt));
+    if features.contains(vk::FormatFeatureFlags::STORAGE_IMAGE) && is_float {
+        Ok(MipmapMethod::Compute)
+    } else {
+        Err(MipmapError::UnsupportedFormat(format))
+    }
+}
+
+/// Fill all mip levels of the image from its first mip level.
+///
+/// Uses `vkCmdBlitImage` when the format supports it. Otherwise, the image is downsampled
+/// with the `compute_fallback` pipeline provided by [`MipmapPlugin`], in which case the image must
+/// have been created with `STORAGE` usage. After completion, the image will be in
+/// `TRANSFER_DST_OPTIMAL` when blitted and `GENERAL` when downsampled with the compute shader.
+///
+/// Returns [`MipmapError::ComputePipelineNotReady`] while the compute pipeline is still being
+/// compiled, in which case mipmap generation should be retried in a later frame.
+pub fn generate_mipmaps<'a, T, I: ImageLike + HasDevice>(
+    image: &'a mut T,
+    filter: vk::Filter,
+    compute_fallback: Option<&'a MipmapPipeline>,
+) -> Result<GenerateMipmapsFuture<'a, T>, MipmapError>
+where
+    T: GPUResource + Deref<Target = I> + Unpin,
+{
+    if image.extent().z != 1 {
+        return Err(MipmapError::Unsupported3D);
+    }
+    let method = select_method(image.device(), image.format(), filter)?;
+    let compute_pipeline = match method {
+        MipmapMethod::Blit => None,
+        MipmapMethod::Compute => {
+            let pipeline =
+                compute_fallback.ok_or(MipmapError::ComputeFallbackRequired(image.format()))?;
+            if pipeline.get(filter).is_none() {
+                return Err(MipmapError::ComputePipelineNotReady);
+            }
+            Some(pipeline)
+        }
+    };
+    Ok(GenerateMipmapsFuture {
+        image,
+        filter,
+        method,
+        compute_pipeline,
+    })
+}
+//endregion
+
+/// Compute pipelines for the compute fallback of [`generate_mipmaps`].
+/// Requires `VK_KHR_push_descriptor` and the `shaderStorageImageReadWithoutFormat` and
+/// `shaderStorageImageWriteWithoutFormat` features.
+#[derive(Resource)]
+pub struct MipmapPipeline {
+    linear: CachedPipeline<ComputePipeline>,
+    nearest: CachedPipeline<ComputePipeline>,
+    layout: Arc<PipelineLayout>,
+}
+impl MipmapPipeline {
+    fn get(&self, filter: vk::Filter) -> Option<&ComputePipeline> {
+        let pipeline = if filter == vk::Filter::NEAREST {
+            &self.nearest
+        } else {
+            &self.linear
+        };
+        pipeline.get().map(|pipeline| pipeline.deref())
+    }
+    pub fn is_ready(&self) -> bool {
+        self.linear.get().is_some() && self.nearest.get().is_some()
+    }
+}
+
+/// Adds the [`MipmapPipeline`] resource, used as the fallback of [`generate_mipmaps`] for formats
+/// that cannot be blitted. Requires the `glsl` feature. The shader is compiled by the asset
+/// processor like any other GLSL shader, so the app must use [`AssetMode::Processed`](bevy::asset::AssetMode::Processed).
+pub struct MipmapPlugin;
+
+#[derive(Resource)]
+struct MipmapComputeSupported;
+
+impl Plugin for MipmapPlugin {
+    fn build(&self, app: &mut App) {
+        let supported = app
+            .add_device_extension::<ash::khr::push_descriptor::Meta>()
+            .is_ok()
+            && app
+                .enable_feature::<vk::PhysicalDeviceFeatures>(|f| {
+                    &mut f.shader_storage_image_read_without_format
+                })
+                .exists()
+            && app
+                .enable_feature::<vk::PhysicalDeviceFeatures>(|f| {
+                    &mut f.shader_storage_image_write_without_format
+                })
+                .exists();
+        if supported {
+            #[cfg(feature = "glsl")]
+            bevy::asset::embedded_asset!(app, "mipmap.comp");
+            app.insert_resource(MipmapComputeSupported);
+            app.add_systems(First, mipmap_pipeline_system);
+        } else {
+            tracing::warn!("Compute fallback for mipmap generation is unsupported on this device");
+        }
+    }
+    fn finish(&self, app: &mut App) {
+        if !app.world().contains_resource::<MipmapComputeSupported>() {
+            return;
+        }
+        #[cfg(feature = "glsl")]
+        {
+            let device = app.world().resource::<Device>().clone();
+            let shader = app
+                .world()
+                .resource::<AssetServer>()
+                .load("embedded://rhyolite/commands/mipmap.comp");
+            let layout_cache = app.world().resource::<LayoutCache>();
+            let set_layout = layout_cache
+                .descriptor_set_layout(
+                    &[
+                        vk::DescriptorSetLayoutBinding {
+                            binding: 0,
+                            descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                            descriptor_count: 1,
+                            stage_flags: vk::ShaderStageFlags::COMPUTE,
+                            ..Default::default()
+                        },
+                        vk::DescriptorSetLayoutBinding {
+                            binding: 1,
+                            descriptor_type: vk::DescriptorType::STORAGE_IMAGE,
+                            descriptor_count: 1,
+                            stage_flags: vk::ShaderStageFlags::COMPUTE,
+                            ..Default::default()
+                        },
+                    ],
+                    vk::DescriptorSetLayoutCreateFlags::PUSH_DESCRIPTOR_KHR,
+                )
+                .unwrap();
+            let layout = layout_cache
+                .pipeline_layout(
+                    vec![set_layout],
+                    &[],
+                    vk::PipelineLayoutCreateFlags::empty(),
+                )
+                .unwrap();
+            let pipeline_cache = app.world().resource::<PipelineCache>();
+            let create = |linear: bool| {
+                pipeline_cache.create_compute(ComputePipelineCreateInfo {
+                    device: device.clone(),
+                    shader: SpecializedShader {
+                        stage: vk::ShaderStageFlags::COMPUTE,
+                        shader: shader.clone(),
+                        ..Default::default()
+                    }
+                    .with_const(0, vk::Bool32::from(linear)),
+                    layout: layout.clone(),
+                    flags: vk::PipelineCreateFlags::empty(),
+                })
+            };
+            let pipeline = MipmapPipeline {
+                linear: create(true),
+                nearest: create(false),
+                layout,
+            };
+            app.insert_resource(pipeline);
+        }
+        #[cfg(not(feature = "glsl"))]
+        tracing::warn!("Compute fallback for mipmap generation requires the glsl feature");
+    }
+}
+
+fn mipmap_pipeline_system(
+    pipeline: Option<ResMut<MipmapPipeline>>,
+    pipeline_cache: Res<PipelineCache>,
+    assets: Res<Assets<ShaderModule>>,
+    task_pool: Res<DeferredOperationTaskPool>,
+) {
+    let Some(mut pipeline) = pipeline else {
+        return;
+    };
+    let pipeline = &mut *pipeline;
+    pipeline_cache.retrieve(&mut pipeline.linear, &assets, &task_pool);
+    pipeline_cache.retrieve(&mut pipeline.nearest, &assets, &task_pool);
+}
@@ -1,11 +1,17 @@
+mod buffer;
 mod closure;
 mod combinator;
 mod image;
+mod mipmap;
+mod relocate;
 mod render;
 
+pub use buffer::*;
 pub use closure::*;
 pub use combinator::*;
 pub use image::*;
+pub use mipmap::*;
+pub use relocate::*;
 
 use crate::define_future;
 
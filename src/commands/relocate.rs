@@ -0,0 +1,223 @@
+use crate::alloc::RelocationKind;
+use crate::buffer::Buffer;
+use crate::future::{BarrierContext, GPUFuture, GPUResource, RecordContext};
+use crate::{define_future, Image, RetiredRelocation};
+use ash::vk;
+use std::ops::Deref;
+
+//region RelocateBuffer
+define_future!(RelocateBufferFuture<'a, S>, 'a, S: Unpin + GPUResource + Deref<Target = Buffer>);
+/// Applies the move proposed for a buffer by the [`Defragmenter`](crate::Defragmenter).
+pub struct RelocateBufferFuture<'a, S> {
+    buffer: &'a mut S,
+    pending: bool,
+}
+impl<S> GPUFuture for RelocateBufferFuture<'_, S>
+where
+    S: Unpin + GPUResource + Deref<Target = Buffer>,
+{
+    /// True if the buffer was moved and now has a new handle.
+    type Output = bool;
+    type Retained = Option<RetiredRelocation>;
+
+    fn barrier(&mut self, mut ctx: BarrierContext) {
+        self.pending = self
+            .buffer
+            .relocation_slot()
+            .is_some_and(|slot| slot.is_pending());
+        if self.pending {
+            ctx.use_resource(
+                self.buffer,
+                vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::TRANSFER_READ,
+            );
+        }
+    }
+
+    fn record(self, ctx: RecordContext) -> (Self::Output, Self::Retained) {
+        let Some(slot) = self.buffer.relocation_slot().filter(|_| self.pending) else {
+            return (false, None);
+        };
+        let RelocationKind::Buffer { size, .. } = slot.kind() else {
+            unreachable!()
+        };
+        // The move may have been cancelled since the barrier was emitted.
+        let Some((old_buffer, new_buffer)) = slot.apply::<vk::Buffer>() else {
+            return (false, None);
+        };
+        unsafe {
+            ctx.device.cmd_copy_buffer(
+                ctx.command_buffer,
+                old_buffer,
+                new_buffer,
+                &[vk::BufferCopy {
+                    src_offset: 0,
+                    dst_offset: 0,
+                    size: *size,
+                }],
+            );
+            ctx.device.cmd_pipeline_barrier2(
+                ctx.command_buffer,
+                &vk::DependencyInfo::default().memory_barriers(&[copy_completed_barrier()]),
+            );
+        }
+        (true, Some(RetiredRelocation::new(slot.clone(), old_buffer)))
+    }
+}
+
+/// Apply the move proposed for `buffer` by the [`Defragmenter`](crate::Defragmenter), if any.
+///
+/// The contents are copied into the new buffer on the GPU and the old buffer is retired once the
+/// frame completes. Returns true if the buffer was moved. The raw handle of the buffer changes in
+/// that case, so descriptors referencing the buffer must be rewritten.
+#[must_use]
+pub fn relocate_buffer<S>(buffer: &mut S) -> RelocateBufferFuture<'_, S>
+where
+    S: Unpin + GPUResource + Deref<Target = Buffer>,
+{
+    RelocateBufferFuture {
+        buffer,
+        pending: false,
+    }
+}
+//endregion
+
+//region RelocateImage
+define_future!(RelocateImageFuture<'a, S>, 'a, S: Unpin + GPUResource + Deref<Target = Image>);
+/// Applies the move proposed for an image by the [`Defragmenter`](crate::Defragmenter).
+pub struct RelocateImageFuture<'a, S> {
+    image: &'a mut S,
+    pending: bool,
+}
+impl<S> GPUFuture for RelocateImageFuture<'_, S>
+where
+    S: Unpin + GPUResource + Deref<Target = Image>,
+{
+    /// True if the image was moved and now has a new handle.
+    type Output = bool;
+    type Retained = Option<RetiredRelocation>;
+
+    fn barrier(&mut self, mut ctx: BarrierContext) {
+        self.pending = self
+            .image
+            .relocation_slot()
+            .is_some_and(|slot| slot.is_pending());
+        if self.pending {
+            // The new image takes over the tracked state of the old image, so it ends up in
+            // TRANSFER_SRC_OPTIMAL as well.
+            ctx.use_image_resource(
+                self.image,
+                vk::PipelineStageFlags2::COPY,
+                vk::AccessFlags2::TRANSFER_READ,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                false,
+            );
+        }
+    }
+
+    fn record(self, ctx: RecordContext) -> (Self::Output, Self::Retained) {
+        let Some(slot) = self.image.relocation_slot().filter(|_| self.pending) else {
+            return (false, None);
+        };
+        let RelocationKind::Image { info, aspect_mask } = slot.kind() else {
+            unreachable!()
+        };
+        // The move may have been cancelled since the barrier was emitted.
+        let Some((old_image, new_image)) = slot.apply::<vk::Image>() else {
+            return (false, None);
+        };
+        let range = vk::ImageSubresourceRange {
+            aspect_mask: *aspect_mask,
+            base_mip_level: 0,
+            level_count: info.mip_levels,
+            base_array_layer: 0,
+            layer_count: info.array_layers,
+        };
+        let regions: Vec<vk::ImageCopy> = (0..info.mip_levels)
+            .map(|level| {
+                let subresource = vk::ImageSubresourceLayers {
+                    aspect_mask: *aspect_mask,
+                    mip_level: level,
+                    base_array_layer: 0,
+                    layer_count: info.array_layers,
+                };
+                vk::ImageCopy {
+                    src_subresource: subresource,
+                    dst_subresource: subresource,
+                    extent: vk::Extent3D {
+                        width: (info.extent.width >> level).max(1),
+                        height: (info.extent.height >> level).max(1),
+                        depth: (info.extent.depth >> level).max(1),
+                    },
+                    ..Default::default()
+                }
+            })
+            .collect();
+        unsafe {
+            ctx.device.cmd_pipeline_barrier2(
+                ctx.command_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&[vk::ImageMemoryBarrier2 {
+                    dst_stage_mask: vk::PipelineStageFlags2::COPY,
+                    dst_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+                    old_layout: vk::ImageLayout::UNDEFINED,
+                    new_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    image: new_image,
+                    subresource_range: range,
+                    ..Default::default()
+                }]),
+            );
+            ctx.device.cmd_copy_image(
+                ctx.command_buffer,
+                old_image,
+                vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                new_image,
+                vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                &regions,
+            );
+            let barrier = copy_completed_barrier();
+            ctx.device.cmd_pipeline_barrier2(
+                ctx.command_buffer,
+                &vk::DependencyInfo::default().image_memory_barriers(&[vk::ImageMemoryBarrier2 {
+                    src_stage_mask: barrier.src_stage_mask,
+                    src_access_mask: barrier.src_access_mask,
+                    dst_stage_mask: barrier.dst_stage_mask,
+                    dst_access_mask: barrier.dst_access_mask,
+                    old_layout: vk::ImageLayout::TRANSFER_DST_OPTIMAL,
+                    new_layout: vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    image: new_image,
+                    subresource_range: range,
+                    ..Default::default()
+                }]),
+            );
+        }
+        (true, Some(RetiredRelocation::new(slot.clone(), old_image)))
+    }
+}
+
+/// Apply the move proposed for `image` by the [`Defragmenter`](crate::Defragmenter), if any.
+///
+/// The image is copied in the layout tracked by its [`GPUResource`] state, and the old image is
+/// retired once the frame completes. Returns true if the image was moved. The raw handle of the
+/// image changes in that case, so image views and descriptors referencing it must be recreated.
+#[must_use]
+pub fn relocate_image<S>(image: &mut S) -> RelocateImageFuture<'_, S>
+where
+    S: Unpin + GPUResource + Deref<Target = Image>,
+{
+    RelocateImageFuture {
+        image,
+        pending: false,
+    }
+}
+//endregion
+
+/// Makes the copied contents visible to everything that uses the new resource afterwards.
+fn copy_completed_barrier() -> vk::MemoryBarrier2<'static> {
+    vk::MemoryBarrier2 {
+        src_stage_mask: vk::PipelineStageFlags2::COPY,
+        src_access_mask: vk::AccessFlags2::TRANSFER_WRITE,
+        dst_stage_mask: vk::PipelineStageFlags2::ALL_COMMANDS,
+        dst_access_mask: vk::AccessFlags2::MEMORY_READ | vk::AccessFlags2::MEMORY_WRITE,
+        ..Default::default()
+    }
+}
@@ -1,8 +1,9 @@
 use crate::{
+    buffer::BufferLike,
     future::RecordContext,
-    pipeline::{GraphicsPipeline, Pipeline},
+    pipeline::{GraphicsPipeline, MeshShaderPlugin, Pipeline},
 };
-use ash::vk;
+use ash::{prelude::VkResult, vk};
 
 pub struct DynamicRenderPass<'a, 's> {
     ctx: &'s mut RecordContext<'a>,
@@ -107,6 +108,22 @@ impl DynamicRenderPass<'_, '_> {
         }
     }
 
+    pub fn bind_descriptor_sets(
+        &mut self,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        descriptor_sets: &[vk::DescriptorSet],
+        dynamic_offsets: &[u32],
+    ) {
+        self.ctx.bind_descriptor_sets(
+            vk::PipelineBindPoint::GRAPHICS,
+            layout,
+            first_set,
+            descriptor_sets,
+            dynamic_offsets,
+        );
+    }
+
     pub fn push_descriptor_set(
         &mut self,
         layout: vk::PipelineLayout,
@@ -146,4 +163,74 @@ impl DynamicRenderPass<'_, '_> {
             );
         }
     }
+
+    /// Requires the [`MeshShaderPlugin`](crate::pipeline::MeshShaderPlugin).
+    pub fn draw_mesh_tasks(&mut self, group_count_x: u32, group_count_y: u32, group_count_z: u32) {
+        unsafe {
+            self.ctx
+                .device
+                .extension::<ash::ext::mesh_shader::Meta>()
+                .cmd_draw_mesh_tasks(
+                    self.ctx.command_buffer,
+                    group_count_x,
+                    group_count_y,
+                    group_count_z,
+                );
+        }
+    }
+
+    /// Requires the [`MeshShaderPlugin`](crate::pipeline::MeshShaderPlugin). `buffer` contains
+    /// `draw_count` [`vk::DrawMeshTasksIndirectCommandEXT`]s.
+    pub fn draw_mesh_tasks_indirect(
+        &mut self,
+        buffer: &impl BufferLike,
+        draw_count: u32,
+        stride: u32,
+    ) {
+        unsafe {
+            self.ctx
+                .device
+                .extension::<ash::ext::mesh_shader::Meta>()
+                .cmd_draw_mesh_tasks_indirect(
+                    self.ctx.command_buffer,
+                    buffer.raw_buffer(),
+                    buffer.offset(),
+                    draw_count,
+                    stride,
+                );
+        }
+    }
+
+    /// Requires the [`MeshShaderPlugin`](crate::pipeline::MeshShaderPlugin). The draw count is
+    /// read from the first `u32` of `count_buffer`, and clamped to `max_draw_count`.
+    ///
+    /// Returns `ERROR_EXTENSION_NOT_PRESENT` without recording anything if the device doesn't
+    /// support indirect count draws. Check with
+    /// [`MeshShaderPlugin::supports_indirect_count`](crate::pipeline::MeshShaderPlugin::supports_indirect_count).
+    pub fn draw_mesh_tasks_indirect_count(
+        &mut self,
+        buffer: &impl BufferLike,
+        count_buffer: &impl BufferLike,
+        max_draw_count: u32,
+        stride: u32,
+    ) -> VkResult<()> {
+        if !MeshShaderPlugin::supports_indirect_count(self.ctx.device) {
+            return Err(vk::Result::ERROR_EXTENSION_NOT_PRESENT);
+        }
+        unsafe {
+            self.ctx
+                .device
+                .extension::<ash::ext::mesh_shader::Meta>()
+                .cmd_draw_mesh_tasks_indirect_count(
+                    self.ctx.command_buffer,
+                    buffer.raw_buffer(),
+                    buffer.offset(),
+                    count_buffer.raw_buffer(),
+                    count_buffer.offset(),
+                    max_draw_count,
+                    stride,
+                );
+        }
+        Ok(())
+    }
 }
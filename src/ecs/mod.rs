@@ -2,4 +2,5 @@ mod pass;
 mod system;
 
 pub use pass::RenderSystemsPass;
+pub(crate) use system::FRAMES_IN_FLIGHT;
 pub use system::{IntoRenderSystem, QueueSystemCtx, RenderSystemCtx};
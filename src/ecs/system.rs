@@ -57,6 +57,10 @@ impl<Returned> RenderSystemCtx<Returned> {
         self.returned_value.take()
     }
 }
+/// Number of frames a render system keeps in flight. Values retained by a frame are dropped
+/// once that many later frames were submitted.
+pub(crate) const FRAMES_IN_FLIGHT: usize = 3;
+
 struct RenderSystemFrame<Returned, Retained> {
     returned: Returned,
     retained: Retained,
@@ -68,7 +72,7 @@ pub struct RenderSystem<
 > {
     inner: T,
     future: Option<F>,
-    frames: RingBuffer<RenderSystemFrame<F::Returned, F::Retained>, 3>,
+    frames: RingBuffer<RenderSystemFrame<F::Returned, F::Retained>, FRAMES_IN_FLIGHT>,
     shared_state_component_id: ComponentId,
 
     /// If `queue_selector` is None, this must be valid upon initialization.
@@ -87,7 +91,7 @@ pub struct RenderSystem<
 pub(super) struct RenderSystemSharedState {
     /// The submission will take the command buffer and push it here.
     /// The prelude system is responsible for popping command buffers from this queue and await on them.
-    pending_command_buffers: RingBuffer<CommandBuffer<states::Pending>, 3>,
+    pending_command_buffers: RingBuffer<CommandBuffer<states::Pending>, FRAMES_IN_FLIGHT>,
 
     /// The prelude system will allocate and populate this command buffer.
     recording_command_buffer: Option<CommandBuffer<states::Recording>>,
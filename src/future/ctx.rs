@@ -13,6 +13,7 @@ struct GlobalResourceContext {
 pub enum BarrierContext<'a> {
     Barrier {
         queue_family_index: u32,
+        separate_depth_stencil_layouts: bool,
         memory_barrier: &'a mut vk::MemoryBarrier2<'static>,
         image_barrier: &'a mut Vec<vk::ImageMemoryBarrier2<'static>>,
         // The local resource state table
@@ -21,6 +22,7 @@ pub enum BarrierContext<'a> {
     },
     Record {
         queue_family_index: u32,
+        separate_depth_stencil_layouts: bool,
         resource_states: &'a mut ResourceStateTable,
     },
 }
@@ -63,36 +65,148 @@ impl<'a> BarrierContext<'a> {
         layout: vk::ImageLayout,
         discard_contents: bool,
     ) {
+        let aspect_mask = resource.subresource_range().aspect_mask;
+        let (layout, stencil_layout) =
+            if aspect_mask.contains(vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL) {
+                split_depth_stencil_layout(layout)
+            } else {
+                (layout, layout)
+            };
+        self.use_image_resource_with_layouts(
+            resource,
+            stage,
+            access,
+            layout,
+            stencil_layout,
+            discard_contents,
+        );
+    }
+
+    /// Use a depth stencil image with different layouts for the depth and the stencil aspects.
+    /// Without the `separateDepthStencilLayouts` feature, layouts that can't be expressed as one
+    /// of the combined layouts, such as `DEPTH_READ_ONLY_STENCIL_ATTACHMENT_OPTIMAL`, fall back
+    /// to `GENERAL`.
+    pub fn use_depth_stencil_image_resource<
+        I: ImageLike + ?Sized,
+        T: GPUResource + Deref<Target = I>,
+    >(
+        &mut self,
+        resource: &mut T,
+        stage: vk::PipelineStageFlags2,
+        access: vk::AccessFlags2,
+        depth_layout: vk::ImageLayout,
+        stencil_layout: vk::ImageLayout,
+        discard_contents: bool,
+    ) {
+        self.use_image_resource_with_layouts(
+            resource,
+            stage,
+            access,
+            depth_layout,
+            stencil_layout,
+            discard_contents,
+        );
+    }
+
+    fn use_image_resource_with_layouts<
+        I: ImageLike + ?Sized,
+        T: GPUResource + Deref<Target = I>,
+    >(
+        &mut self,
+        resource: &mut T,
+        stage: vk::PipelineStageFlags2,
+        access: vk::AccessFlags2,
+        layout: vk::ImageLayout,
+        stencil_layout: vk::ImageLayout,
+        discard_contents: bool,
+    ) {
+        let subresource_range = resource.subresource_range();
+        let separate_depth_stencil_layouts = match self {
+            Self::Barrier {
+                separate_depth_stencil_layouts,
+                ..
+            }
+            | Self::Record {
+                separate_depth_stencil_layouts,
+                ..
+            } => *separate_depth_stencil_layouts,
+        };
+        // Only depth stencil images track the stencil layout separately.
+        let (layout, stencil_layout) = if !subresource_range
+            .aspect_mask
+            .contains(vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL)
+        {
+            (layout, layout)
+        } else if separate_depth_stencil_layouts {
+            (layout, stencil_layout)
+        } else {
+            let layout = fallback_depth_stencil_layout(layout, stencil_layout);
+            (layout, layout)
+        };
         match self {
             Self::Barrier {
                 queue_family_index,
+                separate_depth_stencil_layouts: _,
                 memory_barrier,
                 image_barrier,
                 expected_resource_states: _,
                 resource_states,
             } => {
                 let old_state = resource.get_resource_state(&resource_states);
-                let had_image_layout_transfer = layout != old_state.layout;
+                let had_image_layout_transfer =
+                    layout != old_state.layout || stencil_layout != old_state.stencil_layout;
                 let had_queue_family_transfer = *queue_family_index != old_state.queue_family;
                 if had_image_layout_transfer || had_queue_family_transfer {
                     let memory_barrier = old_state.get_barrier(Access { stage, access }, true);
-                    image_barrier.push(vk::ImageMemoryBarrier2 {
+                    let (old_layout, old_stencil_layout) = if discard_contents {
+                        (vk::ImageLayout::UNDEFINED, vk::ImageLayout::UNDEFINED)
+                    } else {
+                        (old_state.layout, old_state.stencil_layout)
+                    };
+                    let barrier = vk::ImageMemoryBarrier2 {
                         dst_access_mask: memory_barrier.dst_access_mask,
                         src_access_mask: memory_barrier.src_access_mask,
                         dst_stage_mask: memory_barrier.dst_stage_mask,
                         src_stage_mask: memory_barrier.src_stage_mask,
-                        old_layout: if discard_contents {
-                            vk::ImageLayout::UNDEFINED
-                        } else {
-                            old_state.layout
-                        },
                         //src_queue_family_index: old_state.queue_family,
                         //dst_queue_family_index: self.queue_family_index,
-                        new_layout: layout,
                         image: resource.raw_image(),
-                        subresource_range: resource.subresource_range(),
+                        subresource_range,
                         ..Default::default()
-                    });
+                    };
+                    match (
+                        combine_depth_stencil_layout(old_layout, old_stencil_layout),
+                        combine_depth_stencil_layout(layout, stencil_layout),
+                    ) {
+                        (Some(old_layout), Some(new_layout)) => {
+                            image_barrier.push(vk::ImageMemoryBarrier2 {
+                                old_layout,
+                                new_layout,
+                                ..barrier
+                            });
+                        }
+                        _ => {
+                            // Transition the depth and the stencil aspects separately.
+                            image_barrier.push(vk::ImageMemoryBarrier2 {
+                                old_layout,
+                                new_layout: layout,
+                                subresource_range: vk::ImageSubresourceRange {
+                                    aspect_mask: vk::ImageAspectFlags::DEPTH,
+                                    ..subresource_range
+                                },
+                                ..barrier
+                            });
+                            image_barrier.push(vk::ImageMemoryBarrier2 {
+                                old_layout: old_stencil_layout,
+                                new_layout: stencil_layout,
+                                subresource_range: vk::ImageSubresourceRange {
+                                    aspect_mask: vk::ImageAspectFlags::STENCIL,
+                                    ..subresource_range
+                                },
+                                ..barrier
+                            });
+                        }
+                    }
                 } else {
                     let new_barrier = old_state.get_barrier(Access { stage, access }, false);
                     memory_barrier.src_access_mask |= new_barrier.src_access_mask;
@@ -104,10 +218,12 @@ impl<'a> BarrierContext<'a> {
             Self::Record {
                 resource_states,
                 queue_family_index,
+                ..
             } => {
                 let mut old_state = resource.get_resource_state(resource_states);
                 old_state.transition(Access { stage, access });
                 old_state.layout = layout;
+                old_state.stencil_layout = stencil_layout;
                 old_state.queue_family = *queue_family_index;
                 resource.set_resource_state(resource_states, old_state);
             }
@@ -115,6 +231,72 @@ impl<'a> BarrierContext<'a> {
     }
 }
 
+/// Split a layout into the layouts of the depth aspect and the stencil aspect.
+fn split_depth_stencil_layout(layout: vk::ImageLayout) -> (vk::ImageLayout, vk::ImageLayout) {
+    match layout {
+        vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL => (
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL,
+        ),
+        vk::ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL => (
+            vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::STENCIL_READ_ONLY_OPTIMAL,
+        ),
+        vk::ImageLayout::DEPTH_READ_ONLY_STENCIL_ATTACHMENT_OPTIMAL => (
+            vk::ImageLayout::DEPTH_READ_ONLY_OPTIMAL,
+            vk::ImageLayout::STENCIL_ATTACHMENT_OPTIMAL,
+        ),
+        vk::ImageLayout::DEPTH_ATTACHMENT_STENCIL_READ_ONLY_OPTIMAL => (
+            vk::ImageLayout::DEPTH_ATTACHMENT_OPTIMAL,
+            vk::ImageLayout::STENCIL_READ_ONLY_OPTIMAL,
+        ),
+        layout => (layout, layout),
+    }
+}
+
+/// The layout that covers both the depth and the stencil aspects, if there is one.
+fn combine_depth_stencil_layout(
+    depth_layout: vk::ImageLayout,
+    stencil_layout: vk::ImageLayout,
+) -> Option<vk::ImageLayout> {
+    use vk::ImageLayout as L;
+    match (depth_layout, stencil_layout) {
+        (L::DEPTH_ATTACHMENT_OPTIMAL, L::STENCIL_ATTACHMENT_OPTIMAL) => {
+            Some(L::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+        }
+        (L::DEPTH_READ_ONLY_OPTIMAL, L::STENCIL_READ_ONLY_OPTIMAL) => {
+            Some(L::DEPTH_STENCIL_READ_ONLY_OPTIMAL)
+        }
+        (L::DEPTH_READ_ONLY_OPTIMAL, L::STENCIL_ATTACHMENT_OPTIMAL) => {
+            Some(L::DEPTH_READ_ONLY_STENCIL_ATTACHMENT_OPTIMAL)
+        }
+        (L::DEPTH_ATTACHMENT_OPTIMAL, L::STENCIL_READ_ONLY_OPTIMAL) => {
+            Some(L::DEPTH_ATTACHMENT_STENCIL_READ_ONLY_OPTIMAL)
+        }
+        (depth_layout, stencil_layout) if depth_layout == stencil_layout => Some(depth_layout),
+        _ => None,
+    }
+}
+
+/// A layout covering both aspects of a depth stencil image that does not require the
+/// `separateDepthStencilLayouts` feature.
+fn fallback_depth_stencil_layout(
+    depth_layout: vk::ImageLayout,
+    stencil_layout: vk::ImageLayout,
+) -> vk::ImageLayout {
+    use vk::ImageLayout as L;
+    match combine_depth_stencil_layout(depth_layout, stencil_layout) {
+        Some(L::DEPTH_ATTACHMENT_OPTIMAL | L::STENCIL_ATTACHMENT_OPTIMAL) => {
+            L::DEPTH_STENCIL_ATTACHMENT_OPTIMAL
+        }
+        Some(L::DEPTH_READ_ONLY_OPTIMAL | L::STENCIL_READ_ONLY_OPTIMAL) => {
+            L::DEPTH_STENCIL_READ_ONLY_OPTIMAL
+        }
+        Some(layout) => layout,
+        None => L::GENERAL,
+    }
+}
+
 pub struct RecordContext<'a> {
     pub device: &'a Device,
     pub command_buffer: vk::CommandBuffer,
@@ -125,6 +307,7 @@ pub struct RecordContext<'a> {
 pub struct GPUFutureContext {
     device: Device,
     queue_family_index: u32,
+    separate_depth_stencil_layouts: bool,
     pub(crate) command_buffer: vk::CommandBuffer,
 
     pub(crate) memory_barrier: vk::MemoryBarrier2<'static>,
@@ -139,10 +322,14 @@ impl GPUFutureContext {
         command_buffer: vk::CommandBuffer,
         queue_family_index: u32,
     ) -> Self {
+        let separate_depth_stencil_layouts = device
+            .feature::<vk::PhysicalDeviceSeparateDepthStencilLayoutsFeatures>()
+            .is_some_and(|f| f.separate_depth_stencil_layouts == vk::TRUE);
         Self {
             device,
             command_buffer,
             queue_family_index,
+            separate_depth_stencil_layouts,
             memory_barrier: vk::MemoryBarrier2::default(),
             image_barrier: Vec::new(),
             expected_resource_states: Default::default(),
@@ -171,6 +358,7 @@ impl GPUFutureContext {
     pub(crate) fn barrier_ctx_barrier(&mut self) -> BarrierContext {
         BarrierContext::Barrier {
             queue_family_index: self.queue_family_index,
+            separate_depth_stencil_layouts: self.separate_depth_stencil_layouts,
             memory_barrier: &mut self.memory_barrier,
             image_barrier: &mut self.image_barrier,
             expected_resource_states: &mut self.expected_resource_states,
@@ -180,6 +368,7 @@ impl GPUFutureContext {
     pub(crate) fn barrier_ctx_record(&mut self) -> BarrierContext {
         BarrierContext::Record {
             queue_family_index: self.queue_family_index,
+            separate_depth_stencil_layouts: self.separate_depth_stencil_layouts,
             resource_states: &mut self.resource_states,
         }
     }
@@ -8,6 +8,9 @@ pub struct ResourceState {
     pub write: Access,
     pub queue_family: u32,
     pub layout: vk::ImageLayout,
+    /// Layout of the stencil aspect for depth stencil images.
+    /// For all other images, this is always the same as `layout`.
+    pub stencil_layout: vk::ImageLayout,
 }
 impl Default for ResourceState {
     fn default() -> Self {
@@ -16,6 +19,7 @@ impl Default for ResourceState {
             write: Default::default(),
             queue_family: u32::MAX,
             layout: vk::ImageLayout::default(),
+            stencil_layout: vk::ImageLayout::default(),
         }
     }
 }
@@ -97,6 +101,10 @@ impl<T> GPUBorrowedResource<T> {
             state: Default::default(),
         }
     }
+    /// See [`GPUBorrowed::get_mut`].
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        self.item.get_mut()
+    }
 }
 
 #[derive(Default, Clone, Debug)]
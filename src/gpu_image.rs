@@ -0,0 +1,478 @@
+use std::sync::Arc;
+
+use ash::{prelude::VkResult, vk};
+use bevy::{
+    app::{App, Plugin, PostUpdate},
+    asset::{AssetEvent, AssetId, Assets},
+    ecs::{
+        event::EventReader,
+        schedule::{IntoSystemConfigs, SystemSet},
+        system::{Res, ResMut, Resource},
+    },
+    math::{IVec3, UVec3},
+    utils::{HashMap, HashSet},
+};
+use wgpu_types::{TextureDimension, TextureUsages, TextureViewDimension};
+
+use crate::{
+    buffer::{Buffer, StagingBelt},
+    commands::copy_buffer_to_image,
+    ecs::IntoRenderSystem,
+    future::{gpu_future, GPUBorrowedResource, GPUFutureBlock, GPUOwnedResource},
+    image::mip_extent,
+    selectors::Graphics,
+    utils::texture_format_to_vk,
+    Allocator, HasDevice, Image, ImageExt, ImageLike, ImageView, ImageViewLike, Sampler,
+    SamplerCache, SamplerDesc,
+};
+
+/// Uploads [`bevy::image::Image`] assets to the GPU, and keeps the uploaded images in [`GpuImages`].
+///
+/// Images are re-uploaded when the asset was modified, and freed once the asset was removed and the
+/// GPU has finished using them.
+pub struct GpuImagePlugin;
+
+/// The systems preparing and uploading the images.
+/// Render systems sampling from [`GpuImages`] should run after this set.
+#[derive(Debug, Hash, PartialEq, Eq, Clone, SystemSet)]
+pub struct GpuImageUploadSet;
+
+impl Plugin for GpuImagePlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<GpuImages>();
+        app.add_systems(
+            PostUpdate,
+            (
+                prepare_gpu_images,
+                upload_gpu_images
+                    .into_render_system::<Graphics>()
+                    .after(prepare_gpu_images),
+            )
+                .in_set(GpuImageUploadSet),
+        );
+    }
+}
+
+/// A device image together with a view of the whole image.
+/// Created for [`bevy::image::Image`] assets and by the loaders in [`crate::texture`].
+pub struct GpuImage {
+    view: ImageView<Arc<Image>>,
+    sampler: Option<Arc<Sampler>>,
+}
+
+impl GpuImage {
+    fn new(allocator: Allocator, asset: &bevy::image::Image) -> Option<VkResult<Self>> {
+        let info = image_create_info(asset)?;
+        let view_type = asset
+            .texture_view_descriptor
+            .as_ref()
+            .and_then(|desc| desc.dimension)
+            .map(|dimension| match dimension {
+                TextureViewDimension::D1 => vk::ImageViewType::TYPE_1D,
+                TextureViewDimension::D2 => vk::ImageViewType::TYPE_2D,
+                TextureViewDimension::D2Array => vk::ImageViewType::TYPE_2D_ARRAY,
+                TextureViewDimension::Cube => vk::ImageViewType::CUBE,
+                TextureViewDimension::CubeArray => vk::ImageViewType::CUBE_ARRAY,
+                TextureViewDimension::D3 => vk::ImageViewType::TYPE_3D,
+            });
+        Some(Self::from_create_info(allocator, &info, view_type))
+    }
+    /// Create the image and a view of the whole image. The view type is inferred when `None`.
+    pub(crate) fn from_create_info(
+        allocator: Allocator,
+        info: &vk::ImageCreateInfo,
+        view_type: Option<vk::ImageViewType>,
+    ) -> VkResult<Self> {
+        let image = Arc::new(Image::new_device_image(allocator, info)?);
+        let mut view = image.view_builder();
+        if let Some(view_type) = view_type {
+            view = view.view_type(view_type);
+        }
+        let view = view.build()?;
+        Ok(Self {
+            view,
+            sampler: None,
+        })
+    }
+    pub fn image(&self) -> &Image {
+        self.view.image()
+    }
+    pub fn view(&self) -> &ImageView<Arc<Image>> {
+        &self.view
+    }
+    /// The sampler specified by the asset. Textures loaded from files don't specify a sampler.
+    pub fn sampler(&self) -> Option<&Arc<Sampler>> {
+        self.sampler.as_ref()
+    }
+    /// Whether the asset can be uploaded into this image without recreating it.
+    fn matches(&self, asset: &bevy::image::Image) -> bool {
+        let Some(info) = image_create_info(asset) else {
+            return false;
+        };
+        self.image().format() == info.format
+            && self.image().extent()
+                == UVec3::new(info.extent.width, info.extent.height, info.extent.depth)
+            && self.image().mip_levels() == info.mip_levels
+            && self.image().array_layers() == info.array_layers
+            && self.image().create_flags() == info.flags
+            && self.image().usage() == info.usage
+    }
+}
+
+impl ImageLike for GpuImage {
+    fn raw_image(&self) -> vk::Image {
+        self.image().raw_image()
+    }
+    fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        self.image().subresource_range()
+    }
+    fn extent(&self) -> UVec3 {
+        self.image().extent()
+    }
+    fn offset(&self) -> IVec3 {
+        self.image().offset()
+    }
+    fn format(&self) -> vk::Format {
+        self.image().format()
+    }
+    fn create_flags(&self) -> vk::ImageCreateFlags {
+        self.image().create_flags()
+    }
+    fn image_type(&self) -> vk::ImageType {
+        self.image().image_type()
+    }
+}
+impl ImageViewLike for GpuImage {
+    fn raw_image_view(&self) -> vk::ImageView {
+        self.view.raw_image_view()
+    }
+}
+impl HasDevice for GpuImage {
+    fn device(&self) -> &crate::Device {
+        self.image().device()
+    }
+}
+
+/// Maps [`bevy::image::Image`] assets to their uploaded [`GpuImage`].
+#[derive(Resource, Default)]
+pub struct GpuImages {
+    images: HashMap<AssetId<bevy::image::Image>, GPUBorrowedResource<GpuImage>>,
+    /// Images which were created but never written to.
+    uninitialized: HashSet<AssetId<bevy::image::Image>>,
+    /// Images waiting to be uploaded, in the order of their asset events.
+    pending_uploads: Vec<AssetId<bevy::image::Image>>,
+    /// Images no longer in use by the app. They will be freed once the GPU finishes using them.
+    retired: Vec<GPUBorrowedResource<GpuImage>>,
+}
+
+impl GpuImages {
+    /// Returns `None` if the image hasn't been uploaded yet.
+    pub fn get(
+        &self,
+        id: impl Into<AssetId<bevy::image::Image>>,
+    ) -> Option<&GPUBorrowedResource<GpuImage>> {
+        let id = id.into();
+        if self.uninitialized.contains(&id) {
+            return None;
+        }
+        self.images.get(&id)
+    }
+    /// Returns `None` if the image hasn't been uploaded yet.
+    pub fn get_mut(
+        &mut self,
+        id: impl Into<AssetId<bevy::image::Image>>,
+    ) -> Option<&mut GPUBorrowedResource<GpuImage>> {
+        let id = id.into();
+        if self.uninitialized.contains(&id) {
+            return None;
+        }
+        self.images.get_mut(&id)
+    }
+    fn remove(&mut self, id: AssetId<bevy::image::Image>) {
+        self.uninitialized.remove(&id);
+        self.pending_uploads.retain(|pending| *pending != id);
+        if let Some(image) = self.images.remove(&id) {
+            self.retired.push(image);
+        }
+    }
+}
+
+fn image_create_info(asset: &bevy::image::Image) -> Option<vk::ImageCreateInfo<'static>> {
+    let desc = &asset.texture_descriptor;
+    let format = texture_format_to_vk(desc.format)?;
+    let (image_type, depth, array_layers) = match desc.dimension {
+        TextureDimension::D1 => (vk::ImageType::TYPE_1D, 1, desc.size.depth_or_array_layers),
+        TextureDimension::D2 => (vk::ImageType::TYPE_2D, 1, desc.size.depth_or_array_layers),
+        TextureDimension::D3 => (vk::ImageType::TYPE_3D, desc.size.depth_or_array_layers, 1),
+    };
+    let mut flags = vk::ImageCreateFlags::empty();
+    if matches!(
+        asset
+            .texture_view_descriptor
+            .as_ref()
+            .and_then(|desc| desc.dimension),
+        Some(TextureViewDimension::Cube | TextureViewDimension::CubeArray)
+    ) {
+        flags |= vk::ImageCreateFlags::CUBE_COMPATIBLE;
+    }
+    let mut usage = vk::ImageUsageFlags::TRANSFER_DST;
+    for (texture_usage, image_usage) in [
+        (TextureUsages::COPY_SRC, vk::ImageUsageFlags::TRANSFER_SRC),
+        (TextureUsages::TEXTURE_BINDING, vk::ImageUsageFlags::SAMPLED),
+        (TextureUsages::STORAGE_BINDING, vk::ImageUsageFlags::STORAGE),
+        (
+            TextureUsages::RENDER_ATTACHMENT,
+            vk::ImageUsageFlags::COLOR_ATTACHMENT,
+        ),
+    ] {
+        if desc.usage.contains(texture_usage) {
+            usage |= image_usage;
+        }
+    }
+    Some(vk::ImageCreateInfo {
+        flags,
+        image_type,
+        format,
+        extent: vk::Extent3D {
+            width: desc.size.width,
+            height: desc.size.height,
+            depth,
+        },
+        mip_levels: desc.mip_level_count,
+        array_layers,
+        samples: vk::SampleCountFlags::from_raw(desc.sample_count),
+        tiling: vk::ImageTiling::OPTIMAL,
+        usage,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        ..Default::default()
+    })
+}
+
+/// Computes the regions to copy for the asset data, laid out layer by layer with all mip levels of
+/// each layer in sequence. Buffer offsets are relative to the start of the asset data.
+/// Returns the regions and the total size of the data.
+fn copy_regions(asset: &bevy::image::Image) -> (Vec<vk::BufferImageCopy>, vk::DeviceSize) {
+    let desc = &asset.texture_descriptor;
+    let (block_width, block_height) = desc.format.block_dimensions();
+    let block_size = desc.format.block_copy_size(None).unwrap() as u64;
+    let info = image_create_info(asset).unwrap();
+    let extent = UVec3::new(info.extent.width, info.extent.height, info.extent.depth);
+
+    let mut regions = Vec::with_capacity((info.array_layers * info.mip_levels) as usize);
+    let mut offset = 0;
+    for layer in 0..info.array_layers {
+        for level in 0..info.mip_levels {
+            let mip_extent = mip_extent(extent, level);
+            regions.push(vk::BufferImageCopy {
+                buffer_offset: offset,
+                buffer_row_length: 0,
+                buffer_image_height: 0,
+                image_subresource: vk::ImageSubresourceLayers {
+                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    mip_level: level,
+                    base_array_layer: layer,
+                    layer_count: 1,
+                },
+                image_offset: vk::Offset3D::default(),
+                image_extent: vk::Extent3D {
+                    width: mip_extent.x,
+                    height: mip_extent.y,
+                    depth: mip_extent.z,
+                },
+            });
+            offset += mip_extent.x.div_ceil(block_width) as u64
+                * mip_extent.y.div_ceil(block_height) as u64
+                * mip_extent.z as u64
+                * block_size;
+        }
+    }
+    (regions, offset)
+}
+
+fn prepare_gpu_images(
+    mut events: EventReader<AssetEvent<bevy::image::Image>>,
+    assets: Res<Assets<bevy::image::Image>>,
+    allocator: Res<Allocator>,
+    samplers: Res<SamplerCache>,
+    mut gpu_images: ResMut<GpuImages>,
+) {
+    let gpu_images = gpu_images.into_inner();
+    for event in events.read() {
+        match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
+                let Some(asset) = assets.get(*id) else {
+                    continue;
+                };
+                let sampler = match samplers.get(&SamplerDesc::from(&asset.sampler)) {
+                    Ok(sampler) => sampler,
+                    Err(err) => {
+                        tracing::error!(?id, ?err, "Failed to create sampler");
+                        continue;
+                    }
+                };
+                if let Some(existing) = gpu_images
+                    .images
+                    .get_mut(id)
+                    .filter(|existing| existing.matches(asset))
+                {
+                    existing.sampler = Some(sampler);
+                    // Upload into the existing image. The old contents remain valid until then.
+                    if !gpu_images.pending_uploads.contains(id) {
+                        gpu_images.pending_uploads.push(*id);
+                    }
+                    continue;
+                }
+                gpu_images.remove(*id);
+                let mut image = match GpuImage::new(allocator.clone(), asset) {
+                    Some(Ok(image)) => image,
+                    Some(Err(err)) => {
+                        tracing::error!(?id, ?err, "Failed to create GPU image");
+                        continue;
+                    }
+                    None => {
+                        tracing::warn!(
+                            ?id,
+                            format = ?asset.texture_descriptor.format,
+                            "Image format is not supported"
+                        );
+                        continue;
+                    }
+                };
+                image.sampler = Some(sampler);
+                gpu_images
+                    .images
+                    .insert(*id, GPUBorrowedResource::new(image));
+                if asset.data.is_empty() {
+                    // Render targets and other images without initial data.
+                    continue;
+                }
+                gpu_images.uninitialized.insert(*id);
+                gpu_images.pending_uploads.push(*id);
+            }
+            AssetEvent::Removed { id } => {
+                gpu_images.remove(*id);
+            }
+            _ => (),
+        }
+    }
+}
+
+struct UploadBatch {
+    retired: Vec<GPUBorrowedResource<GpuImage>>,
+    /// The assets to upload, with the regions to copy and the offset of the asset data in the staging buffer.
+    uploads: Vec<(AssetId<bevy::image::Image>, Vec<vk::BufferImageCopy>, u64)>,
+    size: vk::DeviceSize,
+    /// An asset larger than a chunk of the staging belt, uploaded through its own staging buffer.
+    dedicated: Option<(
+        AssetId<bevy::image::Image>,
+        Vec<vk::BufferImageCopy>,
+        Buffer,
+    )>,
+}
+
+fn upload_gpu_images<'w, 's>(
+    gpu_images: ResMut<'w, GpuImages>,
+    assets: Res<'w, Assets<bevy::image::Image>>,
+    allocator: Res<'w, Allocator>,
+    mut staging_belt: ResMut<'w, StagingBelt>,
+) -> impl GPUFutureBlock + use<'w, 's> {
+    let gpu_images = gpu_images.into_inner();
+
+    // Upload as many images as fit into one chunk of the staging belt. The rest waits for the next frame.
+    // Images larger than a chunk are uploaded one per frame through a dedicated staging buffer.
+    let mut batch = UploadBatch {
+        retired: std::mem::take(&mut gpu_images.retired),
+        uploads: Vec::new(),
+        size: 0,
+        dedicated: None,
+    };
+    let budget = staging_belt.chunk_size();
+    gpu_images.pending_uploads.retain(|id| {
+        let Some(asset) = assets.get(*id) else {
+            // The asset was removed. The Removed event will free the image.
+            return false;
+        };
+        let (regions, size) = copy_regions(asset);
+        if asset.data.len() as u64 != size {
+            tracing::warn!(
+                ?id,
+                expected = size,
+                actual = asset.data.len(),
+                "Image data size does not match its texture descriptor"
+            );
+            return false;
+        }
+        if size > budget {
+            if batch.dedicated.is_some() {
+                return true;
+            }
+            let buffer = Buffer::new_host(
+                allocator.clone(),
+                size,
+                16,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+            )
+            .and_then(|mut buffer| {
+                buffer.as_slice_mut().copy_from_slice(&asset.data);
+                buffer.flush()?;
+                Ok(buffer)
+            });
+            match buffer {
+                Ok(buffer) => batch.dedicated = Some((*id, regions, buffer)),
+                Err(err) => tracing::error!(?id, size, ?err, "Failed to create staging buffer"),
+            }
+            return false;
+        }
+        // Buffer offsets must be a multiple of the texel block size and 4.
+        let offset = batch.size.next_multiple_of(16);
+        if offset + size > budget {
+            return true;
+        }
+        batch.size = offset + size;
+        batch.uploads.push((*id, regions, offset));
+        false
+    });
+    let batch =
+        (!batch.retired.is_empty() || !batch.uploads.is_empty() || batch.dedicated.is_some())
+            .then_some(batch);
+
+    gpu_future! { move
+        let Some(UploadBatch { retired, mut uploads, size, dedicated }) = batch else {
+            return;
+        };
+        // Keep the retired images alive until the GPU has finished using them.
+        retain!(retired);
+        if let Some((id, regions, buffer)) = dedicated {
+            if let Some(image) = gpu_images.images.get_mut(&id) {
+                let mut buffer = GPUOwnedResource::new(retain!(buffer));
+                copy_buffer_to_image(&mut buffer, image)
+                    .with_regions(&regions)
+                    .discard_image_contents()
+                    .await;
+                gpu_images.uninitialized.remove(&id);
+            }
+        }
+        if uploads.is_empty() {
+            return;
+        }
+        let mut staging_buffer = staging_belt.allocate_buffer(size, 16);
+        for (id, regions, offset) in uploads.iter_mut() {
+            let data = &assets.get(*id).unwrap().data;
+            staging_buffer[*offset as usize..*offset as usize + data.len()].copy_from_slice(data);
+            for region in regions.iter_mut() {
+                region.buffer_offset += staging_buffer.offset + *offset;
+            }
+        }
+        let mut staging_buffer = GPUOwnedResource::new(retain!(staging_buffer));
+        for (id, regions, _) in uploads.iter() {
+            let Some(image) = gpu_images.images.get_mut(id) else {
+                continue;
+            };
+            copy_buffer_to_image(&mut staging_buffer, image)
+                .with_regions(regions)
+                .discard_image_contents()
+                .await;
+            gpu_images.uninitialized.remove(id);
+        }
+    }
+}
@@ -1,9 +1,13 @@
-use std::mem::offset_of;
+use std::{mem::offset_of, ops::Range, sync::Arc};
 
 use ash::{prelude::VkResult, vk};
 use bevy::math::{IVec3, UVec3};
 
-use crate::{Allocator, HasDevice};
+use crate::{
+    alloc::{RelocationKind, RelocationSlot},
+    utils::{format_aspect_mask, Format},
+    Allocator, AllocatorPool, DefragmentationError, Defragmenter, Device, HasDevice,
+};
 use vk_mem::Alloc;
 
 pub trait ImageLike: Send + Sync + 'static {
@@ -14,6 +18,14 @@ pub trait ImageLike: Send + Sync + 'static {
         IVec3::ZERO
     }
     fn format(&self) -> vk::Format;
+    /// The flags that the image was created with.
+    fn create_flags(&self) -> vk::ImageCreateFlags {
+        vk::ImageCreateFlags::empty()
+    }
+    /// The type that the image was created with.
+    fn image_type(&self) -> vk::ImageType {
+        vk::ImageType::TYPE_2D
+    }
 }
 
 pub trait ImageExt: ImageLike + Sized {
@@ -34,32 +46,53 @@ pub trait ImageExt: ImageLike + Sized {
     where
         Self: HasDevice,
     {
-        let view = ImageWithView {
-            view: unsafe {
-                self.device().create_image_view(
-                    &vk::ImageViewCreateInfo {
-                        image: self.raw_image(),
-                        view_type: vk::ImageViewType::TYPE_2D,
-                        format: self.format(),
-                        components: vk::ComponentMapping {
-                            r: vk::ComponentSwizzle::R,
-                            g: vk::ComponentSwizzle::G,
-                            b: vk::ComponentSwizzle::B,
-                            a: vk::ComponentSwizzle::A,
-                        },
-                        subresource_range: self.subresource_range(),
-                        ..Default::default()
-                    },
-                    None,
-                )?
-            },
-            image: self,
-        };
-        Ok(view)
+        let view = self.view_builder().create_raw()?;
+        Ok(ImageWithView { view, image: self })
+    }
+
+    /// Create views with a custom view type, swizzle, subresource range or format.
+    /// Any number of views may be created for one image.
+    fn view_builder(&self) -> ImageViewBuilder<'_, Self>
+    where
+        Self: HasDevice,
+    {
+        ImageViewBuilder::new(self)
+    }
+
+    /// A view of a single mip level of the image.
+    fn mip(self, level: u32) -> ImageSubresource<Self> {
+        let mut range = self.subresource_range();
+        assert!(level < range.level_count, "mip level out of range");
+        range.base_mip_level += level;
+        range.level_count = 1;
+        ImageSubresource {
+            extent: mip_extent(self.extent(), level),
+            offset: self.offset() >> level as i32,
+            inner: self,
+            range,
+        }
+    }
+
+    /// A view of a single array layer of the image.
+    fn layer(self, layer: u32) -> ImageSubresource<Self> {
+        let mut range = self.subresource_range();
+        assert!(layer < range.layer_count, "array layer out of range");
+        range.base_array_layer += layer;
+        range.layer_count = 1;
+        ImageSubresource {
+            extent: self.extent(),
+            offset: self.offset(),
+            inner: self,
+            range,
+        }
     }
 }
 impl<T> ImageExt for T where T: ImageLike {}
 
+pub(crate) fn mip_extent(extent: UVec3, level: u32) -> UVec3 {
+    (extent >> level).max(UVec3::ONE)
+}
+
 pub struct ImageSubregion<T: ImageLike> {
     inner: T,
     extent: UVec3,
@@ -90,24 +123,96 @@ impl<T: ImageLike> ImageLike for ImageSubregion<T> {
     fn format(&self) -> vk::Format {
         self.inner.format()
     }
+
+    fn create_flags(&self) -> vk::ImageCreateFlags {
+        self.inner.create_flags()
+    }
+
+    fn image_type(&self) -> vk::ImageType {
+        self.inner.image_type()
+    }
 }
+/// A subset of the mip levels and array layers of an image.
+/// Created with [`ImageExt::mip`] and [`ImageExt::layer`].
+pub struct ImageSubresource<T: ImageLike> {
+    inner: T,
+    range: vk::ImageSubresourceRange,
+    extent: UVec3,
+    offset: IVec3,
+}
+impl<T: ImageLike> ImageSubresource<T> {
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+impl<T: ImageLike> ImageLike for ImageSubresource<T> {
+    fn raw_image(&self) -> vk::Image {
+        self.inner.raw_image()
+    }
+
+    fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        self.range
+    }
+
+    fn extent(&self) -> UVec3 {
+        self.extent
+    }
+
+    fn offset(&self) -> IVec3 {
+        self.offset
+    }
+
+    fn format(&self) -> vk::Format {
+        self.inner.format()
+    }
+
+    fn create_flags(&self) -> vk::ImageCreateFlags {
+        self.inner.create_flags()
+    }
+
+    fn image_type(&self) -> vk::ImageType {
+        self.inner.image_type()
+    }
+}
+impl<T: ImageLike + HasDevice> HasDevice for ImageSubresource<T> {
+    fn device(&self) -> &crate::Device {
+        self.inner.device()
+    }
+}
+
 pub trait ImageViewLike: ImageLike {
     fn raw_image_view(&self) -> vk::ImageView;
 }
 
-/// A regular image with 1 mip level and 1 layer fully backed by memory
+/// A regular image fully backed by memory
 pub struct Image {
     allocator: Allocator,
     image: vk::Image,
     allocation: vk_mem::Allocation,
     extent: UVec3,
     format: vk::Format,
+    image_type: vk::ImageType,
+    mip_levels: u32,
+    array_layers: u32,
+    samples: vk::SampleCountFlags,
+    usage: vk::ImageUsageFlags,
+    flags: vk::ImageCreateFlags,
+    /// Keeps the pool alive for images allocated from a pool.
+    pool: Option<AllocatorPool>,
+    /// Set when the image opted into defragmentation. Holds the current handle.
+    relocation: Option<Arc<RelocationSlot>>,
 }
 impl Drop for Image {
     fn drop(&mut self) {
+        // A defragmentation pass in progress takes over the allocation.
+        let handed_over = self.relocation.as_ref().is_some_and(|slot| slot.release());
+        let image = self.raw();
         unsafe {
-            self.allocator
-                .destroy_image(self.image, &mut self.allocation);
+            if handed_over {
+                self.allocator.device().destroy_image(image, None);
+            } else {
+                self.allocator.destroy_image(image, &mut self.allocation);
+            }
         }
     }
 }
@@ -126,33 +231,128 @@ impl Image {
                     ..Default::default()
                 },
             )?;
-            Ok(Self {
-                extent: UVec3::new(info.extent.width, info.extent.height, info.extent.depth),
-                allocator,
-                image,
-                allocation,
-                format: info.format,
-            })
+            Ok(Self::from_create_info(allocator, image, allocation, info))
+        }
+    }
+    fn from_create_info(
+        allocator: Allocator,
+        image: vk::Image,
+        allocation: vk_mem::Allocation,
+        info: &vk::ImageCreateInfo,
+    ) -> Self {
+        Self {
+            extent: UVec3::new(info.extent.width, info.extent.height, info.extent.depth),
+            allocator,
+            image,
+            allocation,
+            format: info.format,
+            image_type: info.image_type,
+            mip_levels: info.mip_levels,
+            array_layers: info.array_layers,
+            samples: info.samples,
+            usage: info.usage,
+            flags: info.flags,
+            pool: None,
+            relocation: None,
+        }
+    }
+    /// Create a new image in a custom [`AllocatorPool`].
+    pub fn new_in_pool(pool: &AllocatorPool, info: &vk::ImageCreateInfo) -> VkResult<Self> {
+        unsafe {
+            let (image, allocation) = pool
+                .raw()
+                .create_image(info, &vk_mem::AllocationCreateInfo::default())?;
+            let mut this =
+                Self::from_create_info(pool.allocator().clone(), image, allocation, info);
+            this.pool = Some(pool.clone());
+            Ok(this)
         }
     }
     pub fn extent(&self) -> UVec3 {
         self.extent
     }
+    /// The extent of the given mip level.
+    pub fn mip_extent(&self, level: u32) -> UVec3 {
+        assert!(level < self.mip_levels, "mip level out of range");
+        mip_extent(self.extent, level)
+    }
+    pub fn image_type(&self) -> vk::ImageType {
+        self.image_type
+    }
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+    pub fn samples(&self) -> vk::SampleCountFlags {
+        self.samples
+    }
+    pub fn usage(&self) -> vk::ImageUsageFlags {
+        self.usage
+    }
+    pub(crate) fn relocation_slot(&self) -> Option<&Arc<RelocationSlot>> {
+        self.relocation.as_ref()
+    }
     pub fn raw(&self) -> vk::Image {
-        self.image
+        match self.relocation.as_ref() {
+            Some(relocation) => relocation.handle(),
+            None => self.image,
+        }
+    }
+    /// Allow the [`Defragmenter`] to move this image to a different memory location.
+    ///
+    /// `info` must be the create info that the image was created with. Moves are only applied
+    /// when the owner awaits [`relocate_image`](crate::commands::relocate_image). The raw handle
+    /// of the image changes when that future returns true, so image views and descriptors
+    /// referencing the image must be recreated. Concurrent and host visible images cannot be
+    /// moved.
+    pub fn enable_defragmentation(
+        &mut self,
+        defragmenter: &Defragmenter,
+        info: &vk::ImageCreateInfo,
+    ) -> Result<(), DefragmentationError> {
+        assert_eq!(info.format, self.format);
+        if info.sharing_mode != vk::SharingMode::EXCLUSIVE {
+            return Err(DefragmentationError::ConcurrentImage);
+        }
+        let memory_properties = self
+            .allocator
+            .get_allocation_memory_properties(&self.allocation);
+        if memory_properties.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            return Err(DefragmentationError::HostVisible);
+        }
+        if self.relocation.is_some() {
+            return Ok(());
+        }
+        let info = vk::ImageCreateInfo {
+            p_next: std::ptr::null(),
+            queue_family_index_count: 0,
+            p_queue_family_indices: std::ptr::null(),
+            _marker: std::marker::PhantomData,
+            ..*info
+        };
+        let aspect_mask = self.subresource_range().aspect_mask;
+        let slot = defragmenter.register(
+            &mut self.allocation,
+            vk::Handle::as_raw(self.image),
+            RelocationKind::Image { info, aspect_mask },
+        );
+        self.relocation = Some(slot);
+        Ok(())
     }
 }
 impl ImageLike for Image {
     fn raw_image(&self) -> vk::Image {
-        self.image
+        self.raw()
     }
     fn subresource_range(&self) -> vk::ImageSubresourceRange {
         vk::ImageSubresourceRange {
-            aspect_mask: vk::ImageAspectFlags::COLOR,
+            aspect_mask: format_aspect_mask(self.format),
             base_mip_level: 0,
-            level_count: 1,
+            level_count: self.mip_levels,
             base_array_layer: 0,
-            layer_count: 1,
+            layer_count: self.array_layers,
         }
     }
     fn extent(&self) -> UVec3 {
@@ -161,6 +361,286 @@ impl ImageLike for Image {
     fn format(&self) -> vk::Format {
         self.format
     }
+    fn create_flags(&self) -> vk::ImageCreateFlags {
+        self.flags
+    }
+    fn image_type(&self) -> vk::ImageType {
+        self.image_type
+    }
+}
+
+pub struct ImageViewBuilder<'a, T: ImageLike + HasDevice> {
+    image: &'a T,
+    view_type: Option<vk::ImageViewType>,
+    format: vk::Format,
+    components: vk::ComponentMapping,
+    subresource_range: vk::ImageSubresourceRange,
+}
+
+impl<'a, T: ImageLike + HasDevice> ImageViewBuilder<'a, T> {
+    fn new(image: &'a T) -> Self {
+        Self {
+            image,
+            view_type: None,
+            format: image.format(),
+            components: vk::ComponentMapping::default(),
+            subresource_range: image.subresource_range(),
+        }
+    }
+    /// Defaults to the view type matching the image type, using the array view types for views
+    /// with multiple array layers.
+    pub fn view_type(mut self, view_type: vk::ImageViewType) -> Self {
+        self.view_type = Some(view_type);
+        self
+    }
+    /// Reinterpret the image with a different format.
+    /// The image must have been created with `MUTABLE_FORMAT`, and the two formats must be compatible.
+    pub fn format(mut self, format: vk::Format) -> Self {
+        self.format = format;
+        self
+    }
+    pub fn components(mut self, components: vk::ComponentMapping) -> Self {
+        self.components = components;
+        self
+    }
+    /// Narrow the view to the given mip levels, relative to the subresource range of the image.
+    pub fn mip_levels(mut self, levels: Range<u32>) -> Self {
+        let range = self.image.subresource_range();
+        assert!(levels.start < levels.end && levels.end <= range.level_count);
+        self.subresource_range.base_mip_level = range.base_mip_level + levels.start;
+        self.subresource_range.level_count = levels.end - levels.start;
+        self
+    }
+    /// Narrow the view to the given array layers, relative to the subresource range of the image.
+    pub fn array_layers(mut self, layers: Range<u32>) -> Self {
+        let range = self.image.subresource_range();
+        assert!(layers.start < layers.end && layers.end <= range.layer_count);
+        self.subresource_range.base_array_layer = range.base_array_layer + layers.start;
+        self.subresource_range.layer_count = layers.end - layers.start;
+        self
+    }
+    /// Select a subset of the aspects of the image, for example the depth aspect of a depth stencil image.
+    pub fn aspect_mask(mut self, aspect_mask: vk::ImageAspectFlags) -> Self {
+        assert!(self
+            .image
+            .subresource_range()
+            .aspect_mask
+            .contains(aspect_mask));
+        self.subresource_range.aspect_mask = aspect_mask;
+        self
+    }
+
+    fn resolved_view_type(&self) -> vk::ImageViewType {
+        let view_type = self.view_type.unwrap_or_else(|| {
+            let array = self.subresource_range.layer_count > 1;
+            match self.image.image_type() {
+                vk::ImageType::TYPE_1D if array => vk::ImageViewType::TYPE_1D_ARRAY,
+                vk::ImageType::TYPE_1D => vk::ImageViewType::TYPE_1D,
+                vk::ImageType::TYPE_3D => vk::ImageViewType::TYPE_3D,
+                _ if array => vk::ImageViewType::TYPE_2D_ARRAY,
+                _ => vk::ImageViewType::TYPE_2D,
+            }
+        });
+        let layer_count = self.subresource_range.layer_count;
+        match view_type {
+            vk::ImageViewType::TYPE_1D
+            | vk::ImageViewType::TYPE_2D
+            | vk::ImageViewType::TYPE_3D => {
+                assert_eq!(
+                    layer_count, 1,
+                    "{view_type:?} views must have exactly one array layer"
+                );
+            }
+            vk::ImageViewType::CUBE | vk::ImageViewType::CUBE_ARRAY => {
+                assert!(
+                    self.image
+                        .create_flags()
+                        .contains(vk::ImageCreateFlags::CUBE_COMPATIBLE),
+                    "Cube views require an image created with CUBE_COMPATIBLE"
+                );
+                if view_type == vk::ImageViewType::CUBE {
+                    assert_eq!(layer_count, 6, "Cube views must have 6 array layers");
+                } else {
+                    assert_eq!(
+                        layer_count % 6,
+                        0,
+                        "Cube array views must have a multiple of 6 array layers"
+                    );
+                }
+            }
+            _ => (),
+        }
+        view_type
+    }
+
+    fn create_raw(&self) -> VkResult<vk::ImageView> {
+        if self.format != self.image.format() {
+            assert!(
+                self.image
+                    .create_flags()
+                    .contains(vk::ImageCreateFlags::MUTABLE_FORMAT),
+                "Reinterpreting the format requires an image created with MUTABLE_FORMAT"
+            );
+            // Formats without a `Format` description are left to the validation layers.
+            if let (Some(image_format), Some(view_format)) = (
+                Format::from_vk(self.image.format()),
+                Format::from_vk(self.format),
+            ) {
+                assert!(
+                    image_format.is_compatible_with(&view_format),
+                    "{:?} is not compatible with {:?}",
+                    self.format,
+                    self.image.format()
+                );
+            }
+        }
+        unsafe {
+            self.image.device().create_image_view(
+                &vk::ImageViewCreateInfo {
+                    image: self.image.raw_image(),
+                    view_type: self.resolved_view_type(),
+                    format: self.format,
+                    components: self.components,
+                    subresource_range: self.subresource_range,
+                    ..Default::default()
+                },
+                None,
+            )
+        }
+    }
+
+    fn view_extent(&self) -> UVec3 {
+        mip_extent(
+            self.image.extent(),
+            self.subresource_range.base_mip_level - self.image.subresource_range().base_mip_level,
+        )
+    }
+
+    /// The view holds a clone of `T` to keep the image alive, so `T` is usually an
+    /// `Arc<Image>`.
+    pub fn build(self) -> VkResult<ImageView<T>>
+    where
+        T: Clone,
+    {
+        let view = self.create_raw()?;
+        Ok(ImageView {
+            image: self.image.clone(),
+            view,
+            format: self.format,
+            extent: self.view_extent(),
+            subresource_range: self.subresource_range,
+        })
+    }
+
+    /// Create a view that does not keep the image alive. The caller must make sure that the
+    /// view is no longer used once the image was dropped.
+    pub(crate) fn build_handle(self) -> VkResult<ImageViewHandle> {
+        Ok(ImageViewHandle {
+            device: self.image.device().clone(),
+            view: self.create_raw()?,
+        })
+    }
+}
+
+/// A view of an image created with [`ImageExt::view_builder`].
+///
+/// Holds `T` to keep the image alive. Create views of shared images with `Arc<Image>`.
+pub struct ImageView<T: ImageLike + HasDevice> {
+    image: T,
+    view: vk::ImageView,
+    format: vk::Format,
+    extent: UVec3,
+    subresource_range: vk::ImageSubresourceRange,
+}
+impl<T: ImageLike + HasDevice> ImageView<T> {
+    pub fn image(&self) -> &T {
+        &self.image
+    }
+}
+impl<T: ImageLike + HasDevice> ImageLike for ImageView<T> {
+    fn raw_image(&self) -> vk::Image {
+        self.image.raw_image()
+    }
+    fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        self.subresource_range
+    }
+    fn extent(&self) -> UVec3 {
+        self.extent
+    }
+    fn format(&self) -> vk::Format {
+        self.format
+    }
+    fn create_flags(&self) -> vk::ImageCreateFlags {
+        self.image.create_flags()
+    }
+    fn image_type(&self) -> vk::ImageType {
+        self.image.image_type()
+    }
+}
+impl<T: ImageLike + HasDevice> ImageViewLike for ImageView<T> {
+    fn raw_image_view(&self) -> vk::ImageView {
+        self.view
+    }
+}
+impl<T: ImageLike + HasDevice> HasDevice for ImageView<T> {
+    fn device(&self) -> &Device {
+        self.image.device()
+    }
+}
+impl<T: ImageLike + HasDevice> Drop for ImageView<T> {
+    fn drop(&mut self) {
+        unsafe {
+            self.image.device().destroy_image_view(self.view, None);
+        }
+    }
+}
+
+/// An image view that does not keep its image alive.
+/// Used to retain temporary views until the commands using them have completed.
+pub struct ImageViewHandle {
+    device: Device,
+    view: vk::ImageView,
+}
+impl ImageViewHandle {
+    pub fn raw(&self) -> vk::ImageView {
+        self.view
+    }
+}
+impl Drop for ImageViewHandle {
+    fn drop(&mut self) {
+        unsafe {
+            self.device.destroy_image_view(self.view, None);
+        }
+    }
+}
+
+impl<T: ImageLike> ImageLike for Arc<T> {
+    fn raw_image(&self) -> vk::Image {
+        T::raw_image(self)
+    }
+    fn subresource_range(&self) -> vk::ImageSubresourceRange {
+        T::subresource_range(self)
+    }
+    fn extent(&self) -> UVec3 {
+        T::extent(self)
+    }
+    fn offset(&self) -> IVec3 {
+        T::offset(self)
+    }
+    fn format(&self) -> vk::Format {
+        T::format(self)
+    }
+    fn create_flags(&self) -> vk::ImageCreateFlags {
+        T::create_flags(self)
+    }
+    fn image_type(&self) -> vk::ImageType {
+        T::image_type(self)
+    }
+}
+impl<T: HasDevice> HasDevice for Arc<T> {
+    fn device(&self) -> &Device {
+        T::device(self)
+    }
 }
 
 pub struct ImageWithView<T: ImageLike + HasDevice> {
@@ -188,6 +668,14 @@ impl<T: ImageLike + HasDevice> ImageLike for ImageWithView<T> {
     fn format(&self) -> vk::Format {
         self.image.format()
     }
+
+    fn create_flags(&self) -> vk::ImageCreateFlags {
+        self.image.create_flags()
+    }
+
+    fn image_type(&self) -> vk::ImageType {
+        self.image.image_type()
+    }
 }
 impl<T: ImageLike + HasDevice> ImageViewLike for ImageWithView<T> {
     fn raw_image_view(&self) -> vk::ImageView {
@@ -16,6 +16,7 @@
 #![feature(allocator_api)]
 
 mod alloc;
+mod bindless;
 pub mod buffer;
 pub mod command;
 //pub mod commands;
@@ -25,6 +26,7 @@ mod device;
 pub mod ecs;
 pub mod extensions;
 pub mod future;
+mod gpu_image;
 mod image;
 mod instance;
 mod physical_device;
@@ -37,23 +39,30 @@ pub mod shader;
 mod surface;
 pub mod swapchain;
 pub mod sync;
+pub mod texture;
 //pub mod task;
 
 pub mod commands;
 pub mod utils;
 
-pub use alloc::Allocator;
+pub use alloc::{
+    Allocator, AllocatorPool, AllocatorPoolAlgorithm, AllocatorPoolCreateInfo,
+    DefragmentationError, DefragmentationPlugin, Defragmenter, MemoryBudgetPlugin, MemoryPressure,
+    RetiredRelocation,
+};
 pub use ash;
+pub use bindless::{BindlessHeap, BindlessPlugin, BindlessResourceType};
 pub use cstr::cstr;
 pub use deferred::*;
 pub use device::*;
+pub use gpu_image::{GpuImage, GpuImagePlugin, GpuImageUploadSet, GpuImages};
 pub use image::*;
 pub use instance::*;
 pub use physical_device::*;
 pub use plugin::{RhyoliteApp, RhyolitePlugin};
 pub use query_pool::*;
 pub use queue::*;
-pub use sampler::Sampler;
+pub use sampler::{Sampler, SamplerCache, SamplerDesc};
 pub use surface::*;
 pub use vk_mem;
 
@@ -91,6 +91,13 @@ impl PhysicalDevice {
             }
         }
     }
+    pub fn format_properties(&self, format: vk::Format) -> vk::FormatProperties {
+        unsafe {
+            self.0
+                .instance
+                .get_physical_device_format_properties(self.0.physical_device, format)
+        }
+    }
     pub(crate) fn get_queue_family_properties(&self) -> Vec<vk::QueueFamilyProperties> {
         unsafe {
             self.0
@@ -427,6 +434,10 @@ impl_feature_for_ext!(
     vk::PhysicalDeviceTimelineSemaphoreFeatures<'_>,
     khr::timeline_semaphore::Meta
 );
+impl_feature_for_ext!(
+    vk::PhysicalDeviceSeparateDepthStencilLayoutsFeatures<'_>,
+    khr::separate_depth_stencil_layouts::Meta
+);
 impl_feature_for_ext!(
     vk::PhysicalDeviceDynamicRenderingFeatures<'_>,
     khr::dynamic_rendering::Meta
@@ -443,6 +454,26 @@ impl_feature_for_ext!(
     vk::PhysicalDeviceAccelerationStructureFeaturesKHR<'_>,
     khr::acceleration_structure::Meta
 );
+impl_feature_for_ext!(
+    vk::PhysicalDeviceDescriptorIndexingFeatures<'_>,
+    ext::descriptor_indexing::Meta
+);
+impl_feature_for_ext!(
+    vk::PhysicalDeviceDescriptorBufferFeaturesEXT<'_>,
+    ext::descriptor_buffer::Meta
+);
+impl_feature_for_ext!(
+    vk::PhysicalDeviceGraphicsPipelineLibraryFeaturesEXT<'_>,
+    ext::graphics_pipeline_library::Meta
+);
+impl_feature_for_ext!(
+    vk::PhysicalDeviceMeshShaderFeaturesEXT<'_>,
+    ext::mesh_shader::Meta
+);
+impl_feature_for_ext!(
+    vk::PhysicalDevicePipelineExecutablePropertiesFeaturesKHR<'_>,
+    khr::pipeline_executable_properties::Meta
+);
 impl_feature_for_ext!(
     vk::PhysicalDeviceBufferDeviceAddressFeatures<'_>,
     khr::buffer_device_address::Meta
@@ -1,34 +1,64 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
 
-use ash::vk;
+use ash::{ext, khr, vk};
 use bevy::app::Plugin;
-use bevy::asset::{AssetEvent, AssetId, Assets};
+use bevy::asset::{AssetEvent, AssetId, AssetLoadFailedEvent, Assets};
 use bevy::ecs::world::FromWorld;
 use bevy::ecs::{
-    prelude::EventReader,
-    system::{ResMut, Resource},
+    event::Event,
+    prelude::{EventReader, EventWriter},
+    system::{Res, ResMut, Resource},
 };
 
+use thiserror::Error;
+
 use super::compute::{ComputePipeline, ComputePipelineCreateInfo};
 use super::{
-    BoxedGraphicsPipelineBuildInfo, Builder, BuilderResult, GraphicsPipeline,
-    GraphicsPipelineBuildInfo, Pipeline, PipelineBuildInfo,
+    CachedGraphicsPipelineBuildInfo, GraphicsPipeline, GraphicsPipelineBuildInfo,
+    GraphicsPipelineRegistry, Pipeline, PipelineBuildInfo, PipelineExecutableInfo,
+    SpecializablePipelineBuildInfo,
 };
 use crate::deferred::{DeferredOperationTaskPool, Task};
-use crate::shader::ShaderModule;
+use crate::shader::{ShaderModule, SpecializationInfo};
 use crate::sync::GPUBorrowed;
-use crate::Device;
+use crate::{Device, RhyoliteApp};
 
 #[derive(Resource)]
 pub struct PipelineCache {
     device: Device,
     cache: vk::PipelineCache,
+    /// Where the contents of `cache` are persisted. None if the cache is in-memory only.
+    path: Option<PathBuf>,
+    save_interval: Option<Duration>,
+    last_saved: Instant,
     shader_generations: HashMap<AssetId<ShaderModule>, u32>,
     hot_reload_enabled: bool,
+    graphics_pipelines: GraphicsPipelineRegistry,
+    /// `CAPTURE_STATISTICS` and `CAPTURE_INTERNAL_REPRESENTATIONS` if pipeline executable info
+    /// capture was enabled, empty otherwise.
+    capture_flags: vk::PipelineCreateFlags,
+    build_failures: (
+        crossbeam_channel::Sender<PipelineBuildFailed>,
+        crossbeam_channel::Receiver<PipelineBuildFailed>,
+    ),
+    /// Shaders whose last load failed, reported to every pipeline using them.
+    shader_load_failures: HashMap<AssetId<ShaderModule>, ShaderLoadFailure>,
+    /// Incremented on every shader load failure.
+    load_failure_sequence: u64,
+}
+struct ShaderLoadFailure {
+    sequence: u64,
+    message: String,
 }
 impl Drop for PipelineCache {
     fn drop(&mut self) {
         if self.cache != vk::PipelineCache::null() {
+            self.save();
             unsafe {
                 self.device.destroy_pipeline_cache(self.cache, None);
             }
@@ -38,20 +68,64 @@ impl Drop for PipelineCache {
 
 impl FromWorld for PipelineCache {
     fn from_world(world: &mut bevy::ecs::world::World) -> Self {
-        Self {
-            device: world.resource::<Device>().clone(),
-            cache: vk::PipelineCache::null(), // TODO
-            shader_generations: Default::default(),
-            hot_reload_enabled: true,
-        }
+        Self::new(world.resource::<Device>().clone(), true, None, true)
+    }
+}
+
+/// Size of the `VK_PIPELINE_CACHE_HEADER_VERSION_ONE` header.
+const PIPELINE_CACHE_HEADER_SIZE: usize = 16 + 4 * std::mem::size_of::<u32>();
+
+/// Returns true if `data` was produced by the same driver and device as `properties`.
+/// Drivers are supposed to reject incompatible data themselves, but some of them crash instead.
+fn is_pipeline_cache_compatible(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+    if data.len() < PIPELINE_CACHE_HEADER_SIZE {
+        return false;
     }
+    let read_u32 = |offset: usize| u32::from_ne_bytes(data[offset..offset + 4].try_into().unwrap());
+    let header_size = read_u32(0);
+    let header_version = vk::PipelineCacheHeaderVersion::from_raw(read_u32(4) as i32);
+    header_size as usize >= PIPELINE_CACHE_HEADER_SIZE
+        && header_version == vk::PipelineCacheHeaderVersion::ONE
+        && read_u32(8) == properties.vendor_id
+        && read_u32(12) == properties.device_id
+        && data[16..32] == properties.pipeline_cache_uuid
+}
+
+/// Why a pipeline failed to build.
+#[derive(Debug, Clone, Error)]
+pub enum PipelineBuildError {
+    /// One of the shaders of the pipeline failed to load, for example because it did not compile.
+    #[error("shader {shader:?} failed to load: {message}")]
+    ShaderLoad {
+        shader: AssetId<ShaderModule>,
+        /// The error reported by the asset loader, including the compiler output.
+        message: String,
+    },
+    #[error("pipeline creation failed: {0:?}")]
+    Creation(#[from] vk::Result),
+}
+
+/// Fired when a pipeline failed to build. The [`CachedPipeline`] keeps the last pipeline that was
+/// built successfully, and won't be rebuilt until one of its shaders is modified.
+#[derive(Event, Debug, Clone)]
+pub struct PipelineBuildFailed {
+    /// The shaders used by the pipeline.
+    pub shaders: Vec<AssetId<ShaderModule>>,
+    pub error: PipelineBuildError,
 }
 
 pub struct CachedPipeline<T: Pipeline> {
     build_info: Option<T::BuildInfo>,
     pipeline: Option<GPUBorrowed<T>>,
     task: Option<Task<<T::BuildInfo as PipelineBuildInfo>::Pipeline>>,
+    /// A better version of `pipeline` being built in the background.
+    upgrade: Option<Task<<T::BuildInfo as PipelineBuildInfo>::Pipeline>>,
     shader_generations: HashMap<AssetId<ShaderModule>, u32>,
+    executables: Vec<PipelineExecutableInfo>,
+    /// The error of the last build, if it failed.
+    error: Option<PipelineBuildError>,
+    /// The sequence number of the last shader load failure reported for this pipeline.
+    reported_load_failure: u64,
 }
 impl<T: Pipeline> CachedPipeline<T> {
     pub fn is_ready(&self) -> bool {
@@ -68,13 +142,162 @@ impl<T: Pipeline> CachedPipeline<T> {
     pub fn get(&self) -> Option<&GPUBorrowed<T>> {
         self.pipeline.as_ref()
     }
+    /// The error of the last build, if it failed. Cleared when a rebuild is scheduled.
+    pub fn error(&self) -> Option<&PipelineBuildError> {
+        self.error.as_ref()
+    }
+    /// Compiler statistics of the current pipeline. Empty unless
+    /// [`PipelineCachePlugin::pipeline_executable_info`] was enabled and supported.
+    pub fn executables(&self) -> &[PipelineExecutableInfo] {
+        &self.executables
+    }
+}
+
+/// Specialization constant permutations of one pipeline, built on demand by
+/// [`PipelineCache::retrieve_variant`]. Once `capacity` variants exist, the least recently
+/// retrieved variant is evicted to make room for a new one.
+pub struct CachedPipelineVariants<T: Pipeline, K> {
+    base: T::BuildInfo,
+    specialize: Box<dyn Fn(&K) -> SpecializationInfo + Send + Sync>,
+    variants: HashMap<K, (CachedPipeline<T>, u64)>,
+    capacity: usize,
+    /// Incremented on every retrieval. Used to find the least recently used variant.
+    clock: u64,
+}
+impl<T: Pipeline, K: Hash + Eq> CachedPipelineVariants<T, K> {
+    pub fn get(&self, key: &K) -> Option<&CachedPipeline<T>> {
+        self.variants.get(key).map(|(variant, _)| variant)
+    }
+    /// Number of variants currently cached.
+    pub fn len(&self) -> usize {
+        self.variants.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.variants.is_empty()
+    }
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
 }
 
 impl PipelineCache {
+    /// Create the pipeline cache. If `path` is provided, the cache is initialized with the
+    /// contents of the file, provided that it was written by the same driver and device.
+    pub fn new(
+        device: Device,
+        enabled: bool,
+        path: Option<PathBuf>,
+        hot_reload_enabled: bool,
+    ) -> Self {
+        let cache = if enabled {
+            let initial_data = path
+                .as_deref()
+                .and_then(|path| Self::load(&device, path))
+                .unwrap_or_default();
+            let result = unsafe {
+                device.create_pipeline_cache(
+                    &vk::PipelineCacheCreateInfo::default().initial_data(&initial_data),
+                    None,
+                )
+            };
+            match result {
+                Ok(cache) => cache,
+                Err(err) => {
+                    tracing::warn!("Failed to create pipeline cache: {err:?}");
+                    vk::PipelineCache::null()
+                }
+            }
+        } else {
+            vk::PipelineCache::null()
+        };
+        Self {
+            device,
+            cache,
+            path: if enabled { path } else { None },
+            save_interval: None,
+            last_saved: Instant::now(),
+            shader_generations: Default::default(),
+            hot_reload_enabled,
+            graphics_pipelines: Default::default(),
+            capture_flags: vk::PipelineCreateFlags::empty(),
+            build_failures: crossbeam_channel::unbounded(),
+            shader_load_failures: Default::default(),
+            load_failure_sequence: 0,
+        }
+    }
+
+    fn load(device: &Device, path: &Path) -> Option<Vec<u8>> {
+        let data = match std::fs::read(path) {
+            Ok(data) => data,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return None,
+            Err(err) => {
+                tracing::warn!(
+                    "Failed to read pipeline cache from {}: {err}",
+                    path.display()
+                );
+                return None;
+            }
+        };
+        if !is_pipeline_cache_compatible(&data, device.physical_device().properties()) {
+            tracing::info!(
+                "Discarding pipeline cache at {} created by a different driver or device",
+                path.display()
+            );
+            return None;
+        }
+        tracing::info!(
+            "Loaded {} bytes of pipeline cache from {}",
+            data.len(),
+            path.display()
+        );
+        Some(data)
+    }
+
+    /// The raw pipeline cache handle. May be null if the pipeline cache was disabled.
+    pub fn raw(&self) -> vk::PipelineCache {
+        self.cache
+    }
+
+    /// Write the pipeline cache to disk. Does nothing if no path was configured.
+    pub fn save(&mut self) {
+        self.last_saved = Instant::now();
+        let Some(path) = self.path.as_deref() else {
+            return;
+        };
+        let data = match unsafe { self.device.get_pipeline_cache_data(self.cache) } {
+            Ok(data) => data,
+            Err(err) => {
+                tracing::warn!("Failed to retrieve pipeline cache data: {err:?}");
+                return;
+            }
+        };
+        // Write to a temporary file first so that a crash never leaves a truncated cache behind.
+        let tmp_path = path.with_extension("tmp");
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|_| std::fs::write(&tmp_path, &data))
+            .and_then(|_| std::fs::rename(&tmp_path, path));
+        match result {
+            Ok(()) => tracing::debug!(
+                "Saved {} bytes of pipeline cache to {}",
+                data.len(),
+                path.display()
+            ),
+            Err(err) => {
+                tracing::warn!("Failed to save pipeline cache to {}: {err}", path.display())
+            }
+        }
+    }
+
     pub fn create<T: Pipeline>(&self, build_info: T::BuildInfo) -> CachedPipeline<T> {
         CachedPipeline {
             pipeline: None,
             task: None,
+            upgrade: None,
+            executables: Vec::new(),
+            error: None,
+            reported_load_failure: 0,
             shader_generations: if self.hot_reload_enabled {
                 let mut map = HashMap::new();
                 map.extend(build_info.all_shaders().map(|shader| (shader, 0)));
@@ -85,27 +308,67 @@ impl PipelineCache {
             build_info: Some(build_info),
         }
     }
-    pub fn create_graphics<F>(
+    fn graphics_build_info(
         &self,
-        build_info: GraphicsPipelineBuildInfo<F>,
-    ) -> CachedPipeline<GraphicsPipeline>
-    where
-        F: for<'a> Fn(Builder) -> BuilderResult + Send + Sync + 'static,
-    {
-        let boxed = BoxedGraphicsPipelineBuildInfo {
-            device: build_info.device,
-            stages: build_info.stages,
-            builder: Arc::new(build_info.builder),
-            layout: build_info.layout,
-        };
-        self.create::<GraphicsPipeline>(boxed)
+        mut build_info: GraphicsPipelineBuildInfo,
+    ) -> CachedGraphicsPipelineBuildInfo {
+        build_info.state.flags |= self.capture_flags;
+        CachedGraphicsPipelineBuildInfo {
+            info: build_info,
+            registry: self.graphics_pipelines.clone(),
+            last_key: None,
+        }
+    }
+    pub fn create_graphics(
+        &self,
+        build_info: GraphicsPipelineBuildInfo,
+    ) -> CachedPipeline<GraphicsPipeline> {
+        self.create::<GraphicsPipeline>(self.graphics_build_info(build_info))
     }
     pub fn create_compute(
         &self,
-        build_info: ComputePipelineCreateInfo,
+        mut build_info: ComputePipelineCreateInfo,
     ) -> CachedPipeline<ComputePipeline> {
+        build_info.flags |= self.capture_flags;
         self.create::<ComputePipeline>(build_info)
     }
+    /// Create a pipeline with variants keyed by `K`. `specialize` returns the specialization
+    /// constants of a variant, which are added to those of every shader stage in `build_info`.
+    pub fn create_variants<T: Pipeline, K: Hash + Eq>(
+        &self,
+        build_info: T::BuildInfo,
+        capacity: usize,
+        specialize: impl Fn(&K) -> SpecializationInfo + Send + Sync + 'static,
+    ) -> CachedPipelineVariants<T, K>
+    where
+        T::BuildInfo: SpecializablePipelineBuildInfo,
+    {
+        assert!(capacity > 0, "Pipeline variant capacity must be non-zero");
+        CachedPipelineVariants {
+            base: build_info,
+            specialize: Box::new(specialize),
+            variants: HashMap::new(),
+            capacity,
+            clock: 0,
+        }
+    }
+    pub fn create_graphics_variants<K: Hash + Eq>(
+        &self,
+        build_info: GraphicsPipelineBuildInfo,
+        capacity: usize,
+        specialize: impl Fn(&K) -> SpecializationInfo + Send + Sync + 'static,
+    ) -> CachedPipelineVariants<GraphicsPipeline, K> {
+        self.create_variants(self.graphics_build_info(build_info), capacity, specialize)
+    }
+    pub fn create_compute_variants<K: Hash + Eq>(
+        &self,
+        mut build_info: ComputePipelineCreateInfo,
+        capacity: usize,
+        specialize: impl Fn(&K) -> SpecializationInfo + Send + Sync + 'static,
+    ) -> CachedPipelineVariants<ComputePipeline, K> {
+        build_info.flags |= self.capture_flags;
+        self.create_variants(build_info, capacity, specialize)
+    }
     pub fn is_outdated<T: Pipeline>(&self, cached_pipeline: &CachedPipeline<T>) -> bool {
         for (shader, generation) in cached_pipeline.shader_generations.iter() {
             if let Some(latest_generation) = self.shader_generations.get(shader) {
@@ -124,6 +387,113 @@ impl PipelineCache {
     ) -> Option<&'a GPUBorrowed<T>> {
         self.retrieve_pipeline(cached_pipeline, assets, pool, true)
     }
+    /// Retrieve the variant of the pipeline for `key`, scheduling a build if it wasn't built yet.
+    /// Returns None while the variant is being built for the first time.
+    pub fn retrieve_variant<'a, T: Pipeline, K: Hash + Eq + Clone>(
+        &self,
+        variants: &'a mut CachedPipelineVariants<T, K>,
+        key: K,
+        assets: &Assets<ShaderModule>,
+        pool: &DeferredOperationTaskPool,
+    ) -> Option<&'a GPUBorrowed<T>>
+    where
+        T::BuildInfo: SpecializablePipelineBuildInfo,
+    {
+        variants.clock += 1;
+        let clock = variants.clock;
+        if self.hot_reload_enabled {
+            // Variants built from a modified shader are dropped, so that they get rebuilt instead
+            // of serving a stale pipeline when retrieved next time.
+            // The requested variant keeps serving the stale pipeline while it is being rebuilt.
+            variants
+                .variants
+                .retain(|k, (variant, _)| *k == key || !self.is_outdated(variant));
+        }
+        if !variants.variants.contains_key(&key) {
+            if variants.variants.len() >= variants.capacity {
+                let evicted = variants
+                    .variants
+                    .iter()
+                    .min_by_key(|(_, (_, last_used))| *last_used)
+                    .map(|(k, _)| k.clone());
+                if let Some(evicted) = evicted {
+                    variants.variants.remove(&evicted);
+                }
+            }
+            let constants = (variants.specialize)(&key);
+            let variant = self.create::<T>(variants.base.specialize(&constants));
+            variants.variants.insert(key.clone(), (variant, clock));
+        }
+        let (variant, last_used) = variants.variants.get_mut(&key).unwrap();
+        *last_used = clock;
+        self.retrieve_pipeline(variant, assets, pool, true)
+    }
+    fn report_build_failure<T: Pipeline>(
+        &self,
+        cached_pipeline: &mut CachedPipeline<T>,
+        error: PipelineBuildError,
+    ) {
+        let shaders: Vec<_> = cached_pipeline
+            .build_info
+            .as_ref()
+            .map(|info| info.all_shaders().collect())
+            .unwrap_or_default();
+        tracing::error!("Failed to build pipeline with shaders {shaders:?}: {error}");
+        cached_pipeline.error = Some(error.clone());
+        self.build_failures
+            .0
+            .send(PipelineBuildFailed { shaders, error })
+            .unwrap();
+    }
+    /// Report shader load failures that happened since the last retrieval of the pipeline.
+    fn report_shader_load_failures<T: Pipeline>(&self, cached_pipeline: &mut CachedPipeline<T>) {
+        if let Some(PipelineBuildError::ShaderLoad { shader, .. }) = &cached_pipeline.error {
+            if !self.shader_load_failures.contains_key(shader) {
+                // The shader was loaded successfully since.
+                cached_pipeline.error = None;
+            }
+        }
+        let Some(build_info) = cached_pipeline.build_info.as_ref() else {
+            return;
+        };
+        let failure = build_info
+            .all_shaders()
+            .filter_map(|shader| Some((shader, self.shader_load_failures.get(&shader)?)))
+            .filter(|(_, failure)| failure.sequence > cached_pipeline.reported_load_failure)
+            .max_by_key(|(_, failure)| failure.sequence);
+        if let Some((shader, failure)) = failure {
+            cached_pipeline.reported_load_failure = failure.sequence;
+            let error = PipelineBuildError::ShaderLoad {
+                shader,
+                message: failure.message.clone(),
+            };
+            self.report_build_failure(cached_pipeline, error);
+        }
+    }
+    fn replace_pipeline<T: Pipeline>(
+        &self,
+        cached_pipeline: &mut CachedPipeline<T>,
+        new_pipeline: <T::BuildInfo as PipelineBuildInfo>::Pipeline,
+    ) {
+        // The build info is still needed for rebuilds on shader hot reload and for pending upgrades.
+        let built = if self.hot_reload_enabled || cached_pipeline.upgrade.is_some() {
+            let build_info = cached_pipeline.build_info.as_mut().unwrap();
+            T::from_built(build_info, new_pipeline)
+        } else {
+            let build_info = cached_pipeline.build_info.take().unwrap();
+            T::from_built_with_owned_info(build_info, new_pipeline)
+        };
+        if !self.capture_flags.is_empty() {
+            match PipelineExecutableInfo::query(&self.device, built.as_raw()) {
+                Ok(executables) => {
+                    executables.iter().for_each(PipelineExecutableInfo::log);
+                    cached_pipeline.executables = executables;
+                }
+                Err(err) => tracing::warn!("Failed to query pipeline executable info: {err:?}"),
+            }
+        }
+        cached_pipeline.pipeline.replace(GPUBorrowed::new(built));
+    }
     pub fn retrieve_pipeline<'a, T: Pipeline>(
         &self,
         cached_pipeline: &'a mut CachedPipeline<T>,
@@ -131,32 +501,48 @@ impl PipelineCache {
         pool: &DeferredOperationTaskPool,
         allow_stale: bool,
     ) -> Option<&'a GPUBorrowed<T>> {
+        if !self.shader_load_failures.is_empty() || cached_pipeline.error.is_some() {
+            self.report_shader_load_failures(cached_pipeline);
+        }
         if let Some(pipeline) = &mut cached_pipeline.task {
             if pipeline.is_finished() {
-                let new_pipeline = cached_pipeline.task.take().unwrap().unwrap().unwrap();
-                let built = if self.hot_reload_enabled {
-                    let build_info = cached_pipeline.build_info.as_mut().unwrap();
-                    T::from_built(build_info, new_pipeline)
-                } else {
-                    let build_info = cached_pipeline.build_info.take().unwrap();
-                    T::from_built_with_owned_info(build_info, new_pipeline)
-                };
-                cached_pipeline.pipeline.replace(GPUBorrowed::new(built));
+                match cached_pipeline.task.take().unwrap().unwrap() {
+                    Ok(new_pipeline) => {
+                        cached_pipeline.upgrade = cached_pipeline
+                            .build_info
+                            .as_mut()
+                            .unwrap()
+                            .schedule_upgrade(&new_pipeline, pool, self.cache);
+                        self.replace_pipeline(cached_pipeline, new_pipeline);
+                    }
+                    Err(error) => self.report_build_failure(cached_pipeline, error.into()),
+                }
             } else if !allow_stale {
                 // A build task is pending, and we don't want to return a stale pipeline.
                 return None;
             }
         }
 
+        if let Some(upgrade) = &cached_pipeline.upgrade {
+            if upgrade.is_finished() {
+                match cached_pipeline.upgrade.take().unwrap().unwrap() {
+                    Ok(upgraded) => self.replace_pipeline(cached_pipeline, upgraded),
+                    Err(err) => tracing::warn!("Failed to upgrade pipeline: {err:?}"),
+                }
+            }
+        }
+
         if self.hot_reload_enabled {
             if self.is_outdated(cached_pipeline) {
                 // schedule.
+                cached_pipeline.upgrade = None;
                 cached_pipeline.task = cached_pipeline
                     .build_info
                     .as_mut()
                     .unwrap()
                     .build(pool, assets, self.cache);
                 if cached_pipeline.task.is_some() {
+                    cached_pipeline.error = None;
                     // if cached.pipeline.task is still none, it means that some of the shaders hasn't finished loading.
                     // in that case we skip updating the generations and we'll retry on the next frame.
                     for (shader, generation) in cached_pipeline.shader_generations.iter_mut() {
@@ -170,7 +556,7 @@ impl PipelineCache {
         }
 
         if let Some(pipeline) = cached_pipeline.pipeline.as_ref() {
-            if cached_pipeline.task.is_none() {
+            if cached_pipeline.task.is_none() && cached_pipeline.error.is_none() {
                 return Some(pipeline);
             } else if allow_stale {
                 return Some(pipeline);
@@ -178,7 +564,8 @@ impl PipelineCache {
                 return None;
             }
         } else {
-            if cached_pipeline.task.is_none() {
+            // Failed builds are only retried once a shader was modified.
+            if cached_pipeline.task.is_none() && cached_pipeline.error.is_none() {
                 // schedule
                 cached_pipeline.task = cached_pipeline
                     .build_info
@@ -210,32 +597,218 @@ fn pipeline_cache_shader_updated_system(
     }
 }
 
+fn pipeline_cache_shader_load_failed_system(
+    mut pipeline_cache: ResMut<PipelineCache>,
+    mut events: EventReader<AssetEvent<ShaderModule>>,
+    mut failures: EventReader<AssetLoadFailedEvent<ShaderModule>>,
+) {
+    for event in events.read() {
+        match event {
+            AssetEvent::Added { id } | AssetEvent::Modified { id } | AssetEvent::Removed { id } => {
+                pipeline_cache.shader_load_failures.remove(id);
+            }
+            _ => (),
+        }
+    }
+    for failure in failures.read() {
+        pipeline_cache.load_failure_sequence += 1;
+        let failure_info = ShaderLoadFailure {
+            sequence: pipeline_cache.load_failure_sequence,
+            message: failure.error.to_string(),
+        };
+        pipeline_cache
+            .shader_load_failures
+            .insert(failure.id, failure_info);
+    }
+}
+
+fn pipeline_cache_build_failed_system(
+    pipeline_cache: Res<PipelineCache>,
+    mut events: EventWriter<PipelineBuildFailed>,
+) {
+    events.send_batch(pipeline_cache.build_failures.1.try_iter());
+}
+
+fn pipeline_cache_save_system(mut pipeline_cache: ResMut<PipelineCache>) {
+    let Some(save_interval) = pipeline_cache.save_interval else {
+        return;
+    };
+    if pipeline_cache.last_saved.elapsed() >= save_interval {
+        pipeline_cache.save();
+    }
+}
+
+#[derive(Clone)]
 pub struct PipelineCachePlugin {
-    shader_hot_reload: bool,
-    pipeline_cache_enabled: bool, // TODO: use pipeline cache
+    pub shader_hot_reload: bool,
+    /// Create pipelines with a `VkPipelineCache`.
+    pub pipeline_cache_enabled: bool,
+    /// Load the pipeline cache from this file on startup, and write it back on shutdown.
+    pub pipeline_cache_path: Option<PathBuf>,
+    /// Also write the pipeline cache back periodically, so that pipelines built before a crash
+    /// are not lost.
+    pub save_interval: Option<Duration>,
+    /// Build graphics pipelines from pipeline libraries with `VK_EXT_graphics_pipeline_library`
+    /// if the device supports fast linking.
+    pub graphics_pipeline_library: bool,
+    /// Capture compiler statistics and internal representations of every pipeline with
+    /// `VK_KHR_pipeline_executable_properties`, available through [`CachedPipeline::executables`].
+    /// This may slow down pipeline compilation and should only be enabled for profiling.
+    pub pipeline_executable_info: bool,
 }
 
 impl Default for PipelineCachePlugin {
     fn default() -> Self {
         Self {
             shader_hot_reload: true,
-            pipeline_cache_enabled: false,
+            pipeline_cache_enabled: true,
+            pipeline_cache_path: None,
+            save_interval: None,
+            graphics_pipeline_library: true,
+            pipeline_executable_info: false,
         }
     }
 }
 
 impl Plugin for PipelineCachePlugin {
-    fn build(&self, _app: &mut bevy::prelude::App) {}
+    fn build(&self, app: &mut bevy::prelude::App) {
+        app.add_event::<PipelineBuildFailed>();
+        if self.graphics_pipeline_library
+            && app
+                .add_device_extension::<khr::pipeline_library::Meta>()
+                .is_ok()
+            && app
+                .add_device_extension::<ext::graphics_pipeline_library::Meta>()
+                .is_ok()
+        {
+            app.enable_feature::<vk::PhysicalDeviceGraphicsPipelineLibraryFeaturesEXT>(|f| {
+                &mut f.graphics_pipeline_library
+            });
+        }
+        if self.pipeline_executable_info
+            && app
+                .add_device_extension::<khr::pipeline_executable_properties::Meta>()
+                .is_ok()
+        {
+            app.enable_feature::<vk::PhysicalDevicePipelineExecutablePropertiesFeaturesKHR>(|f| {
+                &mut f.pipeline_executable_info
+            });
+        }
+    }
     fn finish(&self, app: &mut bevy::app::App) {
-        let cache = PipelineCache {
-            device: app.world().resource::<Device>().clone(),
-            cache: vk::PipelineCache::null(), // TODO
-            shader_generations: Default::default(),
-            hot_reload_enabled: self.shader_hot_reload,
-        };
+        let mut cache = PipelineCache::new(
+            app.world().resource::<Device>().clone(),
+            self.pipeline_cache_enabled,
+            self.pipeline_cache_path.clone(),
+            self.shader_hot_reload,
+        );
+        cache.save_interval = self.save_interval;
+        if self.pipeline_executable_info {
+            let supported = cache
+                .device
+                .feature::<vk::PhysicalDevicePipelineExecutablePropertiesFeaturesKHR>()
+                .is_some_and(|f| f.pipeline_executable_info == vk::TRUE);
+            if supported {
+                cache.capture_flags = vk::PipelineCreateFlags::CAPTURE_STATISTICS_KHR
+                    | vk::PipelineCreateFlags::CAPTURE_INTERNAL_REPRESENTATIONS_KHR;
+            } else {
+                tracing::warn!("Pipeline executable info requested but not supported");
+            }
+        }
         app.insert_resource(cache);
+        app.add_systems(bevy::app::Update, pipeline_cache_shader_load_failed_system);
+        app.add_systems(bevy::app::Last, pipeline_cache_build_failed_system);
         if self.shader_hot_reload {
             app.add_systems(bevy::app::Update, pipeline_cache_shader_updated_system);
         }
+        if self.save_interval.is_some() && self.pipeline_cache_path.is_some() {
+            app.add_systems(bevy::app::Last, pipeline_cache_save_system);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2684,
+            pipeline_cache_uuid: [7; vk::UUID_SIZE],
+            ..Default::default()
+        }
+    }
+
+    fn header(properties: &vk::PhysicalDeviceProperties) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend((PIPELINE_CACHE_HEADER_SIZE as u32).to_ne_bytes());
+        data.extend((vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32).to_ne_bytes());
+        data.extend(properties.vendor_id.to_ne_bytes());
+        data.extend(properties.device_id.to_ne_bytes());
+        data.extend(properties.pipeline_cache_uuid);
+        // Driver specific data follows the header.
+        data.extend([0xab; 8]);
+        data
+    }
+
+    #[test]
+    fn test_compatible() {
+        let properties = properties();
+        assert!(is_pipeline_cache_compatible(
+            &header(&properties),
+            &properties
+        ));
+    }
+
+    #[test]
+    fn test_truncated() {
+        let properties = properties();
+        let data = header(&properties);
+        assert!(!is_pipeline_cache_compatible(&[], &properties));
+        assert!(!is_pipeline_cache_compatible(
+            &data[..PIPELINE_CACHE_HEADER_SIZE - 1],
+            &properties
+        ));
+        let mut data = data;
+        data[0..4].copy_from_slice(&8_u32.to_ne_bytes());
+        assert!(!is_pipeline_cache_compatible(&data, &properties));
+    }
+
+    #[test]
+    fn test_header_version() {
+        let properties = properties();
+        let mut data = header(&properties);
+        data[4..8].copy_from_slice(&2_u32.to_ne_bytes());
+        assert!(!is_pipeline_cache_compatible(&data, &properties));
+    }
+
+    #[test]
+    fn test_different_device() {
+        let properties = properties();
+        let data = header(&properties);
+        let other_vendor = vk::PhysicalDeviceProperties {
+            vendor_id: 0x1002,
+            ..properties
+        };
+        assert!(!is_pipeline_cache_compatible(&data, &other_vendor));
+        let other_device = vk::PhysicalDeviceProperties {
+            device_id: 0x2685,
+            ..properties
+        };
+        assert!(!is_pipeline_cache_compatible(&data, &other_device));
+    }
+
+    #[test]
+    fn test_different_uuid() {
+        let properties = properties();
+        let data = header(&properties);
+        let mut pipeline_cache_uuid = properties.pipeline_cache_uuid;
+        pipeline_cache_uuid[15] ^= 1;
+        let other_driver = vk::PhysicalDeviceProperties {
+            pipeline_cache_uuid,
+            ..properties
+        };
+        assert!(!is_pipeline_cache_compatible(&data, &other_driver));
     }
 }
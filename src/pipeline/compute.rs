@@ -5,12 +5,13 @@ use bevy::asset::Assets;
 
 use crate::{
     device::HasDevice,
-    shader::{ShaderModule, SpecializedShader},
+    shader::{ShaderModule, SpecializationInfo, SpecializedShader},
     Device,
 };
 
 use super::{PipelineInner, PipelineLayout};
 
+#[derive(Clone)]
 pub struct ComputePipelineCreateInfo {
     pub device: Device,
     pub shader: SpecializedShader,
@@ -89,3 +90,11 @@ impl super::PipelineBuildInfo for ComputePipelineCreateInfo {
         std::iter::once(self.shader.shader.id())
     }
 }
+
+impl super::SpecializablePipelineBuildInfo for ComputePipelineCreateInfo {
+    fn specialize(&self, constants: &SpecializationInfo) -> Self {
+        let mut info = self.clone();
+        info.shader.specialization_info.extend(constants);
+        info
+    }
+}
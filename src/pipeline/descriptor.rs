@@ -0,0 +1,453 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use ash::{prelude::VkResult, vk};
+
+use crate::{buffer::BufferLike, ecs::FRAMES_IN_FLIGHT, Device, HasDevice, ImageViewLike, Sampler};
+
+use super::DescriptorSetLayout;
+
+/// Allocates descriptor sets for layouts without the `PUSH_DESCRIPTOR_KHR` flag.
+///
+/// New pools are created as needed, each one twice as large as the previous one. The pools used
+/// in a frame are retired with that frame when [`DescriptorAllocator::next_frame`] is called, and
+/// recycled once the frame has completed on the GPU. Render systems should keep one allocator
+/// and call `next_frame` once per frame before allocating. Sets allocated by an allocator on
+/// which `next_frame` is never called stay valid until the allocator is dropped.
+pub struct DescriptorAllocator {
+    device: Device,
+    /// Pools allocated from during the current frame. Sets are allocated from the last one.
+    pools: Vec<vk::DescriptorPool>,
+    /// Pools used by the previous frames, oldest first.
+    frames: VecDeque<Vec<vk::DescriptorPool>>,
+    /// Pools which have been reset.
+    free_pools: Vec<vk::DescriptorPool>,
+    /// The largest number of descriptors of each type required by one set so far.
+    set_sizes: BTreeMap<vk::DescriptorType, u32>,
+    sets_per_pool: u32,
+}
+
+impl Drop for DescriptorAllocator {
+    fn drop(&mut self) {
+        let pools = self.frames.drain(..).flatten();
+        for pool in self
+            .pools
+            .drain(..)
+            .chain(pools)
+            .chain(self.free_pools.drain(..))
+        {
+            unsafe {
+                self.device.destroy_descriptor_pool(pool, None);
+            }
+        }
+    }
+}
+
+impl HasDevice for DescriptorAllocator {
+    fn device(&self) -> &Device {
+        &self.device
+    }
+}
+
+impl DescriptorAllocator {
+    const INITIAL_SETS_PER_POOL: u32 = 16;
+    const MAX_SETS_PER_POOL: u32 = 4096;
+
+    pub fn new(device: Device) -> Self {
+        Self {
+            device,
+            pools: Vec::new(),
+            frames: VecDeque::new(),
+            free_pools: Vec::new(),
+            set_sizes: BTreeMap::new(),
+            sets_per_pool: Self::INITIAL_SETS_PER_POOL,
+        }
+    }
+
+    /// Allocate a descriptor set. The set stays valid until the frame it was allocated in has been
+    /// retired by [`DescriptorAllocator::next_frame`] and completed, or until the allocator is dropped.
+    pub fn allocate(&mut self, layout: &DescriptorSetLayout) -> VkResult<vk::DescriptorSet> {
+        debug_assert!(
+            layout
+                .desc_types
+                .iter()
+                .all(|(ty, _)| *ty != vk::DescriptorType::INLINE_UNIFORM_BLOCK),
+            "Inline uniform blocks are not supported by the DescriptorAllocator"
+        );
+        for (ty, count) in layout.desc_types.iter() {
+            let size = self.set_sizes.entry(*ty).or_insert(0);
+            *size = (*size).max(*count);
+        }
+        let mut is_new_pool = false;
+        loop {
+            if let Some(&pool) = self.pools.last() {
+                let result = unsafe {
+                    self.device.allocate_descriptor_sets(
+                        &vk::DescriptorSetAllocateInfo::default()
+                            .descriptor_pool(pool)
+                            .set_layouts(&[layout.raw()]),
+                    )
+                };
+                match result {
+                    Ok(sets) => return Ok(sets[0]),
+                    Err(
+                        vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL,
+                    ) if !is_new_pool => {}
+                    Err(err) => return Err(err),
+                }
+            }
+            // Pools which were created before the layout was seen may not fit the set.
+            let pool = match self.free_pools.pop() {
+                Some(pool) => pool,
+                None => {
+                    is_new_pool = true;
+                    self.create_pool()?
+                }
+            };
+            self.pools.push(pool);
+        }
+    }
+
+    /// Start a new frame. The pools used during the current frame are retired, and the pools of
+    /// the frame retired [`FRAMES_IN_FLIGHT`] frames ago are reset for reuse.
+    /// Must be called once per frame.
+    pub fn next_frame(&mut self) -> VkResult<()> {
+        self.frames.push_back(std::mem::take(&mut self.pools));
+        while self.frames.len() > FRAMES_IN_FLIGHT {
+            for pool in self.frames.pop_front().unwrap() {
+                unsafe {
+                    // The frame which used these descriptor sets has completed.
+                    self.device
+                        .reset_descriptor_pool(pool, vk::DescriptorPoolResetFlags::empty())?;
+                }
+                self.free_pools.push(pool);
+            }
+        }
+        Ok(())
+    }
+
+    fn create_pool(&mut self) -> VkResult<vk::DescriptorPool> {
+        let pool_sizes: Vec<_> = self
+            .set_sizes
+            .iter()
+            .map(|(ty, count)| vk::DescriptorPoolSize {
+                ty: *ty,
+                descriptor_count: count * self.sets_per_pool,
+            })
+            .collect();
+        let pool = unsafe {
+            self.device.create_descriptor_pool(
+                &vk::DescriptorPoolCreateInfo::default()
+                    .max_sets(self.sets_per_pool)
+                    .pool_sizes(&pool_sizes),
+                None,
+            )
+        }?;
+        self.sets_per_pool = (self.sets_per_pool * 2).min(Self::MAX_SETS_PER_POOL);
+        Ok(pool)
+    }
+}
+
+#[derive(Clone, Copy)]
+enum DescriptorInfo {
+    Image(usize),
+    Buffer(usize),
+}
+
+pub(super) enum DescriptorRef<'a> {
+    Image(&'a vk::DescriptorImageInfo),
+    Buffer(&'a vk::DescriptorBufferInfo),
+}
+
+#[derive(Clone, Copy)]
+struct DescriptorWrite {
+    binding: u32,
+    array_element: u32,
+    ty: vk::DescriptorType,
+    info: DescriptorInfo,
+}
+
+/// Collects descriptor writes for one descriptor set.
+///
+/// The same writes may be applied to a set from a [`DescriptorAllocator`] with
+/// [`DescriptorSetWriter::write`], or pushed with `vkCmdPushDescriptorSetKHR` using
+/// [`DescriptorSetWriter::writes`] with a null set.
+#[derive(Default)]
+pub struct DescriptorSetWriter {
+    image_infos: Vec<vk::DescriptorImageInfo>,
+    buffer_infos: Vec<vk::DescriptorBufferInfo>,
+    writes: Vec<DescriptorWrite>,
+}
+
+impl DescriptorSetWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn image(
+        &mut self,
+        binding: u32,
+        array_element: u32,
+        ty: vk::DescriptorType,
+        info: vk::DescriptorImageInfo,
+    ) -> &mut Self {
+        self.writes.push(DescriptorWrite {
+            binding,
+            array_element,
+            ty,
+            info: DescriptorInfo::Image(self.image_infos.len()),
+        });
+        self.image_infos.push(info);
+        self
+    }
+
+    pub fn buffer(
+        &mut self,
+        binding: u32,
+        array_element: u32,
+        ty: vk::DescriptorType,
+        info: vk::DescriptorBufferInfo,
+    ) -> &mut Self {
+        self.writes.push(DescriptorWrite {
+            binding,
+            array_element,
+            ty,
+            info: DescriptorInfo::Buffer(self.buffer_infos.len()),
+        });
+        self.buffer_infos.push(info);
+        self
+    }
+
+    pub fn sampler(&mut self, binding: u32, sampler: &Sampler) -> &mut Self {
+        self.image(
+            binding,
+            0,
+            vk::DescriptorType::SAMPLER,
+            vk::DescriptorImageInfo {
+                sampler: sampler.raw(),
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn sampled_image(
+        &mut self,
+        binding: u32,
+        view: &impl ImageViewLike,
+        layout: vk::ImageLayout,
+    ) -> &mut Self {
+        self.image(
+            binding,
+            0,
+            vk::DescriptorType::SAMPLED_IMAGE,
+            vk::DescriptorImageInfo {
+                image_view: view.raw_image_view(),
+                image_layout: layout,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn combined_image_sampler(
+        &mut self,
+        binding: u32,
+        view: &impl ImageViewLike,
+        layout: vk::ImageLayout,
+        sampler: &Sampler,
+    ) -> &mut Self {
+        self.image(
+            binding,
+            0,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            vk::DescriptorImageInfo {
+                sampler: sampler.raw(),
+                image_view: view.raw_image_view(),
+                image_layout: layout,
+            },
+        )
+    }
+
+    /// The image must be in the `GENERAL` layout when accessed.
+    pub fn storage_image(&mut self, binding: u32, view: &impl ImageViewLike) -> &mut Self {
+        self.image(
+            binding,
+            0,
+            vk::DescriptorType::STORAGE_IMAGE,
+            vk::DescriptorImageInfo {
+                image_view: view.raw_image_view(),
+                image_layout: vk::ImageLayout::GENERAL,
+                ..Default::default()
+            },
+        )
+    }
+
+    pub fn uniform_buffer(&mut self, binding: u32, buffer: &impl BufferLike) -> &mut Self {
+        self.buffer(
+            binding,
+            0,
+            vk::DescriptorType::UNIFORM_BUFFER,
+            buffer_info(buffer),
+        )
+    }
+
+    pub fn storage_buffer(&mut self, binding: u32, buffer: &impl BufferLike) -> &mut Self {
+        self.buffer(
+            binding,
+            0,
+            vk::DescriptorType::STORAGE_BUFFER,
+            buffer_info(buffer),
+        )
+    }
+
+    /// The descriptor writes targeting `set`.
+    pub fn writes(&self, set: vk::DescriptorSet) -> Vec<vk::WriteDescriptorSet<'_>> {
+        self.writes
+            .iter()
+            .map(|write| {
+                let info = vk::WriteDescriptorSet::default()
+                    .dst_set(set)
+                    .dst_binding(write.binding)
+                    .dst_array_element(write.array_element)
+                    .descriptor_type(write.ty);
+                match write.info {
+                    DescriptorInfo::Image(i) => {
+                        info.image_info(std::slice::from_ref(&self.image_infos[i]))
+                    }
+                    DescriptorInfo::Buffer(i) => {
+                        info.buffer_info(std::slice::from_ref(&self.buffer_infos[i]))
+                    }
+                }
+            })
+            .collect()
+    }
+
+    /// Update `set` with the collected writes.
+    pub fn write(&self, device: &Device, set: vk::DescriptorSet) {
+        unsafe {
+            device.update_descriptor_sets(&self.writes(set), &[]);
+        }
+    }
+
+    pub(super) fn descriptors(
+        &self,
+    ) -> impl Iterator<Item = (u32, u32, vk::DescriptorType, DescriptorRef<'_>)> {
+        self.writes.iter().map(|write| {
+            let info = match write.info {
+                DescriptorInfo::Image(i) => DescriptorRef::Image(&self.image_infos[i]),
+                DescriptorInfo::Buffer(i) => DescriptorRef::Buffer(&self.buffer_infos[i]),
+            };
+            (write.binding, write.array_element, write.ty, info)
+        })
+    }
+
+    pub fn clear(&mut self) {
+        self.image_infos.clear();
+        self.buffer_infos.clear();
+        self.writes.clear();
+    }
+}
+
+fn buffer_info(buffer: &impl BufferLike) -> vk::DescriptorBufferInfo {
+    vk::DescriptorBufferInfo {
+        buffer: buffer.raw_buffer(),
+        offset: buffer.offset(),
+        range: buffer.size(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::{self, Handle};
+
+    use super::DescriptorSetWriter;
+
+    #[test]
+    fn test_writes() {
+        let set = vk::DescriptorSet::from_raw(1);
+        let image_info = vk::DescriptorImageInfo {
+            image_view: vk::ImageView::from_raw(2),
+            image_layout: vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ..Default::default()
+        };
+        let buffer_info = vk::DescriptorBufferInfo {
+            buffer: vk::Buffer::from_raw(3),
+            offset: 16,
+            range: 64,
+        };
+        let storage_info = vk::DescriptorImageInfo {
+            image_view: vk::ImageView::from_raw(4),
+            image_layout: vk::ImageLayout::GENERAL,
+            ..Default::default()
+        };
+        let mut writer = DescriptorSetWriter::new();
+        writer
+            .image(0, 2, vk::DescriptorType::SAMPLED_IMAGE, image_info)
+            .buffer(1, 0, vk::DescriptorType::UNIFORM_BUFFER, buffer_info)
+            .image(3, 0, vk::DescriptorType::STORAGE_IMAGE, storage_info);
+
+        let writes = writer.writes(set);
+        assert_eq!(writes.len(), 3);
+        for write in writes.iter() {
+            assert_eq!(write.dst_set, set);
+            assert_eq!(write.descriptor_count, 1);
+        }
+
+        assert_eq!(writes[0].dst_binding, 0);
+        assert_eq!(writes[0].dst_array_element, 2);
+        assert_eq!(writes[0].descriptor_type, vk::DescriptorType::SAMPLED_IMAGE);
+        assert!(writes[0].p_buffer_info.is_null());
+        let info = unsafe { &*writes[0].p_image_info };
+        assert_eq!(info.image_view, image_info.image_view);
+        assert_eq!(info.image_layout, image_info.image_layout);
+
+        assert_eq!(writes[1].dst_binding, 1);
+        assert_eq!(
+            writes[1].descriptor_type,
+            vk::DescriptorType::UNIFORM_BUFFER
+        );
+        assert!(writes[1].p_image_info.is_null());
+        let info = unsafe { &*writes[1].p_buffer_info };
+        assert_eq!(info.buffer, buffer_info.buffer);
+        assert_eq!((info.offset, info.range), (16, 64));
+
+        // Image infos are indexed separately from buffer infos.
+        assert_eq!(writes[2].dst_binding, 3);
+        let info = unsafe { &*writes[2].p_image_info };
+        assert_eq!(info.image_view, storage_info.image_view);
+        assert_eq!(info.image_layout, vk::ImageLayout::GENERAL);
+    }
+
+    #[test]
+    fn test_push_descriptor_writes() {
+        let mut writer = DescriptorSetWriter::new();
+        writer.image(
+            0,
+            0,
+            vk::DescriptorType::SAMPLER,
+            vk::DescriptorImageInfo {
+                sampler: vk::Sampler::from_raw(5),
+                ..Default::default()
+            },
+        );
+        let writes = writer.writes(vk::DescriptorSet::null());
+        assert_eq!(writes[0].dst_set, vk::DescriptorSet::null());
+        assert_eq!(writes[0].descriptor_type, vk::DescriptorType::SAMPLER);
+        assert_eq!(
+            unsafe { &*writes[0].p_image_info }.sampler,
+            vk::Sampler::from_raw(5)
+        );
+    }
+
+    #[test]
+    fn test_clear() {
+        let mut writer = DescriptorSetWriter::new();
+        writer.buffer(
+            0,
+            0,
+            vk::DescriptorType::STORAGE_BUFFER,
+            vk::DescriptorBufferInfo::default(),
+        );
+        writer.clear();
+        assert!(writer.writes(vk::DescriptorSet::null()).is_empty());
+        assert_eq!(writer.descriptors().count(), 0);
+    }
+}
@@ -0,0 +1,308 @@
+use ash::{ext, prelude::VkResult, vk};
+use bevy::app::{App, Plugin};
+
+use crate::{
+    buffer::{Buffer, BufferLike},
+    future::RecordContext,
+    plugin::remove_device_extension,
+    Allocator, Device, HasDevice, RhyoliteApp,
+};
+
+use super::{
+    descriptor::{DescriptorRef, DescriptorSetWriter},
+    DescriptorSetLayout,
+};
+
+/// Enables `VK_EXT_descriptor_buffer` if the device supports it.
+///
+/// The extension is optional. Use [`DescriptorBuffer::is_supported`] to decide between
+/// descriptor buffers and the [`DescriptorAllocator`](super::DescriptorAllocator).
+pub struct DescriptorBufferPlugin;
+
+impl Plugin for DescriptorBufferPlugin {
+    fn build(&self, app: &mut App) {
+        // Buffer descriptors are written from device addresses. This is checked first so that the
+        // extension is never enabled without being usable.
+        let supported = app
+            .enable_feature::<vk::PhysicalDeviceBufferDeviceAddressFeatures>(|f| {
+                &mut f.buffer_device_address
+            })
+            .exists()
+            && app
+                .add_device_extension::<ext::descriptor_buffer::Meta>()
+                .is_ok();
+        let supported = supported && {
+            let feature = app
+                .enable_feature::<vk::PhysicalDeviceDescriptorBufferFeaturesEXT>(|f| {
+                    &mut f.descriptor_buffer
+                })
+                .exists();
+            if !feature {
+                remove_device_extension::<ext::descriptor_buffer::Meta>(app);
+            }
+            feature
+        };
+        if !supported {
+            tracing::info!(
+                "VK_EXT_descriptor_buffer not supported. Falling back to descriptor pools"
+            );
+        }
+    }
+}
+
+/// A buffer of descriptor sets, for layouts created with the `DESCRIPTOR_BUFFER_EXT` flag.
+/// Pipelines using these layouts must be created with [`vk::PipelineCreateFlags::DESCRIPTOR_BUFFER_EXT`].
+///
+/// Sets are allocated linearly and freed all at once with [`DescriptorBuffer::reset`].
+pub struct DescriptorBuffer {
+    buffer: Buffer,
+    usage: vk::BufferUsageFlags,
+    head: vk::DeviceSize,
+}
+
+impl HasDevice for DescriptorBuffer {
+    fn device(&self) -> &Device {
+        self.buffer.device()
+    }
+}
+
+impl DescriptorBuffer {
+    /// Returns true if the `descriptorBuffer` feature was enabled by the [`DescriptorBufferPlugin`].
+    pub fn is_supported(device: &Device) -> bool {
+        device
+            .feature::<vk::PhysicalDeviceDescriptorBufferFeaturesEXT>()
+            .is_some_and(|f| f.descriptor_buffer == vk::TRUE)
+    }
+
+    /// Create a descriptor buffer of `size` bytes.
+    ///
+    /// `usage` is `RESOURCE_DESCRIPTOR_BUFFER_EXT`, `SAMPLER_DESCRIPTOR_BUFFER_EXT`, or both if
+    /// the set layouts contain samplers as well as resources.
+    pub fn new(
+        allocator: Allocator,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+    ) -> VkResult<Self> {
+        assert!(Self::is_supported(allocator.device()));
+        let alignment = allocator
+            .device()
+            .physical_device()
+            .properties()
+            .get::<vk::PhysicalDeviceDescriptorBufferPropertiesEXT>()
+            .descriptor_buffer_offset_alignment;
+        // Descriptors are written on the host.
+        let buffer = Buffer::new_dynamic(
+            allocator,
+            size,
+            alignment,
+            usage | vk::BufferUsageFlags::SHADER_DEVICE_ADDRESS,
+        )?;
+        Ok(Self {
+            buffer,
+            usage,
+            head: 0,
+        })
+    }
+
+    fn properties(&self) -> &vk::PhysicalDeviceDescriptorBufferPropertiesEXT<'static> {
+        self.buffer
+            .device()
+            .physical_device()
+            .properties()
+            .get::<vk::PhysicalDeviceDescriptorBufferPropertiesEXT>()
+    }
+
+    /// Reserve space for one set of `layout`. Returns the offset of the set in the buffer,
+    /// or None if the buffer is full.
+    pub fn allocate(&mut self, layout: &DescriptorSetLayout) -> Option<vk::DeviceSize> {
+        let size = layout
+            .descriptor_buffer_size()
+            .expect("Layout must be created with the DESCRIPTOR_BUFFER_EXT flag");
+        let alignment = self.properties().descriptor_buffer_offset_alignment;
+        let (offset, head) = bump_allocate(self.head, size, alignment, self.buffer.size())?;
+        self.head = head;
+        Some(offset)
+    }
+
+    /// Free all sets allocated from this buffer.
+    ///
+    /// # Safety
+    /// The GPU must have finished executing all command buffers using the sets.
+    pub unsafe fn reset(&mut self) {
+        self.head = 0;
+    }
+
+    /// Write the descriptors collected by `writer` into the set at `set_offset`.
+    ///
+    /// Buffers must have been created with `SHADER_DEVICE_ADDRESS` usage and an explicit size.
+    pub fn write(
+        &mut self,
+        layout: &DescriptorSetLayout,
+        set_offset: vk::DeviceSize,
+        writer: &DescriptorSetWriter,
+    ) {
+        let device = self.buffer.device().clone();
+        let ext = device.extension::<ext::descriptor_buffer::Meta>();
+        let combined_image_sampler_single_array = self
+            .properties()
+            .combined_image_sampler_descriptor_single_array
+            == vk::TRUE;
+        for (binding, array_element, ty, info) in writer.descriptors() {
+            let descriptor_size = self.descriptor_size(ty);
+            assert!(
+                ty != vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+                    || array_element == 0
+                    || combined_image_sampler_single_array,
+                "Arrays of combined image samplers are not supported on this device"
+            );
+            let offset = set_offset
+                + layout
+                    .descriptor_buffer_binding_offset(binding)
+                    .expect("Binding not found in the layout")
+                + array_element as vk::DeviceSize * descriptor_size as vk::DeviceSize;
+            let address_info;
+            let data = match info {
+                DescriptorRef::Image(image) => match ty {
+                    vk::DescriptorType::SAMPLER => vk::DescriptorDataEXT {
+                        p_sampler: &image.sampler,
+                    },
+                    vk::DescriptorType::COMBINED_IMAGE_SAMPLER => vk::DescriptorDataEXT {
+                        p_combined_image_sampler: image,
+                    },
+                    vk::DescriptorType::SAMPLED_IMAGE => vk::DescriptorDataEXT {
+                        p_sampled_image: image,
+                    },
+                    vk::DescriptorType::STORAGE_IMAGE => vk::DescriptorDataEXT {
+                        p_storage_image: image,
+                    },
+                    vk::DescriptorType::INPUT_ATTACHMENT => vk::DescriptorDataEXT {
+                        p_input_attachment_image: image,
+                    },
+                    _ => panic!("Unsupported image descriptor type {ty:?}"),
+                },
+                DescriptorRef::Buffer(buffer) => {
+                    assert_ne!(buffer.range, vk::WHOLE_SIZE);
+                    let address = unsafe {
+                        device.get_buffer_device_address(&vk::BufferDeviceAddressInfo {
+                            buffer: buffer.buffer,
+                            ..Default::default()
+                        })
+                    };
+                    address_info = vk::DescriptorAddressInfoEXT {
+                        address: address + buffer.offset,
+                        range: buffer.range,
+                        ..Default::default()
+                    };
+                    match ty {
+                        vk::DescriptorType::UNIFORM_BUFFER => vk::DescriptorDataEXT {
+                            p_uniform_buffer: &address_info,
+                        },
+                        vk::DescriptorType::STORAGE_BUFFER => vk::DescriptorDataEXT {
+                            p_storage_buffer: &address_info,
+                        },
+                        _ => panic!("Unsupported buffer descriptor type {ty:?}"),
+                    }
+                }
+            };
+            let dst =
+                &mut self.buffer.as_slice_mut()[offset as usize..offset as usize + descriptor_size];
+            unsafe {
+                ext.get_descriptor(&vk::DescriptorGetInfoEXT::default().ty(ty).data(data), dst);
+            }
+        }
+    }
+
+    /// The binding info for [`RecordContext::bind_descriptor_buffers`].
+    pub fn binding_info(&self) -> vk::DescriptorBufferBindingInfoEXT<'static> {
+        vk::DescriptorBufferBindingInfoEXT {
+            address: self.buffer.device_address(),
+            usage: self.usage,
+            ..Default::default()
+        }
+    }
+
+    fn descriptor_size(&self, ty: vk::DescriptorType) -> usize {
+        let properties = self.properties();
+        match ty {
+            vk::DescriptorType::SAMPLER => properties.sampler_descriptor_size,
+            vk::DescriptorType::COMBINED_IMAGE_SAMPLER => {
+                properties.combined_image_sampler_descriptor_size
+            }
+            vk::DescriptorType::SAMPLED_IMAGE => properties.sampled_image_descriptor_size,
+            vk::DescriptorType::STORAGE_IMAGE => properties.storage_image_descriptor_size,
+            vk::DescriptorType::INPUT_ATTACHMENT => properties.input_attachment_descriptor_size,
+            vk::DescriptorType::UNIFORM_BUFFER => properties.uniform_buffer_descriptor_size,
+            vk::DescriptorType::STORAGE_BUFFER => properties.storage_buffer_descriptor_size,
+            _ => panic!("Unsupported descriptor type {ty:?}"),
+        }
+    }
+}
+
+/// Returns the offset of a `size` bytes allocation at or after `head`, and the new head.
+fn bump_allocate(
+    head: vk::DeviceSize,
+    size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    capacity: vk::DeviceSize,
+) -> Option<(vk::DeviceSize, vk::DeviceSize)> {
+    let offset = head.next_multiple_of(alignment.max(1));
+    let end = offset.checked_add(size)?;
+    (end <= capacity).then_some((offset, end))
+}
+
+impl<'a> RecordContext<'a> {
+    /// Requires `VK_EXT_descriptor_buffer`. Sets are bound with
+    /// [`RecordContext::set_descriptor_buffer_offsets`].
+    pub fn bind_descriptor_buffers(
+        &mut self,
+        binding_infos: &[vk::DescriptorBufferBindingInfoEXT],
+    ) {
+        unsafe {
+            self.device
+                .extension::<ext::descriptor_buffer::Meta>()
+                .cmd_bind_descriptor_buffers(self.command_buffer, binding_infos);
+        }
+    }
+    /// Bind sets at `offsets` in the descriptor buffers at `buffer_indices` of the last
+    /// [`RecordContext::bind_descriptor_buffers`] call.
+    pub fn set_descriptor_buffer_offsets(
+        &mut self,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        buffer_indices: &[u32],
+        offsets: &[vk::DeviceSize],
+    ) {
+        unsafe {
+            self.device
+                .extension::<ext::descriptor_buffer::Meta>()
+                .cmd_set_descriptor_buffer_offsets(
+                    self.command_buffer,
+                    bind_point,
+                    layout,
+                    first_set,
+                    buffer_indices,
+                    offsets,
+                );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::bump_allocate;
+
+    #[test]
+    fn test_bump_allocate() {
+        assert_eq!(bump_allocate(0, 48, 64, 256), Some((0, 48)));
+        // Sets start at the offset alignment.
+        assert_eq!(bump_allocate(48, 48, 64, 256), Some((64, 112)));
+        assert_eq!(bump_allocate(112, 128, 64, 256), Some((128, 256)));
+        // The buffer is full.
+        assert_eq!(bump_allocate(256, 1, 64, 256), None);
+        assert_eq!(bump_allocate(192, 80, 64, 256), None);
+        // Sets with no descriptors take no space.
+        assert_eq!(bump_allocate(100, 0, 1, 100), Some((100, 100)));
+        assert_eq!(bump_allocate(0, u64::MAX, 1, 256), None);
+    }
+}
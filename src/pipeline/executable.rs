@@ -0,0 +1,179 @@
+use std::ffi::CStr;
+
+use ash::{khr, prelude::VkResult, vk};
+
+use crate::Device;
+
+/// Compiler statistics and internal representations of one executable in a pipeline, as reported
+/// by `VK_KHR_pipeline_executable_properties`. A pipeline usually has one executable per shader
+/// stage, but drivers are free to merge or split stages.
+#[derive(Debug, Clone)]
+pub struct PipelineExecutableInfo {
+    pub name: String,
+    pub description: String,
+    pub stages: vk::ShaderStageFlags,
+    pub subgroup_size: u32,
+    pub statistics: Vec<PipelineExecutableStatistic>,
+    /// Only captured with [`vk::PipelineCreateFlags::CAPTURE_INTERNAL_REPRESENTATIONS_KHR`].
+    pub internal_representations: Vec<PipelineInternalRepresentation>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineExecutableStatistic {
+    pub name: String,
+    pub description: String,
+    pub value: PipelineStatisticValue,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PipelineStatisticValue {
+    Bool(bool),
+    I64(i64),
+    U64(u64),
+    F64(f64),
+}
+
+impl std::fmt::Display for PipelineStatisticValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Bool(value) => value.fmt(f),
+            Self::I64(value) => value.fmt(f),
+            Self::U64(value) => value.fmt(f),
+            Self::F64(value) => value.fmt(f),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct PipelineInternalRepresentation {
+    pub name: String,
+    pub description: String,
+    /// If true, `data` is human readable text, for example disassembly.
+    pub is_text: bool,
+    pub data: Vec<u8>,
+}
+
+impl PipelineInternalRepresentation {
+    /// Returns the representation as text, or None if it is binary.
+    pub fn text(&self) -> Option<std::borrow::Cow<'_, str>> {
+        if !self.is_text {
+            return None;
+        }
+        let data = self.data.strip_suffix(&[0]).unwrap_or(&self.data);
+        Some(String::from_utf8_lossy(data))
+    }
+}
+
+fn c_str_to_string(s: Result<&CStr, std::ffi::FromBytesUntilNulError>) -> String {
+    s.map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+impl PipelineExecutableInfo {
+    /// Query the executables of `pipeline`. The pipeline must have been created with
+    /// [`vk::PipelineCreateFlags::CAPTURE_STATISTICS_KHR`] on a device with the
+    /// `pipelineExecutableInfo` feature enabled.
+    pub fn query(device: &Device, pipeline: vk::Pipeline) -> VkResult<Vec<Self>> {
+        let ext = device.extension::<khr::pipeline_executable_properties::Meta>();
+        let properties = unsafe {
+            ext.get_pipeline_executable_properties(
+                &vk::PipelineInfoKHR::default().pipeline(pipeline),
+            )?
+        };
+        properties
+            .iter()
+            .enumerate()
+            .map(|(index, properties)| {
+                let info = vk::PipelineExecutableInfoKHR::default()
+                    .pipeline(pipeline)
+                    .executable_index(index as u32);
+                let statistics = unsafe { ext.get_pipeline_executable_statistics(&info)? }
+                    .iter()
+                    .map(|statistic| PipelineExecutableStatistic {
+                        name: c_str_to_string(statistic.name_as_c_str()),
+                        description: c_str_to_string(statistic.description_as_c_str()),
+                        value: unsafe {
+                            match statistic.format {
+                                vk::PipelineExecutableStatisticFormatKHR::BOOL32 => {
+                                    PipelineStatisticValue::Bool(statistic.value.b32 == vk::TRUE)
+                                }
+                                vk::PipelineExecutableStatisticFormatKHR::INT64 => {
+                                    PipelineStatisticValue::I64(statistic.value.i64)
+                                }
+                                vk::PipelineExecutableStatisticFormatKHR::FLOAT64 => {
+                                    PipelineStatisticValue::F64(statistic.value.f64)
+                                }
+                                _ => PipelineStatisticValue::U64(statistic.value.u64),
+                            }
+                        },
+                    })
+                    .collect();
+                Ok(Self {
+                    name: c_str_to_string(properties.name_as_c_str()),
+                    description: c_str_to_string(properties.description_as_c_str()),
+                    stages: properties.stages,
+                    subgroup_size: properties.subgroup_size,
+                    statistics,
+                    internal_representations: Self::query_internal_representations(ext, &info)?,
+                })
+            })
+            .collect()
+    }
+
+    fn query_internal_representations(
+        ext: &khr::pipeline_executable_properties::Device,
+        info: &vk::PipelineExecutableInfoKHR,
+    ) -> VkResult<Vec<PipelineInternalRepresentation>> {
+        // The first pass only returns the data sizes. Allocate the buffers and query again.
+        let mut representations =
+            unsafe { ext.get_pipeline_executable_internal_representations(info)? };
+        if representations.is_empty() {
+            return Ok(Vec::new());
+        }
+        let mut data: Vec<Vec<u8>> = representations
+            .iter()
+            .map(|representation| vec![0; representation.data_size])
+            .collect();
+        for (representation, data) in representations.iter_mut().zip(data.iter_mut()) {
+            representation.p_data = data.as_mut_ptr().cast();
+        }
+        let mut count = representations.len() as u32;
+        unsafe {
+            (ext.fp()
+                .get_pipeline_executable_internal_representations_khr)(
+                ext.device(),
+                info,
+                &mut count,
+                representations.as_mut_ptr(),
+            )
+            .result()?;
+        }
+        Ok(representations
+            .iter()
+            .zip(data)
+            .map(|(representation, data)| PipelineInternalRepresentation {
+                name: c_str_to_string(representation.name_as_c_str()),
+                description: c_str_to_string(representation.description_as_c_str()),
+                is_text: representation.is_text == vk::TRUE,
+                data,
+            })
+            .collect())
+    }
+
+    /// Log the statistics of this executable.
+    pub fn log(&self) {
+        let statistics = self
+            .statistics
+            .iter()
+            .map(|statistic| format!("{} = {}", statistic.name, statistic.value))
+            .collect::<Vec<_>>()
+            .join(", ");
+        tracing::info!(
+            "Pipeline executable {} ({:?}, subgroup size {}): {}",
+            self.name,
+            self.stages,
+            self.subgroup_size,
+            statistics
+        );
+    }
+}
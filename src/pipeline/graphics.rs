@@ -1,94 +1,176 @@
-use std::sync::Arc;
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex, Weak},
+};
 
 use ash::{prelude::VkResult, vk};
 use bevy::asset::{AssetId, Assets};
 
-use crate::shader::{ShaderModule, SpecializedShader};
+use crate::shader::{ShaderModule, SpecializationInfo, SpecializedShader};
 use crate::{
     deferred::{DeferredOperationTaskPool, Task},
     Device,
 };
 
-use super::{PipelineInner, PipelineLayout};
+use super::{GraphicsPipelineState, PipelineInner, PipelineLayout};
 
+/// Pipelines with identical [`GraphicsPipelineState`], shaders and layout share the same
+/// [`vk::Pipeline`].
 #[derive(Clone)]
-pub struct GraphicsPipeline(Arc<PipelineInner>);
+pub struct GraphicsPipeline {
+    pipeline: Arc<PipelineInner>,
+    /// The graphics pipeline libraries the pipeline was linked from. Kept alive so that they may
+    /// be reused by other pipelines and for the optimized link.
+    libraries: Arc<[Arc<PipelineInner>]>,
+}
 impl GraphicsPipeline {
     pub fn raw(&self) -> vk::Pipeline {
-        self.0.pipeline
+        self.pipeline.pipeline
     }
 }
 impl super::Pipeline for GraphicsPipeline {
-    type BuildInfo = BoxedGraphicsPipelineBuildInfo;
+    type BuildInfo = CachedGraphicsPipelineBuildInfo;
     const TYPE: vk::PipelineBindPoint = vk::PipelineBindPoint::GRAPHICS;
     fn from_built(
-        _info: &mut BoxedGraphicsPipelineBuildInfo,
+        _info: &mut CachedGraphicsPipelineBuildInfo,
         item: <Self::BuildInfo as super::PipelineBuildInfo>::Pipeline,
     ) -> Self {
-        GraphicsPipeline(Arc::new(item))
+        item
     }
 
     fn as_raw(&self) -> vk::Pipeline {
-        self.0.pipeline
+        self.pipeline.pipeline
     }
 }
 
-pub struct Builder<'a> {
-    device: &'a Device,
-    cache: vk::PipelineCache,
-    stages: &'a [vk::PipelineShaderStageCreateInfo<'a>],
-    layout: &'a PipelineLayout,
+#[derive(Clone)]
+pub struct GraphicsPipelineBuildInfo {
+    pub device: Device,
+    /// Vertex, tessellation, geometry and fragment stages, or with the [`MeshShaderPlugin`](super::MeshShaderPlugin),
+    /// task, mesh and fragment stages.
+    pub stages: Vec<SpecializedShader>,
+    pub state: GraphicsPipelineState,
+    pub layout: Arc<PipelineLayout>,
 }
 
-pub struct BuilderResult(VkResult<vk::Pipeline>);
-impl<'a> Builder<'a> {
-    /// All fields except `stages` and `layout` of the [`vk::GraphicsPipelineCreateInfo`] are required to be configured
-    /// as specified in [the Vulkan specification](https://vkdoc.net/man/VkGraphicsPipelineCreateInfo).
-    /// `stages` and `layout` will be automatically filled with [`Self::stages`] and [`Self::layout`] respectively.
-    #[must_use]
-    #[inline]
-    pub fn build(self, info: vk::GraphicsPipelineCreateInfo<'a>) -> BuilderResult {
-        let mut pipeline = vk::Pipeline::null();
-        let info = info.stages(&self.stages).layout(self.layout.raw());
-        let result = unsafe {
-            (self.device.fp_v1_0().create_graphics_pipelines)(
-                self.device.handle(),
-                self.cache,
-                1,
-                &info,
-                std::ptr::null(),
-                &mut pipeline,
-            )
-        };
-        BuilderResult(result.result_with_success(pipeline))
+/// Everything that goes into a graphics pipeline or a graphics pipeline library.
+/// Pipelines with equal keys are interchangeable.
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(super) struct GraphicsPipelineKey {
+    /// Empty for complete pipelines.
+    library: vk::GraphicsPipelineLibraryFlagsEXT,
+    state: GraphicsPipelineState,
+    /// None for the libraries without shaders.
+    layout: Option<LayoutKey>,
+    stages: Vec<ShaderStageKey>,
+}
+
+/// Compares pipeline layouts by identity. Raw handles may be reused by the driver once a layout
+/// is destroyed, while the layout held by the key can't be destroyed as long as the key is in
+/// the registry.
+#[derive(Clone)]
+pub(super) struct LayoutKey(Arc<PipelineLayout>);
+impl PartialEq for LayoutKey {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+impl Eq for LayoutKey {}
+impl Hash for LayoutKey {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state)
     }
 }
 
-pub struct GraphicsPipelineBuildInfo<F>
-where
-    F: for<'a> Fn(Builder<'a>) -> BuilderResult + Send + Sync,
-{
-    pub device: Device,
-    pub stages: Vec<SpecializedShader>,
-    /// The builder function that will be called asynchronously to create the pipeline.
-    ///
-    /// This function is expected to call [`Builder::build`] with a [`vk::GraphicsPipelineCreateInfo`].
-    /// All fields except `stages` and `layout` of the [`vk::GraphicsPipelineCreateInfo`] are required to be configured
-    /// as specified in [the Vulkan specification](https://vkdoc.net/man/VkGraphicsPipelineCreateInfo).
-    /// `stages` and `layout` will be automatically filled with [`Self::stages`] and [`Self::layout`] respectively.
-    pub builder: F,
-    pub layout: Arc<PipelineLayout>,
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(super) struct ShaderStageKey {
+    stage: vk::ShaderStageFlags,
+    flags: vk::PipelineShaderStageCreateFlags,
+    /// The shader asset and the [`ShaderModule::generation`] it was built with, so that a hot
+    /// reloaded shader never matches a pipeline built from the module it replaced.
+    shader: AssetId<ShaderModule>,
+    generation: u64,
+    entry_point: &'static CStr,
+    specialization_data: Vec<u8>,
+    specialization_entries: Vec<(u32, u32, usize)>,
 }
 
-pub struct BoxedGraphicsPipelineBuildInfo {
-    pub device: Device,
-    pub stages: Vec<SpecializedShader>,
-    pub builder: Arc<dyn for<'a> Fn(Builder<'a>) -> BuilderResult + Send + Sync>,
-    pub layout: Arc<PipelineLayout>,
+/// Graphics pipelines and libraries built so far, used to deduplicate identical pipelines.
+pub(super) type GraphicsPipelineRegistry =
+    Arc<Mutex<HashMap<GraphicsPipelineKey, RegisteredPipeline>>>;
+
+pub(super) struct RegisteredPipeline {
+    pipeline: Weak<PipelineInner>,
+    /// The libraries of a fast-linked pipeline. None for libraries, pipelines built without
+    /// libraries, and optimized pipelines.
+    libraries: Option<Weak<[Arc<PipelineInner>]>>,
 }
 
-impl super::PipelineBuildInfo for BoxedGraphicsPipelineBuildInfo {
-    type Pipeline = PipelineInner;
+fn register(
+    registry: &GraphicsPipelineRegistry,
+    key: GraphicsPipelineKey,
+    pipeline: &Arc<PipelineInner>,
+    libraries: Option<&Arc<[Arc<PipelineInner>]>>,
+) {
+    let mut registry = registry.lock().unwrap();
+    registry.retain(|_, registered| registered.pipeline.strong_count() > 0);
+    registry.insert(
+        key,
+        RegisteredPipeline {
+            pipeline: Arc::downgrade(pipeline),
+            libraries: libraries.map(Arc::downgrade),
+        },
+    );
+}
+
+/// Returns the registered pipeline for `key`, along with its libraries if it was fast-linked.
+fn lookup(
+    registry: &GraphicsPipelineRegistry,
+    key: &GraphicsPipelineKey,
+) -> Option<GraphicsPipeline> {
+    let registry = registry.lock().unwrap();
+    let registered = registry.get(key)?;
+    Some(GraphicsPipeline {
+        pipeline: registered.pipeline.upgrade()?,
+        // The owners of a fast-linked pipeline keep its libraries alive along with the pipeline.
+        libraries: registered
+            .libraries
+            .as_ref()
+            .and_then(Weak::upgrade)
+            .unwrap_or_else(|| Arc::new([])),
+    })
+}
+
+/// Returns true if graphics pipelines should be built from libraries and linked.
+fn use_pipeline_libraries(device: &Device) -> bool {
+    device
+        .feature::<vk::PhysicalDeviceGraphicsPipelineLibraryFeaturesEXT>()
+        .is_some_and(|f| f.graphics_pipeline_library == vk::TRUE)
+        && device
+            .physical_device()
+            .properties()
+            .get::<vk::PhysicalDeviceGraphicsPipelineLibraryPropertiesEXT>()
+            .graphics_pipeline_library_fast_linking
+            == vk::TRUE
+}
+
+/// A [`GraphicsPipelineBuildInfo`] tracked by the [`PipelineCache`](super::PipelineCache).
+///
+/// With `VK_EXT_graphics_pipeline_library`, the vertex input, pre-rasterization, fragment shader
+/// and fragment output parts of the pipeline are built as separate libraries, which are shared
+/// between pipelines. The libraries are linked quickly into an unoptimized pipeline, and an
+/// optimized link is scheduled in the background to replace it.
+pub struct CachedGraphicsPipelineBuildInfo {
+    pub(super) info: GraphicsPipelineBuildInfo,
+    pub(super) registry: GraphicsPipelineRegistry,
+    /// The key of the pipeline last scheduled by [`PipelineBuildInfo::build`](super::PipelineBuildInfo::build).
+    pub(super) last_key: Option<GraphicsPipelineKey>,
+}
+
+impl super::PipelineBuildInfo for CachedGraphicsPipelineBuildInfo {
+    type Pipeline = GraphicsPipeline;
 
     fn build(
         &mut self,
@@ -96,21 +178,55 @@ impl super::PipelineBuildInfo for BoxedGraphicsPipelineBuildInfo {
         assets: &Assets<ShaderModule>,
         cache: vk::PipelineCache,
     ) -> Option<Task<Self::Pipeline>> {
-        let device = self.device.clone();
-        let builder = self.builder.clone();
-        let modules = self
+        let info = &self.info;
+        let modules = info
             .stages
             .iter()
-            .map(|shader| assets.get(&shader.shader).map(|s| s.raw()))
+            .map(|shader| assets.get(&shader.shader))
             .collect::<Option<Vec<_>>>()?;
-        let stages = self.stages.clone();
-        let layout = self.layout.clone();
+        let key = GraphicsPipelineKey {
+            library: vk::GraphicsPipelineLibraryFlagsEXT::empty(),
+            state: info.state.clone(),
+            layout: Some(LayoutKey(info.layout.clone())),
+            stages: info
+                .stages
+                .iter()
+                .zip(modules.iter())
+                .map(|(shader, module)| ShaderStageKey {
+                    stage: shader.stage,
+                    flags: shader.flags,
+                    shader: shader.shader.id(),
+                    generation: module.generation(),
+                    entry_point: shader.entry_point,
+                    specialization_data: shader.specialization_info.data().to_vec(),
+                    specialization_entries: shader
+                        .specialization_info
+                        .entries()
+                        .iter()
+                        .map(|entry| (entry.constant_id, entry.offset, entry.size))
+                        .collect(),
+                })
+                .collect(),
+        };
+        let modules: Vec<_> = modules.iter().map(|module| module.raw()).collect();
+        self.last_key = Some(key.clone());
+        if let Some(pipeline) = lookup(&self.registry, &key) {
+            // A fast-linked pipeline is returned with its libraries, so that every owner
+            // upgrades to the optimized pipeline.
+            return Some(pool.schedule(move || Ok(pipeline)));
+        }
+
+        let device = info.device.clone();
+        let stages = info.stages.clone();
+        let layout = info.layout.clone();
+        let registry = self.registry.clone();
+        let use_libraries = use_pipeline_libraries(&device);
         Some(pool.schedule(move || {
             let raw_specialization_info = stages
                 .iter()
                 .map(SpecializedShader::raw_specialization_info)
                 .collect::<Vec<_>>();
-            let stages = stages
+            let raw_stages = stages
                 .iter()
                 .zip(raw_specialization_info.iter())
                 .zip(modules.into_iter())
@@ -119,26 +235,161 @@ impl super::PipelineBuildInfo for BoxedGraphicsPipelineBuildInfo {
                         .flags(shader.flags)
                         .stage(shader.stage)
                         .module(module)
-                        .name(&shader.entry_point)
+                        .name(shader.entry_point)
                         .specialization_info(specialization_info)
                 })
                 .collect::<Vec<_>>();
-            let result = builder(Builder {
-                device: &device,
-                cache,
-                stages: &stages,
-                layout: &layout,
-            })
-            .0?;
-            drop(stages);
+
+            if !use_libraries {
+                let pipeline = key.state.create_pipeline(
+                    &device,
+                    cache,
+                    &raw_stages,
+                    layout.raw(),
+                    vk::GraphicsPipelineLibraryFlagsEXT::empty(),
+                )?;
+                let pipeline = Arc::new(PipelineInner { device, pipeline });
+                register(&registry, key, &pipeline, None);
+                return Ok(GraphicsPipeline {
+                    pipeline,
+                    libraries: Arc::new([]),
+                });
+            }
+
+            let is_mesh_pipeline = stages
+                .iter()
+                .any(|shader| shader.stage == vk::ShaderStageFlags::MESH_EXT);
+            let mut parts = vec![
+                vk::GraphicsPipelineLibraryFlagsEXT::PRE_RASTERIZATION_SHADERS,
+                vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER,
+                vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_OUTPUT_INTERFACE,
+            ];
+            if !is_mesh_pipeline {
+                parts.push(vk::GraphicsPipelineLibraryFlagsEXT::VERTEX_INPUT_INTERFACE);
+            }
+            let libraries = parts
+                .into_iter()
+                .map(|part| {
+                    let stage_in_part = |stage: vk::ShaderStageFlags| match part {
+                        vk::GraphicsPipelineLibraryFlagsEXT::PRE_RASTERIZATION_SHADERS => {
+                            stage != vk::ShaderStageFlags::FRAGMENT
+                        }
+                        vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER => {
+                            stage == vk::ShaderStageFlags::FRAGMENT
+                        }
+                        _ => false,
+                    };
+                    // Only the shader parts of the pipeline depend on the layout.
+                    let part_layout = (part
+                        == vk::GraphicsPipelineLibraryFlagsEXT::PRE_RASTERIZATION_SHADERS
+                        || part == vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER)
+                        .then(|| layout.clone());
+                    let part_key = GraphicsPipelineKey {
+                        library: part,
+                        state: key.state.library_state(part),
+                        layout: part_layout.clone().map(LayoutKey),
+                        stages: key
+                            .stages
+                            .iter()
+                            .filter(|stage| stage_in_part(stage.stage))
+                            .cloned()
+                            .collect(),
+                    };
+                    if let Some(library) = lookup(&registry, &part_key) {
+                        return Ok(library.pipeline);
+                    }
+                    let part_stages: Vec<_> = raw_stages
+                        .iter()
+                        .filter(|stage| stage_in_part(stage.stage))
+                        .copied()
+                        .collect();
+                    let library = part_key.state.create_pipeline(
+                        &device,
+                        cache,
+                        &part_stages,
+                        part_layout.map_or(vk::PipelineLayout::null(), |layout| layout.raw()),
+                        part,
+                    )?;
+                    let library = Arc::new(PipelineInner {
+                        device: device.clone(),
+                        pipeline: library,
+                    });
+                    register(&registry, part_key, &library, None);
+                    Ok(library)
+                })
+                .collect::<VkResult<Vec<_>>>()?;
+            drop(raw_stages);
             drop(raw_specialization_info);
-            Ok(PipelineInner {
-                device,
-                pipeline: result,
+
+            let raw_libraries: Vec<_> = libraries.iter().map(|library| library.pipeline).collect();
+            let pipeline =
+                key.state
+                    .link_pipeline(&device, cache, &raw_libraries, layout.raw(), false)?;
+            let pipeline = Arc::new(PipelineInner { device, pipeline });
+            let libraries: Arc<[_]> = libraries.into();
+            register(&registry, key, &pipeline, Some(&libraries));
+            Ok(GraphicsPipeline {
+                pipeline,
+                libraries,
             })
         }))
     }
     fn all_shaders(&self) -> impl Iterator<Item = AssetId<ShaderModule>> {
-        self.stages.iter().map(|shader| shader.shader.id())
+        self.info.stages.iter().map(|shader| shader.shader.id())
+    }
+
+    fn schedule_upgrade(
+        &mut self,
+        built: &Self::Pipeline,
+        pool: &DeferredOperationTaskPool,
+        cache: vk::PipelineCache,
+    ) -> Option<Task<Self::Pipeline>> {
+        if built.libraries.is_empty() {
+            // Built monolithically, or already optimized.
+            return None;
+        }
+        let key = self.last_key.clone()?;
+        let device = self.info.device.clone();
+        let layout = self.info.layout.clone();
+        let libraries = built.libraries.clone();
+        let registry = self.registry.clone();
+        Some(pool.schedule(move || {
+            // Another owner of the same pipeline may have finished the optimized link already.
+            if let Some(registered) = lookup(&registry, &key) {
+                if registered.libraries.is_empty() {
+                    return Ok(GraphicsPipeline {
+                        pipeline: registered.pipeline,
+                        libraries,
+                    });
+                }
+            }
+            let raw_libraries: Vec<_> = libraries.iter().map(|library| library.pipeline).collect();
+            let pipeline =
+                key.state
+                    .link_pipeline(&device, cache, &raw_libraries, layout.raw(), true)?;
+            let pipeline = Arc::new(PipelineInner { device, pipeline });
+            // Pipelines created from now on share the optimized pipeline.
+            register(&registry, key, &pipeline, None);
+            Ok(GraphicsPipeline {
+                pipeline,
+                libraries,
+            })
+        }))
+    }
+}
+
+impl super::SpecializablePipelineBuildInfo for CachedGraphicsPipelineBuildInfo {
+    fn specialize(&self, constants: &SpecializationInfo) -> Self {
+        let mut info = self.info.clone();
+        for stage in info.stages.iter_mut() {
+            stage.specialization_info.extend(constants);
+        }
+        // The vertex input and fragment output libraries contain no shaders and remain shared
+        // between variants through the registry.
+        Self {
+            info,
+            registry: self.registry.clone(),
+            last_key: None,
+        }
     }
 }
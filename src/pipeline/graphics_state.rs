@@ -0,0 +1,538 @@
+use std::hash::{Hash, Hasher};
+
+use ash::{prelude::VkResult, vk};
+
+use crate::Device;
+
+/// Implements `PartialEq`, `Eq` and `Hash` for state containing floats by comparing their bits.
+macro_rules! impl_eq_hash_by_key {
+    ($ty:ty, |$this:ident| $key:expr) => {
+        impl PartialEq for $ty {
+            fn eq(&self, other: &Self) -> bool {
+                let lhs = {
+                    let $this = self;
+                    $key
+                };
+                let rhs = {
+                    let $this = other;
+                    $key
+                };
+                lhs == rhs
+            }
+        }
+        impl Eq for $ty {}
+        impl Hash for $ty {
+            fn hash<H: Hasher>(&self, state: &mut H) {
+                let $this = self;
+                $key.hash(state)
+            }
+        }
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexBinding {
+    pub binding: u32,
+    pub stride: u32,
+    pub input_rate: vk::VertexInputRate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub binding: u32,
+    pub format: vk::Format,
+    pub offset: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct VertexInputState {
+    pub bindings: Vec<VertexBinding>,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct InputAssemblyState {
+    pub topology: vk::PrimitiveTopology,
+    pub primitive_restart_enable: bool,
+}
+impl Default for InputAssemblyState {
+    fn default() -> Self {
+        Self {
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart_enable: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DepthBias {
+    pub constant_factor: f32,
+    pub clamp: f32,
+    pub slope_factor: f32,
+}
+impl_eq_hash_by_key!(DepthBias, |this| (
+    this.constant_factor.to_bits(),
+    this.clamp.to_bits(),
+    this.slope_factor.to_bits()
+));
+
+#[derive(Clone, Copy, Debug)]
+pub struct RasterizationState {
+    pub depth_clamp_enable: bool,
+    pub rasterizer_discard_enable: bool,
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub depth_bias: Option<DepthBias>,
+    pub line_width: f32,
+}
+impl Default for RasterizationState {
+    fn default() -> Self {
+        Self {
+            depth_clamp_enable: false,
+            rasterizer_discard_enable: false,
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::COUNTER_CLOCKWISE,
+            depth_bias: None,
+            line_width: 1.0,
+        }
+    }
+}
+impl_eq_hash_by_key!(RasterizationState, |this| (
+    this.depth_clamp_enable,
+    this.rasterizer_discard_enable,
+    this.polygon_mode,
+    this.cull_mode,
+    this.front_face,
+    this.depth_bias,
+    this.line_width.to_bits()
+));
+
+#[derive(Clone, Copy, Debug)]
+pub struct MultisampleState {
+    pub rasterization_samples: vk::SampleCountFlags,
+    /// Enables sample shading with the given minimum fraction of samples.
+    pub min_sample_shading: Option<f32>,
+    pub alpha_to_coverage_enable: bool,
+    pub alpha_to_one_enable: bool,
+}
+impl Default for MultisampleState {
+    fn default() -> Self {
+        Self {
+            rasterization_samples: vk::SampleCountFlags::TYPE_1,
+            min_sample_shading: None,
+            alpha_to_coverage_enable: false,
+            alpha_to_one_enable: false,
+        }
+    }
+}
+impl_eq_hash_by_key!(MultisampleState, |this| (
+    this.rasterization_samples,
+    this.min_sample_shading.map(f32::to_bits),
+    this.alpha_to_coverage_enable,
+    this.alpha_to_one_enable
+));
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StencilOpState {
+    pub fail_op: vk::StencilOp,
+    pub pass_op: vk::StencilOp,
+    pub depth_fail_op: vk::StencilOp,
+    pub compare_op: vk::CompareOp,
+    pub compare_mask: u32,
+    pub write_mask: u32,
+    pub reference: u32,
+}
+impl StencilOpState {
+    fn raw(&self) -> vk::StencilOpState {
+        vk::StencilOpState {
+            fail_op: self.fail_op,
+            pass_op: self.pass_op,
+            depth_fail_op: self.depth_fail_op,
+            compare_op: self.compare_op,
+            compare_mask: self.compare_mask,
+            write_mask: self.write_mask,
+            reference: self.reference,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DepthStencilState {
+    pub depth_test_enable: bool,
+    pub depth_write_enable: bool,
+    pub depth_compare_op: vk::CompareOp,
+    /// Enables the depth bounds test with the given min and max bounds.
+    pub depth_bounds: Option<[f32; 2]>,
+    /// Enables the stencil test with the given front and back states.
+    pub stencil: Option<[StencilOpState; 2]>,
+}
+impl Default for DepthStencilState {
+    fn default() -> Self {
+        Self {
+            depth_test_enable: true,
+            depth_write_enable: true,
+            depth_compare_op: vk::CompareOp::LESS_OR_EQUAL,
+            depth_bounds: None,
+            stencil: None,
+        }
+    }
+}
+impl_eq_hash_by_key!(DepthStencilState, |this| (
+    this.depth_test_enable,
+    this.depth_write_enable,
+    this.depth_compare_op,
+    this.depth_bounds.map(|bounds| bounds.map(f32::to_bits)),
+    this.stencil
+));
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BlendState {
+    pub src_color_blend_factor: vk::BlendFactor,
+    pub dst_color_blend_factor: vk::BlendFactor,
+    pub color_blend_op: vk::BlendOp,
+    pub src_alpha_blend_factor: vk::BlendFactor,
+    pub dst_alpha_blend_factor: vk::BlendFactor,
+    pub alpha_blend_op: vk::BlendOp,
+}
+impl BlendState {
+    /// Blending for colors with premultiplied alpha.
+    pub const PREMULTIPLIED_ALPHA: Self = Self {
+        src_color_blend_factor: vk::BlendFactor::ONE,
+        dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ONE,
+        dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        alpha_blend_op: vk::BlendOp::ADD,
+    };
+    /// Blending for colors with straight alpha.
+    pub const ALPHA: Self = Self {
+        src_color_blend_factor: vk::BlendFactor::SRC_ALPHA,
+        dst_color_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        color_blend_op: vk::BlendOp::ADD,
+        src_alpha_blend_factor: vk::BlendFactor::ONE,
+        dst_alpha_blend_factor: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+        alpha_blend_op: vk::BlendOp::ADD,
+    };
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct ColorBlendAttachment {
+    /// None disables blending for the attachment.
+    pub blend: Option<BlendState>,
+    pub color_write_mask: vk::ColorComponentFlags,
+}
+impl Default for ColorBlendAttachment {
+    fn default() -> Self {
+        Self {
+            blend: None,
+            color_write_mask: vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        }
+    }
+}
+impl ColorBlendAttachment {
+    fn raw(&self) -> vk::PipelineColorBlendAttachmentState {
+        let blend = self.blend.unwrap_or(BlendState::PREMULTIPLIED_ALPHA);
+        vk::PipelineColorBlendAttachmentState {
+            blend_enable: self.blend.is_some().into(),
+            src_color_blend_factor: blend.src_color_blend_factor,
+            dst_color_blend_factor: blend.dst_color_blend_factor,
+            color_blend_op: blend.color_blend_op,
+            src_alpha_blend_factor: blend.src_alpha_blend_factor,
+            dst_alpha_blend_factor: blend.dst_alpha_blend_factor,
+            alpha_blend_op: blend.alpha_blend_op,
+            color_write_mask: self.color_write_mask,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ColorBlendState {
+    pub logic_op: Option<vk::LogicOp>,
+    /// One for each of [`RenderingFormats::color_formats`].
+    pub attachments: Vec<ColorBlendAttachment>,
+    pub blend_constants: [f32; 4],
+}
+impl_eq_hash_by_key!(ColorBlendState, |this| (
+    this.logic_op,
+    &this.attachments,
+    this.blend_constants.map(f32::to_bits)
+));
+
+/// Attachment formats for dynamic rendering.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderingFormats {
+    pub view_mask: u32,
+    pub color_formats: Vec<vk::Format>,
+    pub depth_format: vk::Format,
+    pub stencil_format: vk::Format,
+}
+impl Default for RenderingFormats {
+    fn default() -> Self {
+        Self {
+            view_mask: 0,
+            color_formats: Vec::new(),
+            depth_format: vk::Format::UNDEFINED,
+            stencil_format: vk::Format::UNDEFINED,
+        }
+    }
+}
+
+/// Fixed-function state of a graphics pipeline using dynamic rendering.
+///
+/// [`vertex_input`](Self::vertex_input) and [`input_assembly`](Self::input_assembly) are
+/// ignored for pipelines with a mesh shader stage.
+///
+/// Viewports and scissors are always dynamic. Pipelines with identical states, shaders and
+/// layouts are only built once by the [`PipelineCache`](super::PipelineCache).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct GraphicsPipelineState {
+    pub flags: vk::PipelineCreateFlags,
+    pub vertex_input: VertexInputState,
+    pub input_assembly: InputAssemblyState,
+    pub rasterization: RasterizationState,
+    pub multisample: MultisampleState,
+    /// None if the pipeline has neither a depth nor a stencil attachment.
+    pub depth_stencil: Option<DepthStencilState>,
+    pub color_blend: ColorBlendState,
+    pub dynamic_states: Vec<vk::DynamicState>,
+    pub rendering: RenderingFormats,
+}
+
+impl GraphicsPipelineState {
+    /// The subset of the state used by one part of a graphics pipeline library.
+    pub(super) fn library_state(&self, part: vk::GraphicsPipelineLibraryFlagsEXT) -> Self {
+        let mut state = Self {
+            flags: self.flags,
+            dynamic_states: self.dynamic_states.clone(),
+            ..Default::default()
+        };
+        match part {
+            vk::GraphicsPipelineLibraryFlagsEXT::VERTEX_INPUT_INTERFACE => {
+                state.vertex_input = self.vertex_input.clone();
+                state.input_assembly = self.input_assembly;
+            }
+            vk::GraphicsPipelineLibraryFlagsEXT::PRE_RASTERIZATION_SHADERS => {
+                state.rasterization = self.rasterization;
+                state.rendering.view_mask = self.rendering.view_mask;
+            }
+            vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_SHADER => {
+                state.multisample = self.multisample;
+                state.depth_stencil = self.depth_stencil;
+                state.rendering.view_mask = self.rendering.view_mask;
+            }
+            vk::GraphicsPipelineLibraryFlagsEXT::FRAGMENT_OUTPUT_INTERFACE => {
+                state.multisample = self.multisample;
+                state.color_blend = self.color_blend.clone();
+                state.rendering = self.rendering.clone();
+            }
+            _ => unreachable!(),
+        }
+        state
+    }
+
+    /// Link graphics pipeline libraries into a complete pipeline. Without `optimize`, linking is
+    /// fast but the resulting pipeline may be slower than a monolithic pipeline.
+    pub(super) fn link_pipeline(
+        &self,
+        device: &Device,
+        cache: vk::PipelineCache,
+        libraries: &[vk::Pipeline],
+        layout: vk::PipelineLayout,
+        optimize: bool,
+    ) -> VkResult<vk::Pipeline> {
+        let mut library_info = vk::PipelineLibraryCreateInfoKHR::default().libraries(libraries);
+        let mut flags = self.flags;
+        if optimize {
+            flags |= vk::PipelineCreateFlags::LINK_TIME_OPTIMIZATION_EXT;
+        }
+        let info = vk::GraphicsPipelineCreateInfo::default()
+            .flags(flags)
+            .layout(layout)
+            .push_next(&mut library_info);
+        let mut pipeline = vk::Pipeline::null();
+        let result = unsafe {
+            (device.fp_v1_0().create_graphics_pipelines)(
+                device.handle(),
+                cache,
+                1,
+                &info,
+                std::ptr::null(),
+                &mut pipeline,
+            )
+        };
+        result.result_with_success(pipeline)
+    }
+
+    pub(super) fn create_pipeline(
+        &self,
+        device: &Device,
+        cache: vk::PipelineCache,
+        stages: &[vk::PipelineShaderStageCreateInfo],
+        layout: vk::PipelineLayout,
+        library: vk::GraphicsPipelineLibraryFlagsEXT,
+    ) -> VkResult<vk::Pipeline> {
+        let stage_flags = stages
+            .iter()
+            .fold(vk::ShaderStageFlags::empty(), |flags, stage| {
+                flags | stage.stage
+            });
+        if !super::mesh::stages_supported(device, stage_flags) {
+            tracing::error!(
+                "Graphics pipeline with stages {stage_flags:?} requires VK_EXT_mesh_shader. Add the MeshShaderPlugin"
+            );
+            return Err(vk::Result::ERROR_FEATURE_NOT_PRESENT);
+        }
+        let vertex_bindings: Vec<_> = self
+            .vertex_input
+            .bindings
+            .iter()
+            .map(|binding| vk::VertexInputBindingDescription {
+                binding: binding.binding,
+                stride: binding.stride,
+                input_rate: binding.input_rate,
+            })
+            .collect();
+        let vertex_attributes: Vec<_> = self
+            .vertex_input
+            .attributes
+            .iter()
+            .map(|attribute| vk::VertexInputAttributeDescription {
+                location: attribute.location,
+                binding: attribute.binding,
+                format: attribute.format,
+                offset: attribute.offset,
+            })
+            .collect();
+        let vertex_input = vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&vertex_bindings)
+            .vertex_attribute_descriptions(&vertex_attributes);
+        let input_assembly = vk::PipelineInputAssemblyStateCreateInfo {
+            topology: self.input_assembly.topology,
+            primitive_restart_enable: self.input_assembly.primitive_restart_enable.into(),
+            ..Default::default()
+        };
+
+        let mut dynamic_states = self.dynamic_states.clone();
+        let mut viewport = vk::PipelineViewportStateCreateInfo::default();
+        if !dynamic_states.contains(&vk::DynamicState::VIEWPORT_WITH_COUNT) {
+            viewport.viewport_count = 1;
+            if !dynamic_states.contains(&vk::DynamicState::VIEWPORT) {
+                dynamic_states.push(vk::DynamicState::VIEWPORT);
+            }
+        }
+        if !dynamic_states.contains(&vk::DynamicState::SCISSOR_WITH_COUNT) {
+            viewport.scissor_count = 1;
+            if !dynamic_states.contains(&vk::DynamicState::SCISSOR) {
+                dynamic_states.push(vk::DynamicState::SCISSOR);
+            }
+        }
+        let dynamic = vk::PipelineDynamicStateCreateInfo::default().dynamic_states(&dynamic_states);
+
+        let rasterization = &self.rasterization;
+        let depth_bias = rasterization.depth_bias.unwrap_or_default();
+        let rasterization = vk::PipelineRasterizationStateCreateInfo {
+            depth_clamp_enable: rasterization.depth_clamp_enable.into(),
+            rasterizer_discard_enable: rasterization.rasterizer_discard_enable.into(),
+            polygon_mode: rasterization.polygon_mode,
+            cull_mode: rasterization.cull_mode,
+            front_face: rasterization.front_face,
+            depth_bias_enable: rasterization.depth_bias.is_some().into(),
+            depth_bias_constant_factor: depth_bias.constant_factor,
+            depth_bias_clamp: depth_bias.clamp,
+            depth_bias_slope_factor: depth_bias.slope_factor,
+            line_width: rasterization.line_width,
+            ..Default::default()
+        };
+        let multisample = vk::PipelineMultisampleStateCreateInfo {
+            rasterization_samples: self.multisample.rasterization_samples,
+            sample_shading_enable: self.multisample.min_sample_shading.is_some().into(),
+            min_sample_shading: self.multisample.min_sample_shading.unwrap_or_default(),
+            alpha_to_coverage_enable: self.multisample.alpha_to_coverage_enable.into(),
+            alpha_to_one_enable: self.multisample.alpha_to_one_enable.into(),
+            ..Default::default()
+        };
+        let depth_stencil = self.depth_stencil.map(|state| {
+            let [min_depth_bounds, max_depth_bounds] = state.depth_bounds.unwrap_or([0.0, 1.0]);
+            let [front, back] = state
+                .stencil
+                .map(|stencil| stencil.map(|op| op.raw()))
+                .unwrap_or_default();
+            vk::PipelineDepthStencilStateCreateInfo {
+                depth_test_enable: state.depth_test_enable.into(),
+                depth_write_enable: state.depth_write_enable.into(),
+                depth_compare_op: state.depth_compare_op,
+                depth_bounds_test_enable: state.depth_bounds.is_some().into(),
+                stencil_test_enable: state.stencil.is_some().into(),
+                front,
+                back,
+                min_depth_bounds,
+                max_depth_bounds,
+                ..Default::default()
+            }
+        });
+        let color_blend_attachments: Vec<_> = self
+            .color_blend
+            .attachments
+            .iter()
+            .map(ColorBlendAttachment::raw)
+            .collect();
+        let color_blend = vk::PipelineColorBlendStateCreateInfo {
+            logic_op_enable: self.color_blend.logic_op.is_some().into(),
+            logic_op: self.color_blend.logic_op.unwrap_or(vk::LogicOp::COPY),
+            blend_constants: self.color_blend.blend_constants,
+            ..Default::default()
+        }
+        .attachments(&color_blend_attachments);
+        let mut rendering = vk::PipelineRenderingCreateInfo::default()
+            .view_mask(self.rendering.view_mask)
+            .color_attachment_formats(&self.rendering.color_formats)
+            .depth_attachment_format(self.rendering.depth_format)
+            .stencil_attachment_format(self.rendering.stencil_format);
+
+        let mut library_info = vk::GraphicsPipelineLibraryCreateInfoEXT::default().flags(library);
+
+        let mut info = vk::GraphicsPipelineCreateInfo::default()
+            .flags(self.flags)
+            .stages(stages)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .color_blend_state(&color_blend)
+            .dynamic_state(&dynamic)
+            .layout(layout)
+            .push_next(&mut rendering);
+        if !library.is_empty() {
+            info.flags |= vk::PipelineCreateFlags::LIBRARY_KHR
+                | vk::PipelineCreateFlags::RETAIN_LINK_TIME_OPTIMIZATION_INFO_EXT;
+            info = info.push_next(&mut library_info);
+        }
+        // Vertex input and input assembly states are ignored for mesh shading pipelines.
+        if !stage_flags.contains(vk::ShaderStageFlags::MESH_EXT) {
+            info = info
+                .vertex_input_state(&vertex_input)
+                .input_assembly_state(&input_assembly);
+        }
+        if let Some(depth_stencil) = depth_stencil.as_ref() {
+            info = info.depth_stencil_state(depth_stencil);
+        }
+
+        let mut pipeline = vk::Pipeline::null();
+        let result = unsafe {
+            (device.fp_v1_0().create_graphics_pipelines)(
+                device.handle(),
+                cache,
+                1,
+                &info,
+                std::ptr::null(),
+                &mut pipeline,
+            )
+        };
+        result.result_with_success(pipeline)
+    }
+}
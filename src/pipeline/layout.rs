@@ -1,12 +1,34 @@
-use std::{collections::BTreeMap, sync::Arc};
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex, Weak},
+};
 
-use crate::{Device, HasDevice};
+use crate::{
+    shader::{ShaderModule, ShaderReflection, SpecializedShader},
+    Device, HasDevice,
+};
 use ash::{prelude::VkResult, vk};
+use bevy::asset::{AssetId, Assets};
+use bevy::ecs::{system::Resource, world::FromWorld};
+use thiserror::Error;
+
+#[derive(Clone, Copy, Debug)]
+struct LayoutBinding {
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    descriptor_count: u32,
+    stage_flags: vk::ShaderStageFlags,
+    variable_count: bool,
+}
 
 pub struct DescriptorSetLayout {
     device: Device,
     pub(crate) raw: vk::DescriptorSetLayout,
     pub(crate) desc_types: Vec<(vk::DescriptorType, u32)>,
+    bindings: Vec<LayoutBinding>,
+    flags: vk::DescriptorSetLayoutCreateFlags,
+    /// Size of the layout and offsets of each binding, for layouts with the `DESCRIPTOR_BUFFER_EXT` flag.
+    descriptor_buffer_layout: Option<(vk::DeviceSize, Vec<(u32, vk::DeviceSize)>)>,
 }
 impl Drop for DescriptorSetLayout {
     fn drop(&mut self) {
@@ -16,24 +38,36 @@ impl Drop for DescriptorSetLayout {
     }
 }
 impl DescriptorSetLayout {
-    /// Users should obtain the layout from the cache.
-    /// TODO: Actually cache this, or not.
+    /// Users should obtain the layout from the [`LayoutCache`], which shares identical layouts.
     pub fn new(
         device: Device,
         binding_infos: &[vk::DescriptorSetLayoutBinding],
         flags: vk::DescriptorSetLayoutCreateFlags,
     ) -> VkResult<Self> {
-        let raw = unsafe {
-            device.create_descriptor_set_layout(
-                &vk::DescriptorSetLayoutCreateInfo {
-                    flags,
-                    binding_count: binding_infos.len() as u32,
-                    p_bindings: binding_infos.as_ptr(),
-                    ..Default::default()
-                },
-                None,
-            )
-        }?;
+        Self::new_with_binding_flags(device, binding_infos, &[], flags)
+    }
+
+    /// Like [`DescriptorSetLayout::new`], with one [`vk::DescriptorBindingFlags`] for each binding.
+    /// `binding_flags` may be empty if no binding has any flags.
+    pub fn new_with_binding_flags(
+        device: Device,
+        binding_infos: &[vk::DescriptorSetLayoutBinding],
+        binding_flags: &[vk::DescriptorBindingFlags],
+        flags: vk::DescriptorSetLayoutCreateFlags,
+    ) -> VkResult<Self> {
+        assert!(binding_flags.is_empty() || binding_flags.len() == binding_infos.len());
+        let mut binding_flags_info =
+            vk::DescriptorSetLayoutBindingFlagsCreateInfo::default().binding_flags(binding_flags);
+        let mut info = vk::DescriptorSetLayoutCreateInfo {
+            flags,
+            binding_count: binding_infos.len() as u32,
+            p_bindings: binding_infos.as_ptr(),
+            ..Default::default()
+        };
+        if !binding_flags.is_empty() {
+            info = info.push_next(&mut binding_flags_info);
+        }
+        let raw = unsafe { device.create_descriptor_set_layout(&info, None) }?;
 
         let mut desc_types = BTreeMap::new();
 
@@ -59,15 +93,68 @@ impl DescriptorSetLayout {
             }
         }
 
+        let descriptor_buffer_layout = flags
+            .contains(vk::DescriptorSetLayoutCreateFlags::DESCRIPTOR_BUFFER_EXT)
+            .then(|| unsafe {
+                let ext = device.extension::<ash::ext::descriptor_buffer::Meta>();
+                let size = ext.get_descriptor_set_layout_size(raw);
+                let offsets = binding_infos
+                    .iter()
+                    .map(|binding| {
+                        (
+                            binding.binding,
+                            ext.get_descriptor_set_layout_binding_offset(raw, binding.binding),
+                        )
+                    })
+                    .collect();
+                (size, offsets)
+            });
+
+        let bindings = binding_infos
+            .iter()
+            .enumerate()
+            .map(|(i, binding)| LayoutBinding {
+                binding: binding.binding,
+                descriptor_type: binding.descriptor_type,
+                descriptor_count: binding.descriptor_count,
+                stage_flags: binding.stage_flags,
+                variable_count: binding_flags.get(i).is_some_and(|flags| {
+                    flags.contains(vk::DescriptorBindingFlags::VARIABLE_DESCRIPTOR_COUNT)
+                }),
+            })
+            .collect();
+
         Ok(Self {
             device,
             raw,
             desc_types: desc_types.into_iter().collect(),
+            bindings,
+            flags,
+            descriptor_buffer_layout,
         })
     }
     pub unsafe fn raw(&self) -> vk::DescriptorSetLayout {
         self.raw
     }
+    pub fn flags(&self) -> vk::DescriptorSetLayoutCreateFlags {
+        self.flags
+    }
+    /// The number of bytes one set of this layout occupies in a descriptor buffer.
+    /// None unless the layout was created with the `DESCRIPTOR_BUFFER_EXT` flag.
+    pub fn descriptor_buffer_size(&self) -> Option<vk::DeviceSize> {
+        self.descriptor_buffer_layout
+            .as_ref()
+            .map(|(size, _)| *size)
+    }
+    /// The offset of the binding within one set in a descriptor buffer.
+    /// None unless the layout was created with the `DESCRIPTOR_BUFFER_EXT` flag.
+    pub fn descriptor_buffer_binding_offset(&self, binding: u32) -> Option<vk::DeviceSize> {
+        let (_, offsets) = self.descriptor_buffer_layout.as_ref()?;
+        offsets
+            .iter()
+            .find(|(b, _)| *b == binding)
+            .map(|(_, offset)| *offset)
+    }
 }
 
 pub struct PipelineLayout {
@@ -106,13 +193,315 @@ impl PipelineLayout {
             device,
             inner: layout,
             desc_sets: set_layouts,
-            push_constant_range: Vec::new(),
+            push_constant_range: push_constant_ranges.to_vec(),
         })
     }
     pub fn raw(&self) -> vk::PipelineLayout {
         self.inner
     }
 }
+
+/// Bindings of each set, keyed by set and binding number.
+type ReflectedSets = BTreeMap<u32, BTreeMap<u32, vk::DescriptorSetLayoutBinding>>;
+
+/// Merges the descriptor bindings and push constant ranges reflected from each shader stage.
+fn merge_reflections<'a>(
+    reflections: impl IntoIterator<Item = (vk::ShaderStageFlags, &'a ShaderReflection)>,
+) -> Result<(ReflectedSets, Vec<vk::PushConstantRange>), PipelineLayoutError> {
+    let mut sets = ReflectedSets::new();
+    let mut push_constant_ranges: Vec<vk::PushConstantRange> = Vec::new();
+    for (stage, reflection) in reflections {
+        for binding in reflection.bindings.iter() {
+            let existing = sets.entry(binding.set).or_default().entry(binding.binding);
+            let existing = existing.or_insert(vk::DescriptorSetLayoutBinding {
+                binding: binding.binding,
+                descriptor_type: binding.descriptor_type,
+                descriptor_count: binding.descriptor_count,
+                ..Default::default()
+            });
+            if existing.descriptor_type != binding.descriptor_type
+                || existing.descriptor_count != binding.descriptor_count
+            {
+                return Err(PipelineLayoutError::StageMismatch {
+                    set: binding.set,
+                    binding: binding.binding,
+                });
+            }
+            existing.stage_flags |= stage;
+        }
+        if let Some(push_constants) = reflection.push_constants {
+            // Stages with identical ranges share one range.
+            if let Some(range) = push_constant_ranges.iter_mut().find(|range| {
+                range.offset == push_constants.offset && range.size == push_constants.size
+            }) {
+                range.stage_flags |= stage;
+            } else {
+                push_constant_ranges.push(vk::PushConstantRange {
+                    stage_flags: stage,
+                    offset: push_constants.offset,
+                    size: push_constants.size,
+                });
+            }
+        }
+    }
+    Ok((sets, push_constant_ranges))
+}
+
+/// Returns an error if a shader binding reflected as `binding` can't be used with a set layout
+/// created with `bindings`.
+fn validate_binding(
+    bindings: &[LayoutBinding],
+    set: u32,
+    binding: &vk::DescriptorSetLayoutBinding,
+) -> Result<(), PipelineLayoutError> {
+    let Some(existing) = bindings.iter().find(|b| b.binding == binding.binding) else {
+        return Err(PipelineLayoutError::MissingBinding {
+            set,
+            binding: binding.binding,
+        });
+    };
+    // Runtime arrays are reflected with a count of 0 and may bind any number of descriptors.
+    let count_compatible = binding.descriptor_count == 0
+        || existing.variable_count
+        || existing.descriptor_count >= binding.descriptor_count;
+    if existing.descriptor_type != binding.descriptor_type
+        || !count_compatible
+        || !existing.stage_flags.contains(binding.stage_flags)
+    {
+        return Err(PipelineLayoutError::IncompatibleBinding {
+            set,
+            binding: binding.binding,
+        });
+    }
+    Ok(())
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct DescriptorSetLayoutBindingKey {
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    descriptor_count: u32,
+    stage_flags: vk::ShaderStageFlags,
+    binding_flags: vk::DescriptorBindingFlags,
+    immutable_samplers: Vec<vk::Sampler>,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct DescriptorSetLayoutKey {
+    /// Sorted by binding number.
+    bindings: Vec<DescriptorSetLayoutBindingKey>,
+    flags: vk::DescriptorSetLayoutCreateFlags,
+}
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct PipelineLayoutKey {
+    /// Raw handles of the set layouts. They stay valid as long as the pipeline layout is alive.
+    set_layouts: Vec<vk::DescriptorSetLayout>,
+    push_constant_ranges: Vec<(vk::ShaderStageFlags, u32, u32)>,
+    flags: vk::PipelineLayoutCreateFlags,
+}
+
+/// Shares [`DescriptorSetLayout`]s and [`PipelineLayout`]s with identical create infos.
+///
+/// Pipelines created with the same [`PipelineLayout`] may bind the same descriptor sets without
+/// rebinding them. Layouts are destroyed once all [`Arc`]s to them are dropped.
+///
+/// Inserted by [`RhyolitePlugin`](crate::RhyolitePlugin) along with the [`Device`], so it is
+/// available in [`Plugin::finish`](bevy::app::Plugin::finish) of all device plugins.
+#[derive(Resource)]
+pub struct LayoutCache {
+    device: Device,
+    set_layouts: Mutex<HashMap<DescriptorSetLayoutKey, Weak<DescriptorSetLayout>>>,
+    pipeline_layouts: Mutex<HashMap<PipelineLayoutKey, Weak<PipelineLayout>>>,
+}
+
+impl FromWorld for LayoutCache {
+    fn from_world(world: &mut bevy::ecs::world::World) -> Self {
+        Self::new(world.resource::<Device>().clone())
+    }
+}
+
+impl LayoutCache {
+    pub fn new(device: Device) -> Self {
+        Self {
+            device,
+            set_layouts: Default::default(),
+            pipeline_layouts: Default::default(),
+        }
+    }
+
+    /// Returns a descriptor set layout with `bindings`, creating it if it doesn't exist yet.
+    /// Immutable samplers must outlive the returned layout.
+    pub fn descriptor_set_layout(
+        &self,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        flags: vk::DescriptorSetLayoutCreateFlags,
+    ) -> VkResult<Arc<DescriptorSetLayout>> {
+        self.descriptor_set_layout_with_binding_flags(bindings, &[], flags)
+    }
+
+    /// Like [`LayoutCache::descriptor_set_layout`], with one [`vk::DescriptorBindingFlags`] for
+    /// each binding. `binding_flags` may be empty if no binding has any flags.
+    pub fn descriptor_set_layout_with_binding_flags(
+        &self,
+        bindings: &[vk::DescriptorSetLayoutBinding],
+        binding_flags: &[vk::DescriptorBindingFlags],
+        flags: vk::DescriptorSetLayoutCreateFlags,
+    ) -> VkResult<Arc<DescriptorSetLayout>> {
+        assert!(binding_flags.is_empty() || binding_flags.len() == bindings.len());
+        let mut binding_keys: Vec<_> = bindings
+            .iter()
+            .enumerate()
+            .map(|(i, binding)| DescriptorSetLayoutBindingKey {
+                binding: binding.binding,
+                descriptor_type: binding.descriptor_type,
+                descriptor_count: binding.descriptor_count,
+                stage_flags: binding.stage_flags,
+                binding_flags: binding_flags.get(i).copied().unwrap_or_default(),
+                immutable_samplers: if binding.p_immutable_samplers.is_null() {
+                    Vec::new()
+                } else {
+                    unsafe {
+                        std::slice::from_raw_parts(
+                            binding.p_immutable_samplers,
+                            binding.descriptor_count as usize,
+                        )
+                    }
+                    .to_vec()
+                },
+            })
+            .collect();
+        binding_keys.sort_by_key(|binding| binding.binding);
+        let key = DescriptorSetLayoutKey {
+            bindings: binding_keys,
+            flags,
+        };
+
+        let mut set_layouts = self.set_layouts.lock().unwrap();
+        if let Some(layout) = set_layouts.get(&key).and_then(Weak::upgrade) {
+            return Ok(layout);
+        }
+        let layout = Arc::new(DescriptorSetLayout::new_with_binding_flags(
+            self.device.clone(),
+            bindings,
+            binding_flags,
+            flags,
+        )?);
+        set_layouts.retain(|_, layout| layout.strong_count() > 0);
+        set_layouts.insert(key, Arc::downgrade(&layout));
+        Ok(layout)
+    }
+
+    /// Returns a pipeline layout with `set_layouts` and `push_constant_ranges`, creating it if it
+    /// doesn't exist yet.
+    pub fn pipeline_layout(
+        &self,
+        set_layouts: Vec<Arc<DescriptorSetLayout>>,
+        push_constant_ranges: &[vk::PushConstantRange],
+        flags: vk::PipelineLayoutCreateFlags,
+    ) -> VkResult<Arc<PipelineLayout>> {
+        let key = PipelineLayoutKey {
+            set_layouts: set_layouts.iter().map(|layout| layout.raw).collect(),
+            push_constant_ranges: push_constant_ranges
+                .iter()
+                .map(|range| (range.stage_flags, range.offset, range.size))
+                .collect(),
+            flags,
+        };
+
+        let mut pipeline_layouts = self.pipeline_layouts.lock().unwrap();
+        if let Some(layout) = pipeline_layouts.get(&key).and_then(Weak::upgrade) {
+            return Ok(layout);
+        }
+        let layout = Arc::new(PipelineLayout::new(
+            self.device.clone(),
+            set_layouts,
+            push_constant_ranges,
+            flags,
+        )?);
+        pipeline_layouts.retain(|_, layout| layout.strong_count() > 0);
+        pipeline_layouts.insert(key, Arc::downgrade(&layout));
+        Ok(layout)
+    }
+
+    /// Returns a pipeline layout for the reflection data of `shaders`, creating it and its set
+    /// layouts if they don't exist yet.
+    ///
+    /// Descriptor bindings and push constant ranges of all stages are merged. `set_layouts`
+    /// may provide explicit layouts for some of the sets, for example a
+    /// [`BindlessHeap`](crate::BindlessHeap) layout; the shaders are validated against them.
+    /// Other sets are created from the reflected bindings with `set_flags`.
+    pub fn from_shaders(
+        &self,
+        shaders: &[SpecializedShader],
+        assets: &Assets<ShaderModule>,
+        set_layouts: &[Option<Arc<DescriptorSetLayout>>],
+        set_flags: vk::DescriptorSetLayoutCreateFlags,
+    ) -> Result<Arc<PipelineLayout>, PipelineLayoutError> {
+        let reflections = shaders
+            .iter()
+            .map(|shader| {
+                let module = assets
+                    .get(&shader.shader)
+                    .ok_or(PipelineLayoutError::ShaderNotLoaded(shader.shader.id()))?;
+                let reflection = module
+                    .reflection()
+                    .ok_or(PipelineLayoutError::NoReflection(shader.shader.id()))?;
+                Ok((shader.stage, reflection))
+            })
+            .collect::<Result<Vec<_>, PipelineLayoutError>>()?;
+        let (mut sets, push_constant_ranges) = merge_reflections(reflections)?;
+
+        let num_sets = sets
+            .keys()
+            .next_back()
+            .map_or(0, |set| *set as usize + 1)
+            .max(set_layouts.len());
+        let mut desc_sets = Vec::with_capacity(num_sets);
+        for set in 0..num_sets as u32 {
+            let bindings = sets.remove(&set).unwrap_or_default();
+            if let Some(Some(layout)) = set_layouts.get(set as usize) {
+                for binding in bindings.values() {
+                    validate_binding(&layout.bindings, set, binding)?;
+                }
+                desc_sets.push(layout.clone());
+                continue;
+            }
+            let bindings: Vec<_> = bindings.into_values().collect();
+            if let Some(binding) = bindings.iter().find(|b| b.descriptor_count == 0) {
+                return Err(PipelineLayoutError::UnboundedArray {
+                    set,
+                    binding: binding.binding,
+                });
+            }
+            desc_sets.push(self.descriptor_set_layout(&bindings, set_flags)?);
+        }
+
+        Ok(self.pipeline_layout(
+            desc_sets,
+            &push_constant_ranges,
+            vk::PipelineLayoutCreateFlags::empty(),
+        )?)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum PipelineLayoutError {
+    #[error("shader {0:?} has not been loaded")]
+    ShaderNotLoaded(AssetId<ShaderModule>),
+    #[error("shader {0:?} has no reflection data")]
+    NoReflection(AssetId<ShaderModule>),
+    #[error("set {set} binding {binding} is declared differently across shader stages")]
+    StageMismatch { set: u32, binding: u32 },
+    #[error("set {set} binding {binding} is a runtime array and requires an explicit set layout")]
+    UnboundedArray { set: u32, binding: u32 },
+    #[error("set {set} binding {binding} is missing from the provided set layout")]
+    MissingBinding { set: u32, binding: u32 },
+    #[error("set {set} binding {binding} is incompatible with the provided set layout")]
+    IncompatibleBinding { set: u32, binding: u32 },
+    #[error("vulkan error: {0:?}")]
+    VkError(#[from] vk::Result),
+}
 impl HasDevice for PipelineLayout {
     fn device(&self) -> &Device {
         &self.device
@@ -125,3 +514,167 @@ impl Drop for PipelineLayout {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::shader::{DescriptorBindingReflection, PushConstantReflection};
+
+    fn reflection(
+        bindings: &[(u32, u32, vk::DescriptorType, u32)],
+        push_constants: Option<(u32, u32)>,
+    ) -> ShaderReflection {
+        ShaderReflection {
+            bindings: bindings
+                .iter()
+                .map(|&(set, binding, descriptor_type, descriptor_count)| {
+                    DescriptorBindingReflection {
+                        set,
+                        binding,
+                        descriptor_type,
+                        descriptor_count,
+                    }
+                })
+                .collect(),
+            push_constants: push_constants
+                .map(|(offset, size)| PushConstantReflection { offset, size }),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_merge_reflections() {
+        let vertex = reflection(
+            &[
+                (0, 0, vk::DescriptorType::UNIFORM_BUFFER, 1),
+                (1, 0, vk::DescriptorType::STORAGE_BUFFER, 1),
+            ],
+            Some((0, 64)),
+        );
+        let fragment = reflection(
+            &[
+                (0, 0, vk::DescriptorType::UNIFORM_BUFFER, 1),
+                (0, 1, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4),
+            ],
+            Some((0, 64)),
+        );
+        let compute = reflection(&[], Some((64, 16)));
+        let (sets, push_constant_ranges) = merge_reflections([
+            (vk::ShaderStageFlags::VERTEX, &vertex),
+            (vk::ShaderStageFlags::FRAGMENT, &fragment),
+            (vk::ShaderStageFlags::COMPUTE, &compute),
+        ])
+        .unwrap();
+
+        assert_eq!(sets.len(), 2);
+        let shared = &sets[&0][&0];
+        assert_eq!(shared.descriptor_type, vk::DescriptorType::UNIFORM_BUFFER);
+        assert_eq!(
+            shared.stage_flags,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        );
+        let combined = &sets[&0][&1];
+        assert_eq!(combined.descriptor_count, 4);
+        assert_eq!(combined.stage_flags, vk::ShaderStageFlags::FRAGMENT);
+        assert_eq!(sets[&1][&0].stage_flags, vk::ShaderStageFlags::VERTEX);
+
+        // Identical ranges are shared. Other ranges are kept separate.
+        assert_eq!(push_constant_ranges.len(), 2);
+        assert_eq!(
+            push_constant_ranges[0].stage_flags,
+            vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT
+        );
+        assert_eq!(
+            (push_constant_ranges[0].offset, push_constant_ranges[0].size),
+            (0, 64)
+        );
+        assert_eq!(
+            push_constant_ranges[1].stage_flags,
+            vk::ShaderStageFlags::COMPUTE
+        );
+        assert_eq!(
+            (push_constant_ranges[1].offset, push_constant_ranges[1].size),
+            (64, 16)
+        );
+    }
+
+    #[test]
+    fn test_merge_stage_mismatch() {
+        let vertex = reflection(&[(0, 2, vk::DescriptorType::UNIFORM_BUFFER, 1)], None);
+        let fragment = reflection(&[(0, 2, vk::DescriptorType::STORAGE_BUFFER, 1)], None);
+        assert!(matches!(
+            merge_reflections([
+                (vk::ShaderStageFlags::VERTEX, &vertex),
+                (vk::ShaderStageFlags::FRAGMENT, &fragment),
+            ]),
+            Err(PipelineLayoutError::StageMismatch { set: 0, binding: 2 })
+        ));
+
+        let fragment = reflection(&[(0, 2, vk::DescriptorType::UNIFORM_BUFFER, 2)], None);
+        assert!(matches!(
+            merge_reflections([
+                (vk::ShaderStageFlags::VERTEX, &vertex),
+                (vk::ShaderStageFlags::FRAGMENT, &fragment),
+            ]),
+            Err(PipelineLayoutError::StageMismatch { set: 0, binding: 2 })
+        ));
+    }
+
+    #[test]
+    fn test_validate_binding() {
+        let layout = [
+            LayoutBinding {
+                binding: 0,
+                descriptor_type: vk::DescriptorType::SAMPLED_IMAGE,
+                descriptor_count: 16,
+                stage_flags: vk::ShaderStageFlags::ALL,
+                variable_count: false,
+            },
+            LayoutBinding {
+                binding: 1,
+                descriptor_type: vk::DescriptorType::STORAGE_BUFFER,
+                descriptor_count: 8,
+                stage_flags: vk::ShaderStageFlags::COMPUTE,
+                variable_count: true,
+            },
+        ];
+        let binding = |binding, descriptor_type, descriptor_count, stage_flags| {
+            vk::DescriptorSetLayoutBinding {
+                binding,
+                descriptor_type,
+                descriptor_count,
+                stage_flags,
+                ..Default::default()
+            }
+        };
+        let validate = |b| validate_binding(&layout, 3, &b);
+
+        let sampled = vk::DescriptorType::SAMPLED_IMAGE;
+        let storage = vk::DescriptorType::STORAGE_BUFFER;
+        let fragment = vk::ShaderStageFlags::FRAGMENT;
+        let compute = vk::ShaderStageFlags::COMPUTE;
+        assert!(validate(binding(0, sampled, 4, fragment)).is_ok());
+        assert!(validate(binding(0, sampled, 16, fragment)).is_ok());
+        // Runtime arrays may bind any number of descriptors.
+        assert!(validate(binding(0, sampled, 0, fragment)).is_ok());
+        // Variable count bindings may be larger than their declared count.
+        assert!(validate(binding(1, storage, 32, compute)).is_ok());
+
+        assert!(matches!(
+            validate(binding(2, sampled, 1, fragment)),
+            Err(PipelineLayoutError::MissingBinding { set: 3, binding: 2 })
+        ));
+        assert!(matches!(
+            validate(binding(0, storage, 1, fragment)),
+            Err(PipelineLayoutError::IncompatibleBinding { set: 3, binding: 0 })
+        ));
+        assert!(matches!(
+            validate(binding(0, sampled, 17, fragment)),
+            Err(PipelineLayoutError::IncompatibleBinding { set: 3, binding: 0 })
+        ));
+        assert!(matches!(
+            validate(binding(1, storage, 1, fragment)),
+            Err(PipelineLayoutError::IncompatibleBinding { set: 3, binding: 1 })
+        ));
+    }
+}
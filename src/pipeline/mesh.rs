@@ -0,0 +1,72 @@
+use ash::{ext, vk};
+use bevy::app::{App, Plugin};
+
+use crate::{Device, RhyoliteApp};
+
+/// Enables `VK_EXT_mesh_shader`, for graphics pipelines with task, mesh and fragment stages.
+///
+/// Panics during [`Plugin::build`] if the device doesn't support mesh shaders.
+///
+/// `VK_KHR_draw_indirect_count` is enabled as well if available, for
+/// `draw_mesh_tasks_indirect_count`, which is unavailable otherwise; see
+/// [`MeshShaderPlugin::supports_indirect_count`]. The extension is enabled even on Vulkan 1.2
+/// devices, so that the `drawIndirectCount` feature doesn't have to be chained through
+/// [`vk::PhysicalDeviceVulkan12Features`], which conflicts with the other Vulkan 1.2 feature structs.
+pub struct MeshShaderPlugin {
+    /// Also enable task shaders.
+    pub task_shader: bool,
+}
+
+impl Default for MeshShaderPlugin {
+    fn default() -> Self {
+        Self { task_shader: true }
+    }
+}
+
+impl MeshShaderPlugin {
+    /// Returns true if mesh tasks can be drawn with an indirect draw count.
+    pub fn supports_indirect_count(device: &Device) -> bool {
+        device.has_extension_named(ash::khr::draw_indirect_count::NAME)
+    }
+}
+
+impl Plugin for MeshShaderPlugin {
+    fn build(&self, app: &mut App) {
+        if app
+            .add_device_extension::<ext::mesh_shader::Meta>()
+            .is_err()
+        {
+            panic!("MeshShaderPlugin requires VK_EXT_mesh_shader, which is not supported by the device");
+        }
+        if !app
+            .enable_feature::<vk::PhysicalDeviceMeshShaderFeaturesEXT>(|f| &mut f.mesh_shader)
+            .exists()
+        {
+            panic!("MeshShaderPlugin requires the meshShader feature, which is not supported by the device");
+        }
+        if self.task_shader
+            && !app
+                .enable_feature::<vk::PhysicalDeviceMeshShaderFeaturesEXT>(|f| &mut f.task_shader)
+                .exists()
+        {
+            panic!("MeshShaderPlugin requires the taskShader feature, which is not supported by the device. Set `task_shader` to false to use mesh shaders without task shaders");
+        }
+        if app
+            .add_device_extension_named(ash::khr::draw_indirect_count::NAME)
+            .is_err()
+        {
+            tracing::info!(
+                "VK_KHR_draw_indirect_count is not supported. Indirect count mesh draws are unavailable"
+            );
+        }
+    }
+}
+
+/// Returns true if the device supports the stages of a graphics pipeline.
+pub(super) fn stages_supported(device: &Device, stages: vk::ShaderStageFlags) -> bool {
+    let Some(features) = device.feature::<vk::PhysicalDeviceMeshShaderFeaturesEXT>() else {
+        return !stages.intersects(vk::ShaderStageFlags::MESH_EXT | vk::ShaderStageFlags::TASK_EXT);
+    };
+    (!stages.contains(vk::ShaderStageFlags::MESH_EXT) || features.mesh_shader == vk::TRUE)
+        && (!stages.contains(vk::ShaderStageFlags::TASK_EXT) || features.task_shader == vk::TRUE)
+}
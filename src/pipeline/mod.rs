@@ -3,20 +3,30 @@ use bevy::asset::{AssetId, Assets};
 
 use crate::{
     deferred::{DeferredOperationTaskPool, Task},
-    shader::ShaderModule,
+    shader::{ShaderModule, SpecializationInfo},
     Device, HasDevice,
 };
 
 mod cache;
 mod compute;
+mod descriptor;
+mod descriptor_buffer;
+mod executable;
 mod graphics;
+mod graphics_state;
 mod layout;
+mod mesh;
 
 use crate::future::RecordContext;
 pub use cache::*;
 pub use compute::*;
+pub use descriptor::*;
+pub use descriptor_buffer::*;
+pub use executable::*;
 pub use graphics::*;
+pub use graphics_state::*;
 pub use layout::*;
+pub use mesh::*;
 
 pub trait Pipeline: Sized + Send + Sync + 'static {
     type BuildInfo: PipelineBuildInfo;
@@ -49,8 +59,28 @@ pub trait PipelineBuildInfo {
     ) -> Option<Task<Self::Pipeline>>;
 
     /// List of all shaders used by this pipeline.
-    /// Only called when shader hot reloading is enabled.
+    /// Called for shader hot reloading and when reporting build failures.
     fn all_shaders(&self) -> impl Iterator<Item = AssetId<ShaderModule>>;
+
+    /// Called after `built` was returned from [`PipelineBuildInfo::build`]. The implementation may
+    /// schedule a better version of the pipeline, for example an optimized link of a pipeline
+    /// built from libraries, which replaces `built` once finished.
+    fn schedule_upgrade(
+        &mut self,
+        _built: &Self::Pipeline,
+        _pool: &DeferredOperationTaskPool,
+        _cache: vk::PipelineCache,
+    ) -> Option<Task<Self::Pipeline>> {
+        None
+    }
+}
+
+/// A [`PipelineBuildInfo`] that can be specialized into pipeline variants with
+/// [`PipelineCache::retrieve_variant`].
+pub trait SpecializablePipelineBuildInfo: PipelineBuildInfo {
+    /// Returns a copy of this build info with `constants` added to the specialization constants
+    /// of every shader stage. Constants not used by a stage are ignored by Vulkan.
+    fn specialize(&self, constants: &SpecializationInfo) -> Self;
 }
 
 pub struct PipelineInner {
@@ -88,4 +118,43 @@ impl<'a> RecordContext<'a> {
                 .cmd_bind_pipeline(self.command_buffer, T::TYPE, pipeline.as_raw());
         }
     }
+    pub fn bind_descriptor_sets(
+        &mut self,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        descriptor_sets: &[vk::DescriptorSet],
+        dynamic_offsets: &[u32],
+    ) {
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(
+                self.command_buffer,
+                bind_point,
+                layout,
+                first_set,
+                descriptor_sets,
+                dynamic_offsets,
+            );
+        }
+    }
+    /// Requires `VK_KHR_push_descriptor`.
+    pub fn push_descriptor_set(
+        &mut self,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        set: u32,
+        descriptor_writes: &[vk::WriteDescriptorSet<'_>],
+    ) {
+        unsafe {
+            self.device
+                .extension::<ash::khr::push_descriptor::Meta>()
+                .cmd_push_descriptor_set(
+                    self.command_buffer,
+                    bind_point,
+                    layout,
+                    set,
+                    descriptor_writes,
+                );
+        }
+    }
 }
@@ -33,6 +33,11 @@ pub struct RhyolitePlugin {
     pub api_version: Version,
 
     pub physical_device_index: usize,
+
+    /// Settings for the [`PipelineCache`](crate::pipeline::PipelineCache). Set
+    /// [`PipelineCachePlugin::pipeline_cache_path`](crate::pipeline::PipelineCachePlugin::pipeline_cache_path)
+    /// to persist compiled pipelines across runs.
+    pub pipeline_cache: crate::pipeline::PipelineCachePlugin,
 }
 unsafe impl Send for RhyolitePlugin {}
 unsafe impl Sync for RhyolitePlugin {}
@@ -45,6 +50,7 @@ impl Default for RhyolitePlugin {
             engine_version: Default::default(),
             api_version: Version::new(0, 1, 2, 0),
             physical_device_index: 0,
+            pipeline_cache: Default::default(),
         }
     }
 }
@@ -92,6 +98,14 @@ impl DeviceExtensions {
 unsafe impl Send for DeviceExtensions {}
 unsafe impl Sync for DeviceExtensions {}
 
+/// Undo [`RhyoliteApp::add_device_extension`], for plugins which find that a feature of the
+/// extension they rely on is unsupported.
+pub(crate) fn remove_device_extension<T: Extension>(app: &mut App) {
+    let mut extension_settings = app.world_mut().resource_mut::<DeviceExtensions>();
+    extension_settings.enabled_extensions.remove(T::NAME);
+    extension_settings.extension_builders.remove(T::NAME);
+}
+
 #[derive(Resource)]
 struct InstanceExtensions {
     available_extensions: BTreeMap<CString, Version>,
@@ -246,6 +260,13 @@ impl Plugin for RhyolitePlugin {
         app.add_device_extension::<khr::deferred_host_operations::Meta>()
             .ok();
 
+        // Optional features
+        app.enable_feature::<vk::PhysicalDeviceFeatures>(|f| &mut f.sampler_anisotropy);
+        // Without it, depth stencil images are transitioned with combined layouts.
+        app.enable_feature::<vk::PhysicalDeviceSeparateDepthStencilLayoutsFeatures>(|f| {
+            &mut f.separate_depth_stencil_layouts
+        });
+
         // IF supported, must be enabled.
         app.add_device_extension_named(vk::KHR_PORTABILITY_SUBSET_NAME)
             .ok();
@@ -256,7 +277,7 @@ impl Plugin for RhyolitePlugin {
         });
 
         app.add_plugins(crate::buffer::staging::StagingBeltPlugin);
-        app.add_plugins(crate::pipeline::PipelineCachePlugin::default());
+        app.add_plugins(self.pipeline_cache.clone());
 
         app.register_type::<bevy::image::Image>()
             .init_asset::<bevy::image::Image>()
@@ -288,6 +309,9 @@ impl Plugin for RhyolitePlugin {
         //    .init_resource::<crate::task::AsyncTaskPool>();
         app.world_mut()
             .init_resource::<crate::DeferredOperationTaskPool>();
+        app.world_mut().init_resource::<crate::SamplerCache>();
+        // Device plugins may use the layout cache in their own `finish`.
+        app.world_mut().init_resource::<crate::pipeline::LayoutCache>();
         app.init_asset_loader::<crate::shader::loader::SpirvLoader>();
     }
 }
@@ -1,6 +1,18 @@
 use crate::Device;
 use ash::{prelude::VkResult, vk};
-use std::fmt::Debug;
+use bevy::{
+    ecs::{system::Resource, world::FromWorld},
+    image::{
+        ImageAddressMode, ImageCompareFunction, ImageFilterMode, ImageSampler,
+        ImageSamplerBorderColor, ImageSamplerDescriptor,
+    },
+    utils::HashMap,
+};
+use std::{
+    fmt::Debug,
+    hash::{Hash, Hasher},
+    sync::{Arc, Mutex},
+};
 
 pub struct Sampler {
     device: Device,
@@ -27,3 +39,223 @@ impl Drop for Sampler {
         unsafe { self.device.destroy_sampler(self.inner, None) }
     }
 }
+
+/// A hashable description of a sampler, used as the key of the [`SamplerCache`].
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    pub mip_lod_bias: f32,
+    /// Anisotropic filtering is disabled when this is 1.
+    /// Clamped to `maxSamplerAnisotropy` when creating the sampler.
+    pub max_anisotropy: u16,
+    pub compare_op: Option<vk::CompareOp>,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    pub border_color: vk::BorderColor,
+    pub unnormalized_coordinates: bool,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            address_mode_u: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_v: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            address_mode_w: vk::SamplerAddressMode::CLAMP_TO_EDGE,
+            mip_lod_bias: 0.0,
+            max_anisotropy: 1,
+            compare_op: None,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+            unnormalized_coordinates: false,
+        }
+    }
+}
+
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.mag_filter == other.mag_filter
+            && self.min_filter == other.min_filter
+            && self.mipmap_mode == other.mipmap_mode
+            && self.address_mode_u == other.address_mode_u
+            && self.address_mode_v == other.address_mode_v
+            && self.address_mode_w == other.address_mode_w
+            && self.mip_lod_bias.to_bits() == other.mip_lod_bias.to_bits()
+            && self.max_anisotropy == other.max_anisotropy
+            && self.compare_op == other.compare_op
+            && self.min_lod.to_bits() == other.min_lod.to_bits()
+            && self.max_lod.to_bits() == other.max_lod.to_bits()
+            && self.border_color == other.border_color
+            && self.unnormalized_coordinates == other.unnormalized_coordinates
+    }
+}
+impl Eq for SamplerDesc {}
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mag_filter.hash(state);
+        self.min_filter.hash(state);
+        self.mipmap_mode.hash(state);
+        self.address_mode_u.hash(state);
+        self.address_mode_v.hash(state);
+        self.address_mode_w.hash(state);
+        self.mip_lod_bias.to_bits().hash(state);
+        self.max_anisotropy.hash(state);
+        self.compare_op.hash(state);
+        self.min_lod.to_bits().hash(state);
+        self.max_lod.to_bits().hash(state);
+        self.border_color.hash(state);
+        self.unnormalized_coordinates.hash(state);
+    }
+}
+
+impl From<&ImageSamplerDescriptor> for SamplerDesc {
+    fn from(desc: &ImageSamplerDescriptor) -> Self {
+        fn filter(filter: ImageFilterMode) -> vk::Filter {
+            match filter {
+                ImageFilterMode::Nearest => vk::Filter::NEAREST,
+                ImageFilterMode::Linear => vk::Filter::LINEAR,
+            }
+        }
+        fn address_mode(mode: ImageAddressMode) -> vk::SamplerAddressMode {
+            match mode {
+                ImageAddressMode::ClampToEdge => vk::SamplerAddressMode::CLAMP_TO_EDGE,
+                ImageAddressMode::Repeat => vk::SamplerAddressMode::REPEAT,
+                ImageAddressMode::MirrorRepeat => vk::SamplerAddressMode::MIRRORED_REPEAT,
+                ImageAddressMode::ClampToBorder => vk::SamplerAddressMode::CLAMP_TO_BORDER,
+            }
+        }
+        Self {
+            mag_filter: filter(desc.mag_filter),
+            min_filter: filter(desc.min_filter),
+            mipmap_mode: match desc.mipmap_filter {
+                ImageFilterMode::Nearest => vk::SamplerMipmapMode::NEAREST,
+                ImageFilterMode::Linear => vk::SamplerMipmapMode::LINEAR,
+            },
+            address_mode_u: address_mode(desc.address_mode_u),
+            address_mode_v: address_mode(desc.address_mode_v),
+            address_mode_w: address_mode(desc.address_mode_w),
+            mip_lod_bias: 0.0,
+            max_anisotropy: desc.anisotropy_clamp.max(1),
+            compare_op: desc.compare.map(|compare| match compare {
+                ImageCompareFunction::Never => vk::CompareOp::NEVER,
+                ImageCompareFunction::Less => vk::CompareOp::LESS,
+                ImageCompareFunction::Equal => vk::CompareOp::EQUAL,
+                ImageCompareFunction::LessEqual => vk::CompareOp::LESS_OR_EQUAL,
+                ImageCompareFunction::Greater => vk::CompareOp::GREATER,
+                ImageCompareFunction::NotEqual => vk::CompareOp::NOT_EQUAL,
+                ImageCompareFunction::GreaterEqual => vk::CompareOp::GREATER_OR_EQUAL,
+                ImageCompareFunction::Always => vk::CompareOp::ALWAYS,
+            }),
+            min_lod: desc.lod_min_clamp,
+            max_lod: desc.lod_max_clamp,
+            border_color: match desc.border_color {
+                None
+                | Some(ImageSamplerBorderColor::TransparentBlack)
+                | Some(ImageSamplerBorderColor::Zero) => vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+                Some(ImageSamplerBorderColor::OpaqueBlack) => vk::BorderColor::FLOAT_OPAQUE_BLACK,
+                Some(ImageSamplerBorderColor::OpaqueWhite) => vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            },
+            unnormalized_coordinates: false,
+        }
+    }
+}
+
+impl From<&ImageSampler> for SamplerDesc {
+    fn from(sampler: &ImageSampler) -> Self {
+        match sampler {
+            ImageSampler::Default => Self::default(),
+            ImageSampler::Descriptor(desc) => desc.into(),
+        }
+    }
+}
+
+/// Deduplicates samplers by their [`SamplerDesc`].
+///
+/// Devices limit the number of samplers that may exist at the same time (`maxSamplerAllocationCount`),
+/// so samplers should be obtained from this cache instead of created with [`Sampler::new`].
+#[derive(Resource)]
+pub struct SamplerCache {
+    device: Device,
+    max_anisotropy: Option<f32>,
+    samplers: Mutex<HashMap<SamplerDesc, Arc<Sampler>>>,
+}
+
+impl FromWorld for SamplerCache {
+    fn from_world(world: &mut bevy::ecs::world::World) -> Self {
+        let device = world.resource::<Device>().clone();
+        Self::new(device)
+    }
+}
+
+impl SamplerCache {
+    pub fn new(device: Device) -> Self {
+        let anisotropy_enabled = device
+            .feature::<vk::PhysicalDeviceFeatures>()
+            .is_some_and(|f| f.sampler_anisotropy == vk::TRUE);
+        let max_anisotropy = anisotropy_enabled.then(|| {
+            device
+                .physical_device()
+                .properties()
+                .limits
+                .max_sampler_anisotropy
+        });
+        Self {
+            device,
+            max_anisotropy,
+            samplers: Mutex::new(HashMap::default()),
+        }
+    }
+
+    /// Returns the sampler for the descriptor, creating it if it doesn't exist yet.
+    pub fn get(&self, desc: &SamplerDesc) -> VkResult<Arc<Sampler>> {
+        let mut desc = *desc;
+        // Descriptors which only differ in unsupported anisotropy levels map to the same sampler.
+        desc.max_anisotropy = match self.max_anisotropy {
+            Some(max_anisotropy) => desc.max_anisotropy.clamp(1, max_anisotropy as u16),
+            None => 1,
+        };
+        let mut samplers = self.samplers.lock().unwrap();
+        if let Some(sampler) = samplers.get(&desc) {
+            return Ok(sampler.clone());
+        }
+        let sampler = Arc::new(Sampler::new(
+            self.device.clone(),
+            &vk::SamplerCreateInfo {
+                mag_filter: desc.mag_filter,
+                min_filter: desc.min_filter,
+                mipmap_mode: desc.mipmap_mode,
+                address_mode_u: desc.address_mode_u,
+                address_mode_v: desc.address_mode_v,
+                address_mode_w: desc.address_mode_w,
+                mip_lod_bias: desc.mip_lod_bias,
+                anisotropy_enable: (desc.max_anisotropy > 1).into(),
+                max_anisotropy: desc.max_anisotropy as f32,
+                compare_enable: desc.compare_op.is_some().into(),
+                compare_op: desc.compare_op.unwrap_or(vk::CompareOp::NEVER),
+                min_lod: desc.min_lod,
+                max_lod: desc.max_lod,
+                border_color: desc.border_color,
+                unnormalized_coordinates: desc.unnormalized_coordinates.into(),
+                ..Default::default()
+            },
+        )?);
+        samplers.insert(desc, sampler.clone());
+        Ok(sampler)
+    }
+
+    /// Destroy the samplers which are no longer referenced outside of the cache.
+    pub fn clear_unused(&mut self) {
+        self.samplers
+            .get_mut()
+            .unwrap()
+            .retain(|_, sampler| Arc::strong_count(sampler) > 1);
+    }
+}
@@ -64,7 +64,7 @@ pub struct GlslShadercCompiler {
     pub target_vk_version: Version,
 }
 const GLSL_EXTENSIONS: &[&str] = &[
-    "rgen", "rmiss", "rchit", "rahit", "rint", "frag", "vert", "comp",
+    "rgen", "rmiss", "rchit", "rahit", "rint", "frag", "vert", "comp", "task", "mesh",
 ];
 impl AssetLoader for GlslShadercCompiler {
     type Asset = SpirvShaderSource;
@@ -93,6 +93,8 @@ impl AssetLoader for GlslShadercCompiler {
                 "comp" => ShaderKind::Compute,
                 "vert" => ShaderKind::Vertex,
                 "frag" => ShaderKind::Fragment,
+                "task" => ShaderKind::Task,
+                "mesh" => ShaderKind::Mesh,
                 _ => ShaderKind::InferFromSource,
             }
         } else {
@@ -116,19 +118,25 @@ impl AssetLoader for GlslShadercCompiler {
                         continue;
                     }
                     let path = match ty {
-                        shaderc::IncludeType::Relative => ctx
-                            .path()
-                            .parent()
-                            .unwrap()
-                            .join(&filename)
-                            .join(included_filename),
-                        shaderc::IncludeType::Standard => included_filename.into(),
+                        shaderc::IncludeType::Relative => {
+                            let path = ctx
+                                .path()
+                                .parent()
+                                .unwrap()
+                                .join(&filename)
+                                .join(included_filename);
+                            AssetPath::from_path(&normalize_path(&path)).into_owned()
+                        }
+                        // Standard includes may name an asset source,
+                        // e.g. `#include <embedded://rhyolite/bindless.glsl>`
+                        shaderc::IncludeType::Standard => {
+                            AssetPath::parse(included_filename).into_owned()
+                        }
                     };
-                    let normalized_path = normalize_path(&path);
                     let inc = ctx
                         .loader()
                         .immediate()
-                        .load::<GlslShaderSource>(AssetPath::from_path(&normalized_path))
+                        .load::<GlslShaderSource>(path)
                         .await?;
                     let source: &GlslShaderSource = inc.get();
                     pending_sources.push((included_filename.to_string(), source.source.clone()));
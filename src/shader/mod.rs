@@ -1,4 +1,5 @@
 use std::ffi::CStr;
+use std::sync::atomic::{AtomicU64, Ordering};
 
 use crate::Device;
 use ash::prelude::VkResult;
@@ -9,7 +10,9 @@ use cstr::cstr;
 
 #[cfg(feature = "glsl")]
 mod glsl;
+mod reflect;
 mod spirv;
+pub use reflect::*;
 pub mod loader {
     #[cfg(feature = "glsl")]
     pub use super::glsl::*;
@@ -20,8 +23,11 @@ pub mod loader {
 pub struct ShaderModule {
     device: Device,
     module: vk::ShaderModule,
+    reflection: Option<ShaderReflection>,
+    generation: u64,
 }
 impl ShaderModule {
+    /// Create the shader module. The reflection data of the module is retained as well.
     pub fn new(device: Device, code: &[u32]) -> VkResult<Self> {
         let module = unsafe {
             device.create_shader_module(
@@ -33,11 +39,34 @@ impl ShaderModule {
                 None,
             )?
         };
-        Ok(Self { device, module })
+        let reflection = match reflect(code) {
+            Ok(reflection) => Some(reflection),
+            Err(err) => {
+                tracing::warn!("Failed to reflect shader module: {err}");
+                None
+            }
+        };
+        static NEXT_GENERATION: AtomicU64 = AtomicU64::new(0);
+        Ok(Self {
+            device,
+            module,
+            reflection,
+            generation: NEXT_GENERATION.fetch_add(1, Ordering::Relaxed),
+        })
     }
     pub fn raw(&self) -> vk::ShaderModule {
         self.module
     }
+    /// Descriptor bindings, push constants, specialization constants and workgroup sizes
+    /// declared by the shader. None if the SPIR-V binary could not be parsed.
+    pub fn reflection(&self) -> Option<&ShaderReflection> {
+        self.reflection.as_ref()
+    }
+    /// A number unique to this shader module. Unlike the raw handle, which the driver may reuse
+    /// once the module is destroyed, it identifies the module even after a hot reload replaced it.
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
 }
 impl Drop for ShaderModule {
     fn drop(&mut self) {
@@ -120,6 +149,19 @@ impl SpecializationInfo {
             self.data.set_len(self.data.len() + size);
         }
     }
+    /// Add all constants in `other`, replacing constants with the same id.
+    pub fn extend(&mut self, other: &SpecializationInfo) {
+        let offset = self.data.len() as u32;
+        self.data.extend_from_slice(&other.data);
+        for entry in other.entries.iter() {
+            self.entries
+                .retain(|existing| existing.constant_id != entry.constant_id);
+            self.entries.push(vk::SpecializationMapEntry {
+                offset: offset + entry.offset,
+                ..*entry
+            });
+        }
+    }
     fn push_bool(&mut self, constant_id: u32, item: bool) {
         let size = std::mem::size_of::<vk::Bool32>();
         self.entries.push(vk::SpecializationMapEntry {
@@ -0,0 +1,784 @@
+//! Minimal SPIR-V reflection: descriptor bindings, push constants, specialization constants
+//! and compute workgroup sizes.
+
+use std::collections::{BTreeMap, HashMap};
+
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ReflectError {
+    #[error("invalid SPIR-V magic number")]
+    InvalidMagic,
+    #[error("SPIR-V module is truncated")]
+    Truncated,
+    #[error("unsupported descriptor type for variable %{0}")]
+    UnsupportedDescriptor(u32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DescriptorBindingReflection {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// Number of descriptors in the binding. 0 for runtime-sized arrays.
+    pub descriptor_count: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PushConstantReflection {
+    /// Offset of the first member of the push constant block.
+    pub offset: u32,
+    pub size: u32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpecializationConstantReflection {
+    pub constant_id: u32,
+    /// Size of the constant in bytes. Booleans are 4 bytes, matching [`vk::Bool32`].
+    pub size: u32,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntryPointReflection {
+    pub name: String,
+    pub stage: vk::ShaderStageFlags,
+    /// The local workgroup size of compute, task and mesh shaders. Workgroup sizes given by
+    /// specialization constants are reported with their default values.
+    pub workgroup_size: Option<[u32; 3]>,
+}
+
+/// Resources declared by a SPIR-V module.
+///
+/// Resources are reported for the module as a whole, so a module with multiple entry points
+/// reports the union of the resources used by each of them.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShaderReflection {
+    pub entry_points: Vec<EntryPointReflection>,
+    /// Sorted by set and binding.
+    pub bindings: Vec<DescriptorBindingReflection>,
+    pub push_constants: Option<PushConstantReflection>,
+    /// Sorted by constant ID.
+    pub specialization_constants: Vec<SpecializationConstantReflection>,
+}
+
+impl ShaderReflection {
+    pub fn entry_point(&self, name: &str) -> Option<&EntryPointReflection> {
+        self.entry_points.iter().find(|entry| entry.name == name)
+    }
+}
+
+const MAGIC: u32 = 0x0723_0203;
+
+mod op {
+    pub const ENTRY_POINT: u32 = 15;
+    pub const EXECUTION_MODE: u32 = 16;
+    pub const TYPE_BOOL: u32 = 20;
+    pub const TYPE_INT: u32 = 21;
+    pub const TYPE_FLOAT: u32 = 22;
+    pub const TYPE_VECTOR: u32 = 23;
+    pub const TYPE_MATRIX: u32 = 24;
+    pub const TYPE_IMAGE: u32 = 25;
+    pub const TYPE_SAMPLER: u32 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u32 = 27;
+    pub const TYPE_ARRAY: u32 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u32 = 29;
+    pub const TYPE_STRUCT: u32 = 30;
+    pub const TYPE_POINTER: u32 = 32;
+    pub const CONSTANT_TRUE: u32 = 41;
+    pub const CONSTANT_FALSE: u32 = 42;
+    pub const CONSTANT: u32 = 43;
+    pub const CONSTANT_COMPOSITE: u32 = 44;
+    pub const SPEC_CONSTANT_TRUE: u32 = 48;
+    pub const SPEC_CONSTANT_FALSE: u32 = 49;
+    pub const SPEC_CONSTANT: u32 = 50;
+    pub const SPEC_CONSTANT_COMPOSITE: u32 = 51;
+    pub const VARIABLE: u32 = 59;
+    pub const DECORATE: u32 = 71;
+    pub const MEMBER_DECORATE: u32 = 72;
+    pub const EXECUTION_MODE_ID: u32 = 331;
+    pub const TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+}
+
+mod decoration {
+    pub const SPEC_ID: u32 = 1;
+    pub const BLOCK: u32 = 2;
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const BUILT_IN: u32 = 11;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+mod storage_class {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const UNIFORM: u32 = 2;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+const BUILT_IN_WORKGROUP_SIZE: u32 = 25;
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const EXECUTION_MODE_LOCAL_SIZE_ID: u32 = 38;
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+#[derive(Clone)]
+enum Type {
+    Scalar { size: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage { image: u32 },
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { storage_class: u32, pointee: u32 },
+    AccelerationStructure,
+}
+
+#[derive(Default)]
+struct Decorations {
+    spec_id: Option<u32>,
+    set: Option<u32>,
+    binding: Option<u32>,
+    block: bool,
+    buffer_block: bool,
+    array_stride: Option<u32>,
+    built_in: Option<u32>,
+    /// Offset and matrix stride of each struct member, by member index.
+    member_offsets: BTreeMap<u32, u32>,
+    member_matrix_strides: BTreeMap<u32, u32>,
+}
+
+/// Extracts reflection data from a SPIR-V binary.
+pub fn reflect(code: &[u32]) -> Result<ShaderReflection, ReflectError> {
+    if code.len() < 5 {
+        return Err(ReflectError::Truncated);
+    }
+    if code[0] != MAGIC {
+        return Err(ReflectError::InvalidMagic);
+    }
+
+    let mut entry_points: Vec<(u32, EntryPointReflection)> = Vec::new();
+    let mut local_sizes: HashMap<u32, [u32; 3]> = HashMap::new();
+    let mut local_size_ids: HashMap<u32, [u32; 3]> = HashMap::new();
+    let mut types: HashMap<u32, Type> = HashMap::new();
+    let mut decorations: HashMap<u32, Decorations> = HashMap::new();
+    // Scalar constants and spec constants, by result ID.
+    let mut constants: HashMap<u32, u32> = HashMap::new();
+    let mut composites: HashMap<u32, Vec<u32>> = HashMap::new();
+    let mut spec_constants: Vec<(u32, u32)> = Vec::new();
+    let mut variables: Vec<(u32, u32, u32)> = Vec::new();
+
+    let mut words = &code[5..];
+    while !words.is_empty() {
+        let word_count = (words[0] >> 16) as usize;
+        let opcode = words[0] & 0xffff;
+        if word_count == 0 || word_count > words.len() {
+            return Err(ReflectError::Truncated);
+        }
+        let operands = &words[1..word_count];
+        words = &words[word_count..];
+        let operand = |i: usize| operands.get(i).copied().ok_or(ReflectError::Truncated);
+
+        match opcode {
+            op::ENTRY_POINT => {
+                let stage = execution_model_stage(operand(0)?);
+                let id = operand(1)?;
+                let name = parse_string(&operands[2..]);
+                entry_points.push((
+                    id,
+                    EntryPointReflection {
+                        name,
+                        stage,
+                        workgroup_size: None,
+                    },
+                ));
+            }
+            op::EXECUTION_MODE | op::EXECUTION_MODE_ID => match operand(1)? {
+                EXECUTION_MODE_LOCAL_SIZE => {
+                    local_sizes.insert(operand(0)?, [operand(2)?, operand(3)?, operand(4)?]);
+                }
+                EXECUTION_MODE_LOCAL_SIZE_ID => {
+                    local_size_ids.insert(operand(0)?, [operand(2)?, operand(3)?, operand(4)?]);
+                }
+                _ => (),
+            },
+            op::TYPE_BOOL => {
+                types.insert(operand(0)?, Type::Scalar { size: 4 });
+            }
+            op::TYPE_INT | op::TYPE_FLOAT => {
+                types.insert(
+                    operand(0)?,
+                    Type::Scalar {
+                        size: operand(1)? / 8,
+                    },
+                );
+            }
+            op::TYPE_VECTOR => {
+                types.insert(
+                    operand(0)?,
+                    Type::Vector {
+                        component: operand(1)?,
+                        count: operand(2)?,
+                    },
+                );
+            }
+            op::TYPE_MATRIX => {
+                types.insert(
+                    operand(0)?,
+                    Type::Matrix {
+                        column: operand(1)?,
+                        count: operand(2)?,
+                    },
+                );
+            }
+            op::TYPE_IMAGE => {
+                types.insert(
+                    operand(0)?,
+                    Type::Image {
+                        dim: operand(2)?,
+                        sampled: operand(6)?,
+                    },
+                );
+            }
+            op::TYPE_SAMPLER => {
+                types.insert(operand(0)?, Type::Sampler);
+            }
+            op::TYPE_SAMPLED_IMAGE => {
+                types.insert(operand(0)?, Type::SampledImage { image: operand(1)? });
+            }
+            op::TYPE_ARRAY => {
+                // The length is resolved once all constants are known.
+                types.insert(
+                    operand(0)?,
+                    Type::Array {
+                        element: operand(1)?,
+                        length: operand(2)?,
+                    },
+                );
+            }
+            op::TYPE_RUNTIME_ARRAY => {
+                types.insert(
+                    operand(0)?,
+                    Type::RuntimeArray {
+                        element: operand(1)?,
+                    },
+                );
+            }
+            op::TYPE_STRUCT => {
+                types.insert(
+                    operand(0)?,
+                    Type::Struct {
+                        members: operands[1..].to_vec(),
+                    },
+                );
+            }
+            op::TYPE_POINTER => {
+                types.insert(
+                    operand(0)?,
+                    Type::Pointer {
+                        storage_class: operand(1)?,
+                        pointee: operand(2)?,
+                    },
+                );
+            }
+            op::TYPE_ACCELERATION_STRUCTURE => {
+                types.insert(operand(0)?, Type::AccelerationStructure);
+            }
+            op::CONSTANT | op::SPEC_CONSTANT => {
+                constants.insert(operand(1)?, operand(2)?);
+                if opcode == op::SPEC_CONSTANT {
+                    spec_constants.push((operand(0)?, operand(1)?));
+                }
+            }
+            op::CONSTANT_TRUE | op::SPEC_CONSTANT_TRUE => {
+                constants.insert(operand(1)?, 1);
+                if opcode == op::SPEC_CONSTANT_TRUE {
+                    spec_constants.push((operand(0)?, operand(1)?));
+                }
+            }
+            op::CONSTANT_FALSE | op::SPEC_CONSTANT_FALSE => {
+                constants.insert(operand(1)?, 0);
+                if opcode == op::SPEC_CONSTANT_FALSE {
+                    spec_constants.push((operand(0)?, operand(1)?));
+                }
+            }
+            op::CONSTANT_COMPOSITE | op::SPEC_CONSTANT_COMPOSITE => {
+                composites.insert(operand(1)?, operands[2..].to_vec());
+            }
+            op::VARIABLE => {
+                variables.push((operand(0)?, operand(1)?, operand(2)?));
+            }
+            op::DECORATE => {
+                let entry = decorations.entry(operand(0)?).or_default();
+                match operand(1)? {
+                    decoration::SPEC_ID => entry.spec_id = Some(operand(2)?),
+                    decoration::BLOCK => entry.block = true,
+                    decoration::BUFFER_BLOCK => entry.buffer_block = true,
+                    decoration::ARRAY_STRIDE => entry.array_stride = Some(operand(2)?),
+                    decoration::BUILT_IN => entry.built_in = Some(operand(2)?),
+                    decoration::BINDING => entry.binding = Some(operand(2)?),
+                    decoration::DESCRIPTOR_SET => entry.set = Some(operand(2)?),
+                    _ => (),
+                }
+            }
+            op::MEMBER_DECORATE => {
+                let entry = decorations.entry(operand(0)?).or_default();
+                match operand(2)? {
+                    decoration::OFFSET => {
+                        entry.member_offsets.insert(operand(1)?, operand(3)?);
+                    }
+                    decoration::MATRIX_STRIDE => {
+                        entry.member_matrix_strides.insert(operand(1)?, operand(3)?);
+                    }
+                    _ => (),
+                }
+            }
+            _ => (),
+        }
+    }
+
+    let module = Module {
+        types,
+        decorations,
+        constants,
+    };
+
+    // A constant decorated with the WorkgroupSize built-in overrides the LocalSize of all entry points.
+    let workgroup_size_built_in = module
+        .decorations
+        .iter()
+        .find(|(_, d)| d.built_in == Some(BUILT_IN_WORKGROUP_SIZE))
+        .and_then(|(id, _)| composites.get(id))
+        .and_then(|components| module.resolve_ids(components));
+    let entry_points = entry_points
+        .into_iter()
+        .map(|(id, mut entry_point)| {
+            let local_size = local_sizes.get(&id).copied().or_else(|| {
+                local_size_ids
+                    .get(&id)
+                    .and_then(|ids| module.resolve_ids(ids))
+            });
+            if entry_point.stage.intersects(
+                vk::ShaderStageFlags::COMPUTE
+                    | vk::ShaderStageFlags::TASK_EXT
+                    | vk::ShaderStageFlags::MESH_EXT,
+            ) {
+                entry_point.workgroup_size = workgroup_size_built_in.or(local_size);
+            }
+            entry_point
+        })
+        .collect();
+
+    let mut bindings = Vec::new();
+    let mut push_constants = None;
+    for (result_type, id, storage_class) in variables {
+        let Some(Type::Pointer { pointee, .. }) = module.types.get(&result_type) else {
+            continue;
+        };
+        match storage_class {
+            storage_class::PUSH_CONSTANT => {
+                push_constants = module.push_constant_range(*pointee);
+            }
+            storage_class::UNIFORM_CONSTANT
+            | storage_class::UNIFORM
+            | storage_class::STORAGE_BUFFER => {
+                let Some(decorations) = module.decorations.get(&id) else {
+                    continue;
+                };
+                let (Some(set), Some(binding)) = (decorations.set, decorations.binding) else {
+                    continue;
+                };
+                let (element, descriptor_count) = match module.types.get(pointee) {
+                    Some(Type::Array { element, length }) => {
+                        (*element, module.constants.get(length).copied().unwrap_or(1))
+                    }
+                    Some(Type::RuntimeArray { element }) => (*element, 0),
+                    _ => (*pointee, 1),
+                };
+                let descriptor_type = module
+                    .descriptor_type(element, storage_class)
+                    .ok_or(ReflectError::UnsupportedDescriptor(id))?;
+                bindings.push(DescriptorBindingReflection {
+                    set,
+                    binding,
+                    descriptor_type,
+                    descriptor_count,
+                });
+            }
+            _ => (),
+        }
+    }
+    bindings.sort_by_key(|binding| (binding.set, binding.binding));
+    // Aliased declarations of the same binding, for example textures of different dimensionality.
+    bindings.dedup_by_key(|binding| (binding.set, binding.binding));
+
+    let mut specialization_constants: Vec<_> = spec_constants
+        .into_iter()
+        .filter_map(|(result_type, id)| {
+            let constant_id = module.decorations.get(&id)?.spec_id?;
+            Some(SpecializationConstantReflection {
+                constant_id,
+                size: module.size_of(result_type).unwrap_or(4),
+            })
+        })
+        .collect();
+    specialization_constants.sort_by_key(|constant| constant.constant_id);
+
+    Ok(ShaderReflection {
+        entry_points,
+        bindings,
+        push_constants,
+        specialization_constants,
+    })
+}
+
+struct Module {
+    types: HashMap<u32, Type>,
+    decorations: HashMap<u32, Decorations>,
+    constants: HashMap<u32, u32>,
+}
+
+impl Module {
+    fn resolve_ids(&self, ids: &[u32]) -> Option<[u32; 3]> {
+        let mut size = [1; 3];
+        for (size, id) in size.iter_mut().zip(ids.iter()) {
+            *size = *self.constants.get(id)?;
+        }
+        Some(size)
+    }
+
+    fn descriptor_type(&self, ty: u32, storage_class: u32) -> Option<vk::DescriptorType> {
+        let descriptor_type = match self.types.get(&ty)? {
+            Type::Sampler => vk::DescriptorType::SAMPLER,
+            Type::SampledImage { image } => match self.types.get(image)? {
+                Type::Image {
+                    dim: DIM_BUFFER, ..
+                } => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+                _ => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            },
+            Type::Image {
+                dim: DIM_SUBPASS_DATA,
+                ..
+            } => vk::DescriptorType::INPUT_ATTACHMENT,
+            Type::Image {
+                dim: DIM_BUFFER,
+                sampled,
+            } => match sampled {
+                2 => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+                _ => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            },
+            Type::Image { sampled, .. } => match sampled {
+                2 => vk::DescriptorType::STORAGE_IMAGE,
+                _ => vk::DescriptorType::SAMPLED_IMAGE,
+            },
+            Type::AccelerationStructure => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            Type::Struct { .. } => {
+                let decorations = self.decorations.get(&ty);
+                if storage_class == storage_class::STORAGE_BUFFER
+                    || decorations.is_some_and(|d| d.buffer_block)
+                {
+                    vk::DescriptorType::STORAGE_BUFFER
+                } else if decorations.is_some_and(|d| d.block) {
+                    vk::DescriptorType::UNIFORM_BUFFER
+                } else {
+                    return None;
+                }
+            }
+            _ => return None,
+        };
+        Some(descriptor_type)
+    }
+
+    fn push_constant_range(&self, ty: u32) -> Option<PushConstantReflection> {
+        let Type::Struct { members } = self.types.get(&ty)? else {
+            return None;
+        };
+        let decorations = self.decorations.get(&ty)?;
+        let mut start = u32::MAX;
+        let mut end = 0;
+        for (i, member) in members.iter().enumerate() {
+            let offset = *decorations.member_offsets.get(&(i as u32))?;
+            let size = match self.types.get(member)? {
+                Type::Matrix { count, .. } => {
+                    let stride = *decorations.member_matrix_strides.get(&(i as u32))?;
+                    stride * count
+                }
+                _ => self.size_of(*member)?,
+            };
+            start = start.min(offset);
+            end = end.max(offset + size);
+        }
+        (start < end).then_some(PushConstantReflection {
+            offset: start,
+            size: end - start,
+        })
+    }
+
+    /// Size of a type with explicit layout.
+    fn size_of(&self, ty: u32) -> Option<u32> {
+        let size = match self.types.get(&ty)? {
+            Type::Scalar { size } => *size,
+            Type::Vector { component, count } => self.size_of(*component)? * count,
+            // Matrix strides are decorated on the struct member, see `push_constant_range`.
+            Type::Matrix { column, count } => self.size_of(*column)? * count,
+            Type::Array { element, length } => {
+                let stride = match self.decorations.get(&ty).and_then(|d| d.array_stride) {
+                    Some(stride) => stride,
+                    None => self.size_of(*element)?,
+                };
+                stride * self.constants.get(length)?
+            }
+            Type::RuntimeArray { .. } => 0,
+            Type::Struct { members } => {
+                let decorations = self.decorations.get(&ty)?;
+                let mut end = 0;
+                for (i, member) in members.iter().enumerate() {
+                    let offset = *decorations.member_offsets.get(&(i as u32))?;
+                    let size = match (
+                        self.types.get(member)?,
+                        decorations.member_matrix_strides.get(&(i as u32)),
+                    ) {
+                        (Type::Matrix { count, .. }, Some(stride)) => stride * count,
+                        _ => self.size_of(*member)?,
+                    };
+                    end = end.max(offset + size);
+                }
+                end
+            }
+            _ => return None,
+        };
+        Some(size)
+    }
+}
+
+fn execution_model_stage(execution_model: u32) -> vk::ShaderStageFlags {
+    match execution_model {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        5267 => vk::ShaderStageFlags::TASK_NV,
+        5268 => vk::ShaderStageFlags::MESH_NV,
+        5313 => vk::ShaderStageFlags::RAYGEN_KHR,
+        5314 => vk::ShaderStageFlags::INTERSECTION_KHR,
+        5315 => vk::ShaderStageFlags::ANY_HIT_KHR,
+        5316 => vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+        5317 => vk::ShaderStageFlags::MISS_KHR,
+        5318 => vk::ShaderStageFlags::CALLABLE_KHR,
+        5364 => vk::ShaderStageFlags::TASK_EXT,
+        5365 => vk::ShaderStageFlags::MESH_EXT,
+        _ => vk::ShaderStageFlags::empty(),
+    }
+}
+
+/// Decodes a nul-terminated literal string packed into words.
+fn parse_string(words: &[u32]) -> String {
+    let bytes: Vec<u8> = words
+        .iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take_while(|byte| *byte != 0)
+        .collect();
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+#[cfg(all(test, feature = "glsl"))]
+mod tests {
+    use super::*;
+
+    fn compile(source: &str, kind: shaderc::ShaderKind) -> Vec<u32> {
+        let compiler = shaderc::Compiler::new().unwrap();
+        let mut options = shaderc::CompileOptions::new().unwrap();
+        options.set_target_env(
+            shaderc::TargetEnv::Vulkan,
+            shaderc::EnvVersion::Vulkan1_2 as u32,
+        );
+        compiler
+            .compile_into_spirv(source, kind, "test.glsl", "main", Some(&options))
+            .unwrap()
+            .as_binary()
+            .to_vec()
+    }
+
+    fn binding(
+        set: u32,
+        binding: u32,
+        descriptor_type: vk::DescriptorType,
+        descriptor_count: u32,
+    ) -> DescriptorBindingReflection {
+        DescriptorBindingReflection {
+            set,
+            binding,
+            descriptor_type,
+            descriptor_count,
+        }
+    }
+
+    #[test]
+    fn test_invalid_module() {
+        assert!(matches!(reflect(&[MAGIC]), Err(ReflectError::Truncated)));
+        assert!(matches!(
+            reflect(&[0, 0, 0, 0, 0]),
+            Err(ReflectError::InvalidMagic)
+        ));
+        // An instruction claiming more words than the module has left.
+        assert!(matches!(
+            reflect(&[MAGIC, 0, 0, 0, 0, 4 << 16 | op::DECORATE]),
+            Err(ReflectError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_descriptor_types() {
+        let code = compile(
+            r#"
+            #version 460
+            #extension GL_EXT_nonuniform_qualifier : require
+            layout(local_size_x = 8, local_size_y = 4) in;
+            layout(set = 0, binding = 0) uniform Uniforms { vec4 color; } u_uniforms;
+            layout(set = 0, binding = 1) buffer Storage { float data[]; } u_storage;
+            layout(set = 0, binding = 2) uniform texture2D u_sampled;
+            layout(set = 0, binding = 3, rgba8) uniform image2D u_storage_image;
+            layout(set = 1, binding = 0) uniform sampler2D u_combined[4];
+            layout(set = 1, binding = 1) uniform sampler u_sampler;
+            layout(set = 1, binding = 2) uniform samplerBuffer u_uniform_texel;
+            layout(set = 1, binding = 3, r32f) uniform imageBuffer u_storage_texel;
+            layout(set = 2, binding = 0) uniform texture2D u_textures[];
+            void main() {
+                uint i = gl_GlobalInvocationID.x;
+                vec4 value = u_uniforms.color
+                    + texture(sampler2D(u_sampled, u_sampler), vec2(0.0))
+                    + texture(u_combined[i % 4], vec2(0.0))
+                    + texelFetch(u_uniform_texel, int(i))
+                    + imageLoad(u_storage_texel, int(i))
+                    + texture(sampler2D(u_textures[nonuniformEXT(i)], u_sampler), vec2(0.0));
+                imageStore(u_storage_image, ivec2(gl_GlobalInvocationID.xy), value);
+                u_storage.data[i] = value.x;
+            }
+            "#,
+            shaderc::ShaderKind::Compute,
+        );
+        let reflection = reflect(&code).unwrap();
+        assert_eq!(
+            reflection.bindings,
+            [
+                binding(0, 0, vk::DescriptorType::UNIFORM_BUFFER, 1),
+                binding(0, 1, vk::DescriptorType::STORAGE_BUFFER, 1),
+                binding(0, 2, vk::DescriptorType::SAMPLED_IMAGE, 1),
+                binding(0, 3, vk::DescriptorType::STORAGE_IMAGE, 1),
+                binding(1, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4),
+                binding(1, 1, vk::DescriptorType::SAMPLER, 1),
+                binding(1, 2, vk::DescriptorType::UNIFORM_TEXEL_BUFFER, 1),
+                binding(1, 3, vk::DescriptorType::STORAGE_TEXEL_BUFFER, 1),
+                // Runtime arrays are reported with a count of 0.
+                binding(2, 0, vk::DescriptorType::SAMPLED_IMAGE, 0),
+            ]
+        );
+        assert_eq!(reflection.push_constants, None);
+        assert!(reflection.specialization_constants.is_empty());
+
+        let entry_point = reflection.entry_point("main").unwrap();
+        assert_eq!(entry_point.stage, vk::ShaderStageFlags::COMPUTE);
+        assert_eq!(entry_point.workgroup_size, Some([8, 4, 1]));
+    }
+
+    #[test]
+    fn test_push_constants() {
+        let code = compile(
+            r#"
+            #version 460
+            layout(push_constant) uniform PushConstants {
+                layout(offset = 16) mat4 transform;
+                float scale;
+                mat3 normal;
+            } u_push_constants;
+            layout(location = 0) out vec3 v_normal;
+            void main() {
+                gl_Position = u_push_constants.transform * vec4(u_push_constants.scale);
+                v_normal = u_push_constants.normal * vec3(1.0);
+            }
+            "#,
+            shaderc::ShaderKind::Vertex,
+        );
+        let reflection = reflect(&code).unwrap();
+        // The mat3 starts at 96 and has a matrix stride of 16, so the block ends at 144
+        // rather than at 96 + 36.
+        assert_eq!(
+            reflection.push_constants,
+            Some(PushConstantReflection {
+                offset: 16,
+                size: 128,
+            })
+        );
+        assert!(reflection.bindings.is_empty());
+
+        let entry_point = reflection.entry_point("main").unwrap();
+        assert_eq!(entry_point.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(entry_point.workgroup_size, None);
+    }
+
+    #[test]
+    fn test_specialization_constants() {
+        let code = compile(
+            r#"
+            #version 460
+            layout(local_size_x_id = 3, local_size_y = 2) in;
+            layout(constant_id = 0) const bool FLAG = true;
+            layout(constant_id = 1) const double SCALE = 2.0;
+            layout(constant_id = 2) const int COUNT = 6;
+            layout(set = 0, binding = 0) uniform sampler2D u_samplers[COUNT];
+            layout(set = 0, binding = 1, r32f) uniform image2D u_output;
+            void main() {
+                vec4 value = texture(u_samplers[gl_LocalInvocationIndex % COUNT], vec2(0.0));
+                if (FLAG) {
+                    value *= float(SCALE);
+                }
+                imageStore(u_output, ivec2(gl_GlobalInvocationID.xy), value);
+            }
+            "#,
+            shaderc::ShaderKind::Compute,
+        );
+        let reflection = reflect(&code).unwrap();
+        assert_eq!(
+            reflection.specialization_constants,
+            [
+                // Booleans are 4 bytes, matching `vk::Bool32`.
+                SpecializationConstantReflection {
+                    constant_id: 0,
+                    size: 4,
+                },
+                SpecializationConstantReflection {
+                    constant_id: 1,
+                    size: 8,
+                },
+                SpecializationConstantReflection {
+                    constant_id: 2,
+                    size: 4,
+                },
+                // The x dimension of the workgroup size.
+                SpecializationConstantReflection {
+                    constant_id: 3,
+                    size: 4,
+                },
+            ]
+        );
+        // Array lengths given by specialization constants resolve to their default values.
+        assert_eq!(
+            reflection.bindings,
+            [
+                binding(0, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 6),
+                binding(0, 1, vk::DescriptorType::STORAGE_IMAGE, 1),
+            ]
+        );
+        // Workgroup sizes given by specialization constants are reported with their default values.
+        let entry_point = reflection.entry_point("main").unwrap();
+        assert_eq!(entry_point.workgroup_size, Some([1, 2, 1]));
+    }
+}
@@ -10,13 +10,24 @@ use bevy::tasks::ConditionalSendFuture;
 
 use thiserror::Error;
 
-use super::ShaderModule;
+use super::{reflect, ReflectError, ShaderModule, ShaderReflection};
 
 #[derive(TypePath, Asset)]
 pub struct SpirvShaderSource {
     pub(crate) source: Vec<u32>,
 }
 
+impl SpirvShaderSource {
+    pub fn code(&self) -> &[u32] {
+        &self.source
+    }
+    /// Descriptor bindings, push constants, specialization constants and workgroup sizes
+    /// declared by the shader.
+    pub fn reflect(&self) -> Result<ShaderReflection, ReflectError> {
+        reflect(&self.source)
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum SpirvLoaderError {
     #[error("io error: {0}")]
@@ -185,6 +185,12 @@ impl<T> GPUBorrowed<T> {
             value: inner_value,
         }
     }
+    /// Mutable access for futures that update the value in place. The value must not be replaced
+    /// through the returned reference, because the old value would be dropped without waiting
+    /// on the semaphore.
+    pub(crate) fn get_mut(&mut self) -> &mut T {
+        &mut self.value
+    }
 }
 impl<T> Deref for GPUBorrowed<T> {
     type Target = T;
@@ -0,0 +1,172 @@
+use ash::vk;
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    ecs::world::FromWorld,
+    math::UVec3,
+    tasks::ConditionalSendFuture,
+};
+use ddsfile::{Caps2, D3DFormat, Dds, DxgiFormat, MiscFlag};
+
+use super::{
+    copy_region, level_size, texture_create_info, texture_format, Texture, TextureLoaderError,
+};
+use crate::{image::mip_extent, Allocator};
+
+pub struct DdsLoader {
+    allocator: Allocator,
+}
+impl FromWorld for DdsLoader {
+    fn from_world(world: &mut bevy::ecs::world::World) -> Self {
+        Self {
+            allocator: world.resource::<Allocator>().clone(),
+        }
+    }
+}
+impl AssetLoader for DdsLoader {
+    type Asset = Texture;
+    type Settings = ();
+    type Error = TextureLoaderError;
+    fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext,
+    ) -> impl ConditionalSendFuture<Output = Result<Self::Asset, Self::Error>> {
+        let allocator = self.allocator.clone();
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            load_dds(&allocator, &bytes)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["dds"]
+    }
+}
+
+fn load_dds(allocator: &Allocator, bytes: &[u8]) -> Result<Texture, TextureLoaderError> {
+    let dds = Dds::read(&mut std::io::Cursor::new(bytes))?;
+    let format = dds_format(&dds)?;
+    let format_info = texture_format(format)?;
+
+    let is_cube = dds.header.caps2.contains(Caps2::CUBEMAP)
+        || dds
+            .header10
+            .as_ref()
+            .is_some_and(|header| header.misc_flag.contains(MiscFlag::TEXTURECUBE));
+    if is_cube
+        && !dds.header.caps2.contains(
+            Caps2::CUBEMAP_POSITIVEX
+                | Caps2::CUBEMAP_NEGATIVEX
+                | Caps2::CUBEMAP_POSITIVEY
+                | Caps2::CUBEMAP_NEGATIVEY
+                | Caps2::CUBEMAP_POSITIVEZ
+                | Caps2::CUBEMAP_NEGATIVEZ,
+        )
+    {
+        return Err(TextureLoaderError::InvalidTexture(
+            "cubemap is missing faces".to_string(),
+        ));
+    }
+    let is_array = dds.get_num_array_layers() > 1;
+    let mut array_layers = dds.get_num_array_layers().max(1);
+    if is_cube {
+        array_layers *= 6;
+    }
+    let extent = UVec3::new(dds.get_width(), dds.get_height(), dds.get_depth().max(1));
+    let view_type = match (extent.z > 1, is_cube, is_array) {
+        (true, ..) => vk::ImageViewType::TYPE_3D,
+        (_, true, false) => vk::ImageViewType::CUBE,
+        (_, true, true) => vk::ImageViewType::CUBE_ARRAY,
+        (_, false, false) => vk::ImageViewType::TYPE_2D,
+        (_, false, true) => vk::ImageViewType::TYPE_2D_ARRAY,
+    };
+    let mip_levels = dds.get_num_mipmap_levels().max(1);
+
+    // Each array layer is stored with its full mip chain in sequence.
+    let mut regions = Vec::with_capacity((array_layers * mip_levels) as usize);
+    let mut offset = 0;
+    for layer in 0..array_layers {
+        for level in 0..mip_levels {
+            let level_extent = mip_extent(extent, level);
+            regions.push(copy_region(offset, level, layer, 1, level_extent));
+            offset += level_size(&format_info, level_extent);
+        }
+    }
+    if offset != dds.data.len() as u64 {
+        return Err(TextureLoaderError::InvalidTexture(format!(
+            "texture has {} bytes, expected {offset}",
+            dds.data.len()
+        )));
+    }
+    let info = texture_create_info(format, view_type, extent, mip_levels, array_layers);
+    Texture::new(allocator, &info, view_type, dds.data, regions)
+}
+
+fn dds_format(dds: &Dds) -> Result<vk::Format, TextureLoaderError> {
+    if let Some(format) = dds.get_dxgi_format() {
+        let format = match format {
+            DxgiFormat::R8_UNorm => vk::Format::R8_UNORM,
+            DxgiFormat::R8G8_UNorm => vk::Format::R8G8_UNORM,
+            DxgiFormat::R8G8B8A8_UNorm => vk::Format::R8G8B8A8_UNORM,
+            DxgiFormat::R8G8B8A8_UNorm_sRGB => vk::Format::R8G8B8A8_SRGB,
+            DxgiFormat::B8G8R8A8_UNorm => vk::Format::B8G8R8A8_UNORM,
+            DxgiFormat::B8G8R8A8_UNorm_sRGB => vk::Format::B8G8R8A8_SRGB,
+            DxgiFormat::R16_Float => vk::Format::R16_SFLOAT,
+            DxgiFormat::R16G16_Float => vk::Format::R16G16_SFLOAT,
+            DxgiFormat::R16G16B16A16_Float => vk::Format::R16G16B16A16_SFLOAT,
+            DxgiFormat::R32_Float => vk::Format::R32_SFLOAT,
+            DxgiFormat::R32G32_Float => vk::Format::R32G32_SFLOAT,
+            DxgiFormat::R32G32B32A32_Float => vk::Format::R32G32B32A32_SFLOAT,
+            DxgiFormat::R10G10B10A2_UNorm => vk::Format::A2B10G10R10_UNORM_PACK32,
+            DxgiFormat::R11G11B10_Float => vk::Format::B10G11R11_UFLOAT_PACK32,
+            DxgiFormat::R9G9B9E5_SharedExp => vk::Format::E5B9G9R9_UFLOAT_PACK32,
+            DxgiFormat::BC1_UNorm => vk::Format::BC1_RGBA_UNORM_BLOCK,
+            DxgiFormat::BC1_UNorm_sRGB => vk::Format::BC1_RGBA_SRGB_BLOCK,
+            DxgiFormat::BC2_UNorm => vk::Format::BC2_UNORM_BLOCK,
+            DxgiFormat::BC2_UNorm_sRGB => vk::Format::BC2_SRGB_BLOCK,
+            DxgiFormat::BC3_UNorm => vk::Format::BC3_UNORM_BLOCK,
+            DxgiFormat::BC3_UNorm_sRGB => vk::Format::BC3_SRGB_BLOCK,
+            DxgiFormat::BC4_UNorm => vk::Format::BC4_UNORM_BLOCK,
+            DxgiFormat::BC4_SNorm => vk::Format::BC4_SNORM_BLOCK,
+            DxgiFormat::BC5_UNorm => vk::Format::BC5_UNORM_BLOCK,
+            DxgiFormat::BC5_SNorm => vk::Format::BC5_SNORM_BLOCK,
+            DxgiFormat::BC6H_UF16 => vk::Format::BC6H_UFLOAT_BLOCK,
+            DxgiFormat::BC6H_SF16 => vk::Format::BC6H_SFLOAT_BLOCK,
+            DxgiFormat::BC7_UNorm => vk::Format::BC7_UNORM_BLOCK,
+            DxgiFormat::BC7_UNorm_sRGB => vk::Format::BC7_SRGB_BLOCK,
+            format => {
+                return Err(TextureLoaderError::InvalidTexture(format!(
+                    "unsupported DXGI format {format:?}"
+                )))
+            }
+        };
+        return Ok(format);
+    }
+    if let Some(format) = dds.get_d3d_format() {
+        let format = match format {
+            D3DFormat::L8 => vk::Format::R8_UNORM,
+            D3DFormat::A8B8G8R8 => vk::Format::R8G8B8A8_UNORM,
+            D3DFormat::A8R8G8B8 => vk::Format::B8G8R8A8_UNORM,
+            D3DFormat::R16F => vk::Format::R16_SFLOAT,
+            D3DFormat::G16R16F => vk::Format::R16G16_SFLOAT,
+            D3DFormat::A16B16G16R16F => vk::Format::R16G16B16A16_SFLOAT,
+            D3DFormat::R32F => vk::Format::R32_SFLOAT,
+            D3DFormat::G32R32F => vk::Format::R32G32_SFLOAT,
+            D3DFormat::A32B32G32R32F => vk::Format::R32G32B32A32_SFLOAT,
+            D3DFormat::DXT1 => vk::Format::BC1_RGBA_UNORM_BLOCK,
+            D3DFormat::DXT3 => vk::Format::BC2_UNORM_BLOCK,
+            D3DFormat::DXT5 => vk::Format::BC3_UNORM_BLOCK,
+            format => {
+                return Err(TextureLoaderError::InvalidTexture(format!(
+                    "unsupported D3D format {format:?}"
+                )))
+            }
+        };
+        return Ok(format);
+    }
+    Err(TextureLoaderError::InvalidTexture(
+        "unknown pixel format".to_string(),
+    ))
+}
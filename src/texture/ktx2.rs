@@ -0,0 +1,131 @@
+use std::{borrow::Cow, io::Read};
+
+use ash::vk;
+use bevy::{
+    asset::{io::Reader, AssetLoader, LoadContext},
+    ecs::world::FromWorld,
+    math::UVec3,
+    tasks::ConditionalSendFuture,
+};
+
+use super::{
+    copy_region, level_size, texture_create_info, texture_format, Texture, TextureLoaderError,
+};
+use crate::{image::mip_extent, Allocator};
+
+pub struct Ktx2Loader {
+    allocator: Allocator,
+}
+impl FromWorld for Ktx2Loader {
+    fn from_world(world: &mut bevy::ecs::world::World) -> Self {
+        Self {
+            allocator: world.resource::<Allocator>().clone(),
+        }
+    }
+}
+impl AssetLoader for Ktx2Loader {
+    type Asset = Texture;
+    type Settings = ();
+    type Error = TextureLoaderError;
+    fn load(
+        &self,
+        reader: &mut dyn Reader,
+        _settings: &Self::Settings,
+        _load_context: &mut LoadContext,
+    ) -> impl ConditionalSendFuture<Output = Result<Self::Asset, Self::Error>> {
+        let allocator = self.allocator.clone();
+        Box::pin(async move {
+            let mut bytes = Vec::new();
+            reader.read_to_end(&mut bytes).await?;
+            load_ktx2(&allocator, &bytes)
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        &["ktx2"]
+    }
+}
+
+fn load_ktx2(allocator: &Allocator, bytes: &[u8]) -> Result<Texture, TextureLoaderError> {
+    let ktx = ::ktx2::Reader::new(bytes)?;
+    let header = ktx.header();
+    let Some(format) = header.format else {
+        return Err(TextureLoaderError::InvalidTexture(
+            "Basis Universal textures must be transcoded before loading".to_string(),
+        ));
+    };
+    let format = vk::Format::from_raw(format.0.get() as i32);
+    let format_info = texture_format(format)?;
+
+    // Dimensions not present in the file are stored as 0.
+    let extent = UVec3::new(
+        header.pixel_width,
+        header.pixel_height.max(1),
+        header.pixel_depth.max(1),
+    );
+    let is_array = header.layer_count > 0;
+    let is_cube = header.face_count == 6;
+    let view_type = match (
+        header.pixel_depth > 0,
+        header.pixel_height > 0,
+        is_cube,
+        is_array,
+    ) {
+        (true, ..) => vk::ImageViewType::TYPE_3D,
+        (_, _, true, false) => vk::ImageViewType::CUBE,
+        (_, _, true, true) => vk::ImageViewType::CUBE_ARRAY,
+        (_, false, _, false) => vk::ImageViewType::TYPE_1D,
+        (_, false, _, true) => vk::ImageViewType::TYPE_1D_ARRAY,
+        (_, true, _, false) => vk::ImageViewType::TYPE_2D,
+        (_, true, _, true) => vk::ImageViewType::TYPE_2D_ARRAY,
+    };
+    // Cube faces are stored as consecutive array layers.
+    let array_layers = header.layer_count.max(1) * header.face_count;
+
+    // Each level contains all array layers and faces of that level in sequence.
+    let mut data = Vec::new();
+    let mut regions = Vec::with_capacity(ktx.levels().len());
+    for (level, level_data) in ktx.levels().enumerate() {
+        let level = level as u32;
+        let level_data: Cow<[u8]> = match header.supercompression_scheme {
+            None => Cow::Borrowed(level_data),
+            Some(::ktx2::SupercompressionScheme::Zstandard) => {
+                let mut cursor = std::io::Cursor::new(level_data);
+                let mut decoder = ruzstd::StreamingDecoder::new(&mut cursor)
+                    .map_err(|err| TextureLoaderError::InvalidTexture(err.to_string()))?;
+                let mut decompressed = Vec::new();
+                decoder.read_to_end(&mut decompressed)?;
+                Cow::Owned(decompressed)
+            }
+            Some(scheme) => {
+                return Err(TextureLoaderError::UnsupportedSupercompression(format!(
+                    "{scheme:?}"
+                )))
+            }
+        };
+        let level_extent = mip_extent(extent, level);
+        let expected_size = level_size(&format_info, level_extent) * array_layers as u64;
+        if level_data.len() as u64 != expected_size {
+            return Err(TextureLoaderError::InvalidTexture(format!(
+                "mip level {level} has {} bytes, expected {expected_size}",
+                level_data.len()
+            )));
+        }
+        regions.push(copy_region(
+            data.len() as u64,
+            level,
+            0,
+            array_layers,
+            level_extent,
+        ));
+        data.extend_from_slice(&level_data);
+    }
+    let info = texture_create_info(
+        format,
+        view_type,
+        extent,
+        regions.len() as u32,
+        array_layers,
+    );
+    Texture::new(allocator, &info, view_type, data, regions)
+}
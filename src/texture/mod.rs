@@ -0,0 +1,423 @@
+//! Loaders for textures stored in GPU-ready container formats, including block-compressed formats.
+//!
+//! The loaders create the [`Texture`] directly on the device, and [`TexturePlugin`] uploads the
+//! texture data on the next frame.
+
+#[cfg(feature = "dds")]
+mod dds;
+#[cfg(feature = "ktx2")]
+mod ktx2;
+
+#[cfg(feature = "dds")]
+pub use dds::DdsLoader;
+#[cfg(feature = "ktx2")]
+pub use ktx2::Ktx2Loader;
+
+use ash::vk;
+use bevy::{
+    app::{App, Plugin, PostUpdate},
+    asset::{Asset, AssetApp, AssetEvent, AssetId, Assets},
+    ecs::{
+        event::EventReader,
+        schedule::IntoSystemConfigs,
+        system::{Res, ResMut, Resource},
+    },
+    math::UVec3,
+    reflect::TypePath,
+};
+use thiserror::Error;
+
+use crate::{
+    buffer::{Buffer, StagingBelt},
+    commands::copy_buffer_to_image,
+    ecs::IntoRenderSystem,
+    future::{gpu_future, GPUBorrowedResource, GPUFutureBlock, GPUOwnedResource},
+    selectors::Graphics,
+    utils::{Format, Permutation},
+    Allocator, Device, GpuImage, GpuImageUploadSet, HasDevice, RhyoliteApp,
+};
+
+/// Registers the [`Texture`] asset and its loaders.
+/// Must be added after [`RhyolitePlugin`](crate::RhyolitePlugin).
+pub struct TexturePlugin;
+
+impl Plugin for TexturePlugin {
+    fn build(&self, app: &mut App) {
+        // Block-compressed formats are optional. The loaders return an error for textures in
+        // formats that the device does not support.
+        app.enable_feature::<vk::PhysicalDeviceFeatures>(|f| &mut f.texture_compression_bc);
+        app.enable_feature::<vk::PhysicalDeviceFeatures>(|f| &mut f.texture_compression_etc2);
+        app.enable_feature::<vk::PhysicalDeviceFeatures>(|f| &mut f.texture_compression_astc_ldr);
+
+        app.init_asset::<Texture>();
+        app.init_resource::<PendingTextureUploads>();
+        app.add_systems(
+            PostUpdate,
+            (
+                queue_texture_uploads,
+                upload_textures
+                    .into_render_system::<Graphics>()
+                    .after(queue_texture_uploads),
+            )
+                .in_set(GpuImageUploadSet),
+        );
+    }
+    fn finish(&self, app: &mut App) {
+        #[cfg(feature = "ktx2")]
+        app.init_asset_loader::<Ktx2Loader>();
+        #[cfg(feature = "dds")]
+        app.init_asset_loader::<DdsLoader>();
+    }
+}
+
+/// A texture loaded from a KTX2 or DDS file.
+///
+/// The image is freed as soon as the asset is dropped, so render systems using the texture
+/// should hold on to its handle.
+#[derive(TypePath, Asset)]
+pub struct Texture {
+    image: GPUBorrowedResource<GpuImage>,
+    /// Texture data waiting to be uploaded.
+    pending: Option<TextureData>,
+}
+
+struct TextureData {
+    data: Vec<u8>,
+    /// Buffer offsets are relative to the start of `data`.
+    regions: Vec<vk::BufferImageCopy>,
+}
+
+impl Texture {
+    fn new(
+        allocator: &Allocator,
+        info: &vk::ImageCreateInfo,
+        view_type: vk::ImageViewType,
+        data: Vec<u8>,
+        regions: Vec<vk::BufferImageCopy>,
+    ) -> Result<Self, TextureLoaderError> {
+        check_format_support(allocator.device(), info)?;
+        let image = GpuImage::from_create_info(allocator.clone(), info, Some(view_type))?;
+        Ok(Self {
+            image: GPUBorrowedResource::new(image),
+            pending: Some(TextureData { data, regions }),
+        })
+    }
+    /// Returns `None` if the texture hasn't been uploaded yet.
+    pub fn image(&self) -> Option<&GPUBorrowedResource<GpuImage>> {
+        if self.pending.is_some() {
+            return None;
+        }
+        Some(&self.image)
+    }
+    /// Returns `None` if the texture hasn't been uploaded yet.
+    pub fn image_mut(&mut self) -> Option<&mut GPUBorrowedResource<GpuImage>> {
+        if self.pending.is_some() {
+            return None;
+        }
+        Some(&mut self.image)
+    }
+}
+
+#[derive(Debug, Error)]
+pub enum TextureLoaderError {
+    #[error("io error: {0}")]
+    IoError(#[from] std::io::Error),
+
+    #[error("vulkan error: {0:?}")]
+    VkError(#[from] vk::Result),
+
+    #[cfg(feature = "ktx2")]
+    #[error("ktx2 error: {0}")]
+    Ktx2Error(#[from] ::ktx2::ParseError),
+
+    #[cfg(feature = "dds")]
+    #[error("dds error: {0}")]
+    DdsError(#[from] ddsfile::Error),
+
+    #[error("invalid texture: {0}")]
+    InvalidTexture(String),
+
+    #[error("supercompression scheme {0} is not supported")]
+    UnsupportedSupercompression(String),
+
+    #[error("{0:?} is not supported by this device")]
+    UnsupportedFormat(vk::Format),
+
+    #[error("{format:?} requires the {feature} feature, which is not available on this device")]
+    MissingFeature {
+        format: vk::Format,
+        feature: &'static str,
+    },
+
+    #[error("the texture exceeds the image limits of this device for {0:?}")]
+    ExceedsLimits(vk::Format),
+}
+
+/// Validates a format read from a texture file. Only core color formats are accepted.
+fn texture_format(format: vk::Format) -> Result<Format, TextureLoaderError> {
+    if format.as_raw() < vk::Format::R4G4_UNORM_PACK8.as_raw()
+        || format.as_raw() > vk::Format::ASTC_12X12_SRGB_BLOCK.as_raw()
+    {
+        return Err(TextureLoaderError::UnsupportedFormat(format));
+    }
+    let info = Format::from(format);
+    if info.permutation.aspect_mask() != vk::ImageAspectFlags::COLOR {
+        return Err(TextureLoaderError::InvalidTexture(format!(
+            "{format:?} is not a color format"
+        )));
+    }
+    Ok(info)
+}
+
+fn check_format_support(
+    device: &Device,
+    info: &vk::ImageCreateInfo,
+) -> Result<(), TextureLoaderError> {
+    let format = Format::from(info.format);
+    if format.is_compressed() {
+        let features = device.feature::<vk::PhysicalDeviceFeatures>();
+        let (enabled, feature) = match format.permutation {
+            Permutation::ETC2_RGB
+            | Permutation::ETC2_RGBA
+            | Permutation::EAC_R
+            | Permutation::EAC_RG => (
+                features.map(|f| f.texture_compression_etc2),
+                "textureCompressionETC2",
+            ),
+            Permutation::ASTC { .. } => (
+                features.map(|f| f.texture_compression_astc_ldr),
+                "textureCompressionASTC_LDR",
+            ),
+            _ => (
+                features.map(|f| f.texture_compression_bc),
+                "textureCompressionBC",
+            ),
+        };
+        if enabled != Some(vk::TRUE) {
+            return Err(TextureLoaderError::MissingFeature {
+                format: info.format,
+                feature,
+            });
+        }
+    }
+    let Some(properties) =
+        device
+            .physical_device()
+            .image_format_properties(&vk::PhysicalDeviceImageFormatInfo2 {
+                format: info.format,
+                ty: info.image_type,
+                tiling: info.tiling,
+                usage: info.usage,
+                flags: info.flags,
+                ..Default::default()
+            })?
+    else {
+        return Err(TextureLoaderError::UnsupportedFormat(info.format));
+    };
+    let limits = properties.image_format_properties;
+    if info.extent.width > limits.max_extent.width
+        || info.extent.height > limits.max_extent.height
+        || info.extent.depth > limits.max_extent.depth
+        || info.mip_levels > limits.max_mip_levels
+        || info.array_layers > limits.max_array_layers
+    {
+        return Err(TextureLoaderError::ExceedsLimits(info.format));
+    }
+    Ok(())
+}
+
+fn texture_create_info(
+    format: vk::Format,
+    view_type: vk::ImageViewType,
+    extent: UVec3,
+    mip_levels: u32,
+    array_layers: u32,
+) -> vk::ImageCreateInfo<'static> {
+    let image_type = match view_type {
+        vk::ImageViewType::TYPE_1D | vk::ImageViewType::TYPE_1D_ARRAY => vk::ImageType::TYPE_1D,
+        vk::ImageViewType::TYPE_3D => vk::ImageType::TYPE_3D,
+        _ => vk::ImageType::TYPE_2D,
+    };
+    let flags = match view_type {
+        vk::ImageViewType::CUBE | vk::ImageViewType::CUBE_ARRAY => {
+            vk::ImageCreateFlags::CUBE_COMPATIBLE
+        }
+        _ => vk::ImageCreateFlags::empty(),
+    };
+    vk::ImageCreateInfo {
+        flags,
+        image_type,
+        format,
+        extent: vk::Extent3D {
+            width: extent.x,
+            height: extent.y,
+            depth: extent.z,
+        },
+        mip_levels,
+        array_layers,
+        samples: vk::SampleCountFlags::TYPE_1,
+        tiling: vk::ImageTiling::OPTIMAL,
+        usage: vk::ImageUsageFlags::SAMPLED | vk::ImageUsageFlags::TRANSFER_DST,
+        initial_layout: vk::ImageLayout::UNDEFINED,
+        ..Default::default()
+    }
+}
+
+/// The size in bytes of one array layer of a mip level with the given extent.
+fn level_size(format: &Format, extent: UVec3) -> u64 {
+    let (block_width, block_height) = format.block_extent();
+    extent.x.div_ceil(block_width) as u64
+        * extent.y.div_ceil(block_height) as u64
+        * extent.z as u64
+        * format.block_size() as u64
+}
+
+fn copy_region(
+    buffer_offset: u64,
+    mip_level: u32,
+    base_array_layer: u32,
+    layer_count: u32,
+    extent: UVec3,
+) -> vk::BufferImageCopy {
+    vk::BufferImageCopy {
+        buffer_offset,
+        buffer_row_length: 0,
+        buffer_image_height: 0,
+        image_subresource: vk::ImageSubresourceLayers {
+            aspect_mask: vk::ImageAspectFlags::COLOR,
+            mip_level,
+            base_array_layer,
+            layer_count,
+        },
+        image_offset: vk::Offset3D::default(),
+        image_extent: vk::Extent3D {
+            width: extent.x,
+            height: extent.y,
+            depth: extent.z,
+        },
+    }
+}
+
+/// Textures waiting to be uploaded, in the order they were loaded.
+#[derive(Resource, Default)]
+struct PendingTextureUploads(Vec<AssetId<Texture>>);
+
+fn queue_texture_uploads(
+    mut events: EventReader<AssetEvent<Texture>>,
+    mut pending: ResMut<PendingTextureUploads>,
+) {
+    for event in events.read() {
+        match event {
+            // Reloaded textures are replaced with a new asset, which triggers `Modified`.
+            AssetEvent::Added { id } | AssetEvent::Modified { id } => {
+                if !pending.0.contains(id) {
+                    pending.0.push(*id);
+                }
+            }
+            AssetEvent::Removed { id } => {
+                pending.0.retain(|pending| pending != id);
+            }
+            _ => (),
+        }
+    }
+}
+
+fn upload_textures<'w, 's>(
+    mut pending: ResMut<'w, PendingTextureUploads>,
+    textures: ResMut<'w, Assets<Texture>>,
+    allocator: Res<'w, Allocator>,
+    mut staging_belt: ResMut<'w, StagingBelt>,
+) -> impl GPUFutureBlock + use<'w, 's> {
+    let textures = textures.into_inner();
+
+    // Upload as many textures as fit into one chunk of the staging belt. The rest waits for the next frame.
+    // Textures larger than a chunk are uploaded one per frame through a dedicated staging buffer.
+    let budget = staging_belt.chunk_size();
+    let mut size = 0;
+    let mut uploads = Vec::new();
+    let mut dedicated = None;
+    pending.0.retain(|id| {
+        // Textures without pending data were already uploaded.
+        let Some(data) = textures
+            .get(*id)
+            .and_then(|texture| texture.pending.as_ref())
+        else {
+            return false;
+        };
+        let data_size = data.data.len() as u64;
+        if data_size > budget {
+            if dedicated.is_some() {
+                return true;
+            }
+            let buffer = Buffer::new_host(
+                allocator.clone(),
+                data_size,
+                16,
+                vk::BufferUsageFlags::TRANSFER_SRC,
+            )
+            .and_then(|mut buffer| {
+                buffer.as_slice_mut().copy_from_slice(&data.data);
+                buffer.flush()?;
+                Ok(buffer)
+            });
+            match buffer {
+                Ok(buffer) => dedicated = Some((*id, buffer)),
+                Err(err) => {
+                    tracing::error!(
+                        ?id,
+                        size = data_size,
+                        ?err,
+                        "Failed to create staging buffer"
+                    )
+                }
+            }
+            return false;
+        }
+        // Buffer offsets must be a multiple of the texel block size.
+        let offset = size.next_multiple_of(16);
+        if offset + data_size > budget {
+            return true;
+        }
+        size = offset + data_size;
+        uploads.push((*id, offset));
+        false
+    });
+    let uploads = (!uploads.is_empty() || dedicated.is_some()).then_some(uploads);
+
+    gpu_future! { move
+        let Some(uploads) = uploads else {
+            return;
+        };
+        if let Some((id, buffer)) = dedicated {
+            let texture = textures.get_mut(id).unwrap();
+            let TextureData { regions, .. } = texture.pending.take().unwrap();
+            let mut buffer = GPUOwnedResource::new(retain!(buffer));
+            copy_buffer_to_image(&mut buffer, &mut texture.image)
+                .with_regions(&regions)
+                .discard_image_contents()
+                .await;
+        }
+        if uploads.is_empty() {
+            return;
+        }
+        let mut staging_buffer = staging_belt.allocate_buffer(size, 16);
+        let mut batch = Vec::with_capacity(uploads.len());
+        for (id, offset) in uploads {
+            let texture = textures.get_mut(id).unwrap();
+            let TextureData { data, mut regions } = texture.pending.take().unwrap();
+            staging_buffer[offset as usize..offset as usize + data.len()].copy_from_slice(&data);
+            for region in regions.iter_mut() {
+                region.buffer_offset += staging_buffer.offset + offset;
+            }
+            batch.push((id, regions));
+        }
+        let mut staging_buffer = GPUOwnedResource::new(retain!(staging_buffer));
+        for (id, regions) in batch.iter() {
+            let texture = textures.get_mut(*id).unwrap();
+            copy_buffer_to_image(&mut staging_buffer, &mut texture.image)
+                .with_regions(regions)
+                .discard_image_contents()
+                .await;
+        }
+    }
+}
@@ -35,7 +35,7 @@ pub struct Format {
 }
 
 #[allow(non_camel_case_types)]
-#[derive(Clone, Debug, Copy)]
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
 pub enum Permutation {
     R,
     G,
@@ -79,10 +79,107 @@ pub enum Permutation {
     },
 }
 
+impl Permutation {
+    /// The image aspects present in a format with this permutation.
+    pub fn aspect_mask(&self) -> vk::ImageAspectFlags {
+        match self {
+            Permutation::D => vk::ImageAspectFlags::DEPTH,
+            Permutation::S => vk::ImageAspectFlags::STENCIL,
+            Permutation::DS => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+            _ => vk::ImageAspectFlags::COLOR,
+        }
+    }
+}
+
+/// The image aspects present in `format`.
+///
+/// Unlike converting to [`Format`], this also works for formats unknown to this crate,
+/// which are assumed to be color formats.
+pub fn format_aspect_mask(format: vk::Format) -> vk::ImageAspectFlags {
+    Format::from_vk(format).map_or(vk::ImageAspectFlags::COLOR, |f| f.permutation.aspect_mask())
+}
+
+impl Format {
+    pub fn is_compressed(&self) -> bool {
+        matches!(
+            self.permutation,
+            Permutation::BC1_RGB
+                | Permutation::BC1_RGBA
+                | Permutation::BC2
+                | Permutation::BC3
+                | Permutation::BC4
+                | Permutation::BC5
+                | Permutation::BC6H
+                | Permutation::BC7
+                | Permutation::ETC2_RGB
+                | Permutation::ETC2_RGBA
+                | Permutation::EAC_R
+                | Permutation::EAC_RG
+                | Permutation::ASTC { .. }
+        )
+    }
+    /// The width and height in texels of one texel block.
+    pub fn block_extent(&self) -> (u32, u32) {
+        match self.permutation {
+            Permutation::ASTC { x, y } => (x as u32, y as u32),
+            _ if self.is_compressed() => (4, 4),
+            _ => (1, 1),
+        }
+    }
+    /// The size in bytes of one texel block. Only defined for color formats.
+    pub fn block_size(&self) -> u32 {
+        assert_eq!(
+            self.permutation.aspect_mask(),
+            vk::ImageAspectFlags::COLOR,
+            "Block size is only defined for color formats"
+        );
+        match self.permutation {
+            Permutation::BC1_RGB
+            | Permutation::BC1_RGBA
+            | Permutation::BC4
+            | Permutation::ETC2_RGB
+            | Permutation::EAC_R => 8,
+            Permutation::ETC2_RGBA if self.a == 1 => 8,
+            _ if self.is_compressed() => 16,
+            _ => (self.r as u32 + self.g as u32 + self.b as u32 + self.a as u32) / 8,
+        }
+    }
+    /// Whether the two formats belong to the same compatibility class, so that a `MUTABLE_FORMAT`
+    /// image of one format may be viewed with the other format.
+    /// Depth stencil formats are only compatible with themselves.
+    pub fn is_compatible_with(&self, other: &Format) -> bool {
+        if self.permutation.aspect_mask() != vk::ImageAspectFlags::COLOR
+            || other.permutation.aspect_mask() != vk::ImageAspectFlags::COLOR
+        {
+            return self.permutation == other.permutation
+                && self.ty == other.ty
+                && self.r == other.r;
+        }
+        match (self.is_compressed(), other.is_compressed()) {
+            // Compressed formats are compatible when they have the same block encoding.
+            (true, true) => self.permutation == other.permutation && self.a == other.a,
+            // Uncompressed formats are compatible when they have the same texel size.
+            (false, false) => {
+                let bits = |f: &Format| f.r as u32 + f.g as u32 + f.b as u32 + f.a as u32;
+                bits(self) == bits(other)
+            }
+            _ => false,
+        }
+    }
+}
+
 impl From<vk::Format> for Format {
-    #[rustfmt::skip]
     fn from(value: vk::Format) -> Self {
-        match value {
+        Format::from_vk(value).unwrap_or_else(|| panic!("Unsupported format {value:?}"))
+    }
+}
+
+impl Format {
+    /// Describe the format. Returns None for formats that cannot be described, such as
+    /// multi-planar formats.
+    #[rustfmt::skip]
+    pub fn from_vk(value: vk::Format) -> Option<Self> {
+        let format = match value {
             vk::Format::R4G4_UNORM_PACK8 => Format { r: 4, g: 4, b: 0, a: 0, ty: FormatType::UNorm, permutation: Permutation::RG },
             vk::Format::R4G4B4A4_UNORM_PACK16 => Format { r: 4, g: 4, b: 4, a: 4, ty: FormatType::UNorm, permutation: Permutation::RGBA },
             vk::Format::B4G4R4A4_UNORM_PACK16 => Format { r: 4, g: 4, b: 4, a: 4, ty: FormatType::UNorm, permutation: Permutation::BGRA },
@@ -295,8 +392,9 @@ impl From<vk::Format> for Format {
             vk::Format::ASTC_12X10_SRGB_BLOCK => Format { r: 0, g: 0, b: 0, a: 0, ty: FormatType::sRGB, permutation: Permutation::ASTC { x: 12, y: 10 } },
             vk::Format::ASTC_12X12_UNORM_BLOCK => Format { r: 0, g: 0, b: 0, a: 0, ty: FormatType::UNorm, permutation: Permutation::ASTC { x: 12, y: 12 } },
             vk::Format::ASTC_12X12_SRGB_BLOCK => Format { r: 0, g: 0, b: 0, a: 0, ty: FormatType::sRGB, permutation: Permutation::ASTC { x: 12, y: 12 } },
-            _ => panic!(),
-        }
+            _ => return None,
+        };
+        Some(format)
     }
 }
 
@@ -499,6 +597,111 @@ impl TryFrom<Format> for vk::Format {
     }
 }
 
+/// Maps a wgpu texture format, as used by [`bevy::image::Image`], to the equivalent Vulkan format.
+/// Returns `None` for depth stencil and multi-planar formats, which can't be uploaded from asset data.
+#[rustfmt::skip]
+pub fn texture_format_to_vk(format: wgpu_types::TextureFormat) -> Option<vk::Format> {
+    use wgpu_types::{AstcBlock, AstcChannel, TextureFormat};
+    let format = match format {
+        TextureFormat::R8Unorm => vk::Format::R8_UNORM,
+        TextureFormat::R8Snorm => vk::Format::R8_SNORM,
+        TextureFormat::R8Uint => vk::Format::R8_UINT,
+        TextureFormat::R8Sint => vk::Format::R8_SINT,
+        TextureFormat::R16Uint => vk::Format::R16_UINT,
+        TextureFormat::R16Sint => vk::Format::R16_SINT,
+        TextureFormat::R16Unorm => vk::Format::R16_UNORM,
+        TextureFormat::R16Snorm => vk::Format::R16_SNORM,
+        TextureFormat::R16Float => vk::Format::R16_SFLOAT,
+        TextureFormat::Rg8Unorm => vk::Format::R8G8_UNORM,
+        TextureFormat::Rg8Snorm => vk::Format::R8G8_SNORM,
+        TextureFormat::Rg8Uint => vk::Format::R8G8_UINT,
+        TextureFormat::Rg8Sint => vk::Format::R8G8_SINT,
+        TextureFormat::R32Uint => vk::Format::R32_UINT,
+        TextureFormat::R32Sint => vk::Format::R32_SINT,
+        TextureFormat::R32Float => vk::Format::R32_SFLOAT,
+        TextureFormat::Rg16Uint => vk::Format::R16G16_UINT,
+        TextureFormat::Rg16Sint => vk::Format::R16G16_SINT,
+        TextureFormat::Rg16Unorm => vk::Format::R16G16_UNORM,
+        TextureFormat::Rg16Snorm => vk::Format::R16G16_SNORM,
+        TextureFormat::Rg16Float => vk::Format::R16G16_SFLOAT,
+        TextureFormat::Rgba8Unorm => vk::Format::R8G8B8A8_UNORM,
+        TextureFormat::Rgba8UnormSrgb => vk::Format::R8G8B8A8_SRGB,
+        TextureFormat::Rgba8Snorm => vk::Format::R8G8B8A8_SNORM,
+        TextureFormat::Rgba8Uint => vk::Format::R8G8B8A8_UINT,
+        TextureFormat::Rgba8Sint => vk::Format::R8G8B8A8_SINT,
+        TextureFormat::Bgra8Unorm => vk::Format::B8G8R8A8_UNORM,
+        TextureFormat::Bgra8UnormSrgb => vk::Format::B8G8R8A8_SRGB,
+        TextureFormat::Rgb9e5Ufloat => vk::Format::E5B9G9R9_UFLOAT_PACK32,
+        TextureFormat::Rgb10a2Uint => vk::Format::A2B10G10R10_UINT_PACK32,
+        TextureFormat::Rgb10a2Unorm => vk::Format::A2B10G10R10_UNORM_PACK32,
+        TextureFormat::Rg11b10Ufloat => vk::Format::B10G11R11_UFLOAT_PACK32,
+        TextureFormat::Rg32Uint => vk::Format::R32G32_UINT,
+        TextureFormat::Rg32Sint => vk::Format::R32G32_SINT,
+        TextureFormat::Rg32Float => vk::Format::R32G32_SFLOAT,
+        TextureFormat::Rgba16Uint => vk::Format::R16G16B16A16_UINT,
+        TextureFormat::Rgba16Sint => vk::Format::R16G16B16A16_SINT,
+        TextureFormat::Rgba16Unorm => vk::Format::R16G16B16A16_UNORM,
+        TextureFormat::Rgba16Snorm => vk::Format::R16G16B16A16_SNORM,
+        TextureFormat::Rgba16Float => vk::Format::R16G16B16A16_SFLOAT,
+        TextureFormat::Rgba32Uint => vk::Format::R32G32B32A32_UINT,
+        TextureFormat::Rgba32Sint => vk::Format::R32G32B32A32_SINT,
+        TextureFormat::Rgba32Float => vk::Format::R32G32B32A32_SFLOAT,
+
+        TextureFormat::Bc1RgbaUnorm => vk::Format::BC1_RGBA_UNORM_BLOCK,
+        TextureFormat::Bc1RgbaUnormSrgb => vk::Format::BC1_RGBA_SRGB_BLOCK,
+        TextureFormat::Bc2RgbaUnorm => vk::Format::BC2_UNORM_BLOCK,
+        TextureFormat::Bc2RgbaUnormSrgb => vk::Format::BC2_SRGB_BLOCK,
+        TextureFormat::Bc3RgbaUnorm => vk::Format::BC3_UNORM_BLOCK,
+        TextureFormat::Bc3RgbaUnormSrgb => vk::Format::BC3_SRGB_BLOCK,
+        TextureFormat::Bc4RUnorm => vk::Format::BC4_UNORM_BLOCK,
+        TextureFormat::Bc4RSnorm => vk::Format::BC4_SNORM_BLOCK,
+        TextureFormat::Bc5RgUnorm => vk::Format::BC5_UNORM_BLOCK,
+        TextureFormat::Bc5RgSnorm => vk::Format::BC5_SNORM_BLOCK,
+        TextureFormat::Bc6hRgbUfloat => vk::Format::BC6H_UFLOAT_BLOCK,
+        TextureFormat::Bc6hRgbFloat => vk::Format::BC6H_SFLOAT_BLOCK,
+        TextureFormat::Bc7RgbaUnorm => vk::Format::BC7_UNORM_BLOCK,
+        TextureFormat::Bc7RgbaUnormSrgb => vk::Format::BC7_SRGB_BLOCK,
+        TextureFormat::Etc2Rgb8Unorm => vk::Format::ETC2_R8G8B8_UNORM_BLOCK,
+        TextureFormat::Etc2Rgb8UnormSrgb => vk::Format::ETC2_R8G8B8_SRGB_BLOCK,
+        TextureFormat::Etc2Rgb8A1Unorm => vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK,
+        TextureFormat::Etc2Rgb8A1UnormSrgb => vk::Format::ETC2_R8G8B8A1_SRGB_BLOCK,
+        TextureFormat::Etc2Rgba8Unorm => vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+        TextureFormat::Etc2Rgba8UnormSrgb => vk::Format::ETC2_R8G8B8A8_SRGB_BLOCK,
+        TextureFormat::EacR11Unorm => vk::Format::EAC_R11_UNORM_BLOCK,
+        TextureFormat::EacR11Snorm => vk::Format::EAC_R11_SNORM_BLOCK,
+        TextureFormat::EacRg11Unorm => vk::Format::EAC_R11G11_UNORM_BLOCK,
+        TextureFormat::EacRg11Snorm => vk::Format::EAC_R11G11_SNORM_BLOCK,
+        TextureFormat::Astc { block, channel } => {
+            let (x, y) = match block {
+                AstcBlock::B4x4 => (4, 4),
+                AstcBlock::B5x4 => (5, 4),
+                AstcBlock::B5x5 => (5, 5),
+                AstcBlock::B6x5 => (6, 5),
+                AstcBlock::B6x6 => (6, 6),
+                AstcBlock::B8x5 => (8, 5),
+                AstcBlock::B8x6 => (8, 6),
+                AstcBlock::B8x8 => (8, 8),
+                AstcBlock::B10x5 => (10, 5),
+                AstcBlock::B10x6 => (10, 6),
+                AstcBlock::B10x8 => (10, 8),
+                AstcBlock::B10x10 => (10, 10),
+                AstcBlock::B12x10 => (12, 10),
+                AstcBlock::B12x12 => (12, 12),
+            };
+            let ty = match channel {
+                AstcChannel::Unorm => FormatType::UNorm,
+                AstcChannel::UnormSrgb => FormatType::sRGB,
+                // HDR ASTC formats are not representable as a `Format`.
+                AstcChannel::Hdr => return None,
+            };
+            let format = Format { r: 0, g: 0, b: 0, a: 0, ty, permutation: Permutation::ASTC { x, y } };
+            return vk::Format::try_from(format).ok();
+        }
+        _ => return None,
+    };
+    Some(format)
+}
+
 #[derive(Clone, Debug)]
 pub struct ColorSpace {
     pub primaries: ColorSpacePrimaries,
@@ -702,4 +905,104 @@ mod tests {
     fn test_color_space_conversion() {
         let _mat = super::ColorSpacePrimaries::ACES_AP1.to_xyz();
     }
+
+    #[test]
+    fn test_aspect_mask() {
+        use super::{format_aspect_mask, Format};
+        use ash::vk;
+        let depth_stencil = vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL;
+        for (format, aspect) in [
+            (vk::Format::R8G8B8A8_UNORM, vk::ImageAspectFlags::COLOR),
+            (vk::Format::BC7_SRGB_BLOCK, vk::ImageAspectFlags::COLOR),
+            (vk::Format::D16_UNORM, vk::ImageAspectFlags::DEPTH),
+            (vk::Format::X8_D24_UNORM_PACK32, vk::ImageAspectFlags::DEPTH),
+            (vk::Format::D32_SFLOAT, vk::ImageAspectFlags::DEPTH),
+            (vk::Format::S8_UINT, vk::ImageAspectFlags::STENCIL),
+            (vk::Format::D16_UNORM_S8_UINT, depth_stencil),
+            (vk::Format::D24_UNORM_S8_UINT, depth_stencil),
+            (vk::Format::D32_SFLOAT_S8_UINT, depth_stencil),
+        ] {
+            assert_eq!(format_aspect_mask(format), aspect);
+            assert_eq!(Format::from(format).permutation.aspect_mask(), aspect);
+        }
+        // Formats without a `Format` description are treated as color formats.
+        let ycbcr = vk::Format::G8_B8R8_2PLANE_420_UNORM;
+        assert_eq!(format_aspect_mask(ycbcr), vk::ImageAspectFlags::COLOR);
+        assert!(Format::from_vk(ycbcr).is_none());
+    }
+
+    #[test]
+    fn test_texture_format_to_vk() {
+        use super::texture_format_to_vk;
+        use ash::vk;
+        use wgpu_types::{AstcBlock, AstcChannel, TextureFormat};
+        assert_eq!(
+            texture_format_to_vk(TextureFormat::Rgba8UnormSrgb),
+            Some(vk::Format::R8G8B8A8_SRGB)
+        );
+        assert_eq!(
+            texture_format_to_vk(TextureFormat::Bc7RgbaUnorm),
+            Some(vk::Format::BC7_UNORM_BLOCK)
+        );
+        assert_eq!(
+            texture_format_to_vk(TextureFormat::Astc {
+                block: AstcBlock::B6x6,
+                channel: AstcChannel::UnormSrgb
+            }),
+            Some(vk::Format::ASTC_6X6_SRGB_BLOCK)
+        );
+        assert_eq!(texture_format_to_vk(TextureFormat::Depth32Float), None);
+    }
+
+    #[test]
+    fn test_block_size() {
+        use super::Format;
+        use ash::vk;
+        let block = |f: vk::Format| {
+            let format = Format::from(f);
+            (format.block_extent(), format.block_size())
+        };
+        assert_eq!(block(vk::Format::R8G8B8A8_UNORM), ((1, 1), 4));
+        assert_eq!(block(vk::Format::E5B9G9R9_UFLOAT_PACK32), ((1, 1), 4));
+        assert_eq!(block(vk::Format::R16G16B16A16_SFLOAT), ((1, 1), 8));
+        assert_eq!(block(vk::Format::BC1_RGBA_SRGB_BLOCK), ((4, 4), 8));
+        assert_eq!(block(vk::Format::BC7_UNORM_BLOCK), ((4, 4), 16));
+        assert_eq!(block(vk::Format::ETC2_R8G8B8A1_UNORM_BLOCK), ((4, 4), 8));
+        assert_eq!(block(vk::Format::ETC2_R8G8B8A8_UNORM_BLOCK), ((4, 4), 16));
+        assert_eq!(block(vk::Format::ASTC_10X6_UNORM_BLOCK), ((10, 6), 16));
+    }
+
+    #[test]
+    fn test_format_compatibility() {
+        use super::Format;
+        use ash::vk;
+        let compatible =
+            |a: vk::Format, b: vk::Format| Format::from(a).is_compatible_with(&Format::from(b));
+        assert!(compatible(
+            vk::Format::R8G8B8A8_UNORM,
+            vk::Format::R8G8B8A8_SRGB
+        ));
+        assert!(compatible(vk::Format::R8G8B8A8_UNORM, vk::Format::R32_UINT));
+        assert!(compatible(
+            vk::Format::B10G11R11_UFLOAT_PACK32,
+            vk::Format::R32_SFLOAT
+        ));
+        assert!(compatible(
+            vk::Format::BC7_UNORM_BLOCK,
+            vk::Format::BC7_SRGB_BLOCK
+        ));
+        assert!(!compatible(
+            vk::Format::R8G8B8A8_UNORM,
+            vk::Format::R16G16B16A16_SFLOAT
+        ));
+        assert!(!compatible(
+            vk::Format::BC1_RGB_UNORM_BLOCK,
+            vk::Format::BC1_RGBA_UNORM_BLOCK
+        ));
+        assert!(!compatible(
+            vk::Format::BC7_UNORM_BLOCK,
+            vk::Format::R32G32B32A32_UINT
+        ));
+        assert!(!compatible(vk::Format::D32_SFLOAT, vk::Format::R32_SFLOAT));
+    }
 }